@@ -11,4 +11,6 @@ pub enum BigQueryExecutorError {
     JobIdNoneError,
     #[error("Schema is missing")]
     SchemaMissing,
+    #[error("Query job was cancelled")]
+    Cancelled,
 }
@@ -45,6 +45,7 @@ use rayon::prelude::IntoParallelIterator;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 
 pub struct BigQueryExecutor {
     client: Client,
@@ -61,7 +62,10 @@ impl BigQueryExecutor {
         }
     }
 
-    pub async fn execute_query(&self) -> Result<LazyFrame, BigQueryExecutorError> {
+    pub async fn execute_query(
+        &self,
+        cancellation_token: &CancellationToken,
+    ) -> Result<LazyFrame, BigQueryExecutorError> {
         let job = self.client.job();
         let result_set = job
             .query(
@@ -94,7 +98,13 @@ impl BigQueryExecutor {
             } else if rs.schema.is_some() {
                 break rs;
             }
-            sleep(Duration::from_millis(500)).await;
+            tokio::select! {
+                _ = sleep(Duration::from_millis(500)) => {}
+                _ = cancellation_token.cancelled() => {
+                    self.cancel_job(job, &job_id, location.as_deref()).await;
+                    return Err(BigQueryExecutorError::Cancelled);
+                }
+            }
         };
 
         if rs.schema.is_none() {
@@ -154,6 +164,9 @@ impl BigQueryExecutor {
                     break;
                 }
             }
+            if cancellation_token.is_cancelled() {
+                return Err(BigQueryExecutorError::Cancelled);
+            }
             let page_token = rs.page_token.clone();
             rs = self
                 .get_query_results(job, &job_id, location.clone(), page_token)
@@ -170,6 +183,13 @@ impl BigQueryExecutor {
         }
     }
 
+    async fn cancel_job(&self, job: &JobApi, job_id: &str, location: Option<&str>) {
+        // Best effort, the job is abandoned regardless of whether cancelling it succeeds
+        let _ = job
+            .cancel_job(self.project_id.as_str(), job_id, location)
+            .await;
+    }
+
     async fn get_query_results(
         &self,
         job: &JobApi,
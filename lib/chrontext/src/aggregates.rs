@@ -0,0 +1,34 @@
+use crate::constants::{FIRST, LAST, MEDIAN, PERCENTILE, STDDEV, VARIANCE};
+use oxrdf::NamedNode;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CustomAggregate {
+    Median,
+    // Between 0 and 1
+    Percentile(f64),
+    Stddev,
+    Variance,
+    // Ordered by the timestamps of the values
+    First,
+    Last,
+}
+
+impl CustomAggregate {
+    pub fn from_iri(iri: &NamedNode) -> Option<CustomAggregate> {
+        match iri.as_str() {
+            MEDIAN => Some(CustomAggregate::Median),
+            STDDEV => Some(CustomAggregate::Stddev),
+            VARIANCE => Some(CustomAggregate::Variance),
+            FIRST => Some(CustomAggregate::First),
+            LAST => Some(CustomAggregate::Last),
+            iri => {
+                let percentile = iri.strip_prefix(PERCENTILE)?.parse::<f64>().ok()?;
+                if percentile > 0.0 && percentile <= 100.0 {
+                    Some(CustomAggregate::Percentile(percentile / 100.0))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
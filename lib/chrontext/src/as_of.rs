@@ -0,0 +1,155 @@
+use crate::constants::AS_OF;
+use crate::errors::ChrontextError;
+use crate::filter_rewriting::{conjuncts, filter, rewrite_filters};
+use crate::splitter::QueryParseError;
+use oxrdf::vocab::xsd;
+use oxsdatatypes::DayTimeDuration;
+use spargebra::algebra::{Expression, Function};
+use spargebra::Query;
+use std::str::FromStr;
+use std::time::Duration;
+use virtualized_query::{AsOfStrategy, AsOfSynchronizer};
+
+// Removes ct:asOf(?left, ?right, strategy, tolerance) conditions from filters.
+// These are not evaluated as conditions, instead the series are matched when they are joined.
+pub fn extract_as_of_synchronizers(
+    query: Query,
+) -> Result<(Query, Vec<AsOfSynchronizer>), ChrontextError> {
+    let mut synchronizers = vec![];
+    let query = match query {
+        Query::Select {
+            dataset,
+            pattern,
+            base_iri,
+        } => Query::Select {
+            dataset,
+            pattern: rewrite_filters(pattern, &mut |expr, inner| {
+                let mut keep = vec![];
+                for e in conjuncts(expr) {
+                    if let Some(s) = as_of_synchronizer(&e)? {
+                        synchronizers.push(s);
+                    } else {
+                        keep.push(e);
+                    }
+                }
+                Ok(filter(keep, inner))
+            })?,
+            base_iri,
+        },
+        _ => return Err(QueryParseError::NotSelectQuery.into()),
+    };
+    Ok((query, synchronizers))
+}
+
+fn as_of_synchronizer(expr: &Expression) -> Result<Option<AsOfSynchronizer>, ChrontextError> {
+    let Expression::FunctionCall(Function::Custom(nn), args) = expr else {
+        return Ok(None);
+    };
+    if nn.as_str() != AS_OF {
+        return Ok(None);
+    }
+    let invalid = |reason: &str| ChrontextError::InvalidAsOfArguments(reason.to_string());
+    let (Some(Expression::Variable(left)), Some(Expression::Variable(right))) =
+        (args.first(), args.get(1))
+    else {
+        return Err(invalid(
+            "the first two arguments must be timestamp variables",
+        ));
+    };
+    if args.len() > 4 {
+        return Err(invalid("expected at most four arguments"));
+    }
+    let strategy = match args.get(2) {
+        None => AsOfStrategy::Backward,
+        Some(Expression::Literal(l)) => match l.value() {
+            "backward" => AsOfStrategy::Backward,
+            "forward" => AsOfStrategy::Forward,
+            "nearest" => AsOfStrategy::Nearest,
+            _ => {
+                return Err(invalid(
+                    "the strategy must be \"backward\", \"forward\" or \"nearest\"",
+                ))
+            }
+        },
+        Some(_) => return Err(invalid("the strategy must be a string literal")),
+    };
+    let tolerance = match args.get(3) {
+        None => None,
+        Some(Expression::Literal(l))
+            if l.datatype() == xsd::DURATION || l.datatype() == xsd::DAY_TIME_DURATION =>
+        {
+            Some(parse_day_time_duration(l.value()).ok_or_else(|| {
+                invalid("the tolerance must be a duration without years or months")
+            })?)
+        }
+        Some(_) => return Err(invalid("the tolerance must be an xsd:duration literal")),
+    };
+    Ok(Some(AsOfSynchronizer {
+        left: left.clone(),
+        right: right.clone(),
+        strategy,
+        tolerance,
+    }))
+}
+
+// Years and months have no fixed length, so only days and time are accepted, e.g. P1DT2H0.5S
+pub(crate) fn parse_day_time_duration(s: &str) -> Option<Duration> {
+    Duration::try_from(DayTimeDuration::from_str(s).ok()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxrdf::{Literal, NamedNode, Variable};
+
+    #[test]
+    fn test_parse_day_time_duration() {
+        assert_eq!(
+            parse_day_time_duration("PT2S"),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            parse_day_time_duration("P1DT1H0.5S"),
+            Some(Duration::from_secs_f64(86400.0 + 3600.0 + 0.5))
+        );
+        assert_eq!(parse_day_time_duration("P1M"), None);
+        assert_eq!(parse_day_time_duration("-PT1S"), None);
+        assert_eq!(parse_day_time_duration("2 seconds"), None);
+    }
+
+    fn as_of(args: Vec<Expression>) -> Result<Option<AsOfSynchronizer>, ChrontextError> {
+        let mut all_args = vec![
+            Expression::Variable(Variable::new_unchecked("t1")),
+            Expression::Variable(Variable::new_unchecked("t2")),
+        ];
+        all_args.extend(args);
+        as_of_synchronizer(&Expression::FunctionCall(
+            Function::Custom(NamedNode::new_unchecked(AS_OF)),
+            all_args,
+        ))
+    }
+
+    #[test]
+    fn test_as_of_strategy_and_tolerance() {
+        let s = as_of(vec![]).unwrap().unwrap();
+        assert_eq!(s.strategy, AsOfStrategy::Backward);
+        assert_eq!(s.tolerance, None);
+        let s = as_of(vec![
+            Expression::Literal(Literal::new_simple_literal("nearest")),
+            Expression::Literal(Literal::new_typed_literal("PT1.5S", xsd::DAY_TIME_DURATION)),
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(s.strategy, AsOfStrategy::Nearest);
+        assert_eq!(s.tolerance, Some(Duration::from_secs_f64(1.5)));
+        assert!(as_of(vec![Expression::Literal(Literal::new_simple_literal(
+            "closest"
+        ))])
+        .is_err());
+        assert!(as_of(vec![
+            Expression::Literal(Literal::new_simple_literal("forward")),
+            Expression::Literal(Literal::new_typed_literal("P1Y", xsd::DURATION)),
+        ])
+        .is_err());
+    }
+}
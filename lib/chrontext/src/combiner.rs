@@ -5,6 +5,7 @@ mod lazy_order;
 pub(crate) mod static_subqueries;
 pub(crate) mod virtualized_queries;
 
+use polars::prelude::PolarsError;
 use representation::query_context::Context;
 
 use crate::preparing::TimeseriesQueryPrepper;
@@ -17,21 +18,37 @@ use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::sync::Arc;
 use thiserror::Error;
+use tokio_util::sync::CancellationToken;
 use virtualization::errors::ChrontextError;
 use virtualization::{Virtualization, VirtualizedDatabase};
 use virtualized_query::pushdown_setting::PushdownSetting;
-use virtualized_query::{BasicVirtualizedQuery, VirtualizedResultValidationError};
+use virtualized_query::{
+    AsOfSynchronizer, BasicVirtualizedQuery, VirtualizedResultValidationError,
+};
 
 #[derive(Debug, Error)]
 pub enum CombinerError {
     VirtualizedDatabaseError(ChrontextError),
-    StaticQueryExecutionError(SparqlQueryError),
+    StaticQueryExecutionError {
+        source: SparqlQueryError,
+        query: String,
+        context: String,
+    },
     QueryProcessingError(#[from] QueryProcessingError),
     InconsistentDatatype(String, String, String),
     TimeseriesValidationError(VirtualizedResultValidationError),
     ResourceIsNotString(String, String),
     InconsistentResourceName(String, String, String),
     ResourceTemplateNotFound(String),
+    IdentifierIsBlankNode(String),
+    InvalidGapFill(String),
+    UnsupportedAggregation(String),
+    InvalidWindow(String),
+    GroupingError(PolarsError),
+    StaticQueryConstraintError(PolarsError),
+    StaticQueryResultsError(PolarsError),
+    UnsupportedStaticQuery(String),
+    Cancelled,
 }
 
 impl Display for CombinerError {
@@ -47,8 +64,16 @@ impl Display for CombinerError {
             CombinerError::VirtualizedDatabaseError(vqe) => {
                 write!(f, "Virtualized query error {}", vqe)
             }
-            CombinerError::StaticQueryExecutionError(sqee) => {
-                write!(f, "Static query execution error {}", sqee)
+            CombinerError::StaticQueryExecutionError {
+                source,
+                query,
+                context,
+            } => {
+                write!(
+                    f,
+                    "Static query execution error {} at context `{}` for query:\n{}",
+                    source, context, query
+                )
             }
             CombinerError::TimeseriesValidationError(v) => {
                 write!(f, "Virtualized results validation error {}", v)
@@ -71,6 +96,40 @@ impl Display for CombinerError {
             CombinerError::ResourceTemplateNotFound(resource) => {
                 write!(f, "No template found for resource: {}", resource)
             }
+            CombinerError::IdentifierIsBlankNode(context) => {
+                write!(
+                    f,
+                    "Identifier variable for context {context} is bound to a blank node"
+                )
+            }
+            CombinerError::InvalidGapFill(reason) => {
+                write!(f, "Invalid gap filling aggregation, {reason}")
+            }
+            CombinerError::InvalidWindow(reason) => {
+                write!(f, "Invalid window function, {reason}")
+            }
+            CombinerError::UnsupportedAggregation(aggregation) => {
+                write!(f, "Unsupported aggregation {aggregation}")
+            }
+            CombinerError::GroupingError(e) => {
+                write!(f, "Error computing groups of static solutions {}", e)
+            }
+            CombinerError::StaticQueryConstraintError(e) => {
+                write!(f, "Error constraining static query by solutions {}", e)
+            }
+            CombinerError::StaticQueryResultsError(e) => {
+                write!(f, "Error collecting static query results {}", e)
+            }
+            CombinerError::UnsupportedStaticQuery(query) => {
+                write!(
+                    f,
+                    "Static query must be a SELECT query with a projection, got:\n{}",
+                    query
+                )
+            }
+            CombinerError::Cancelled => {
+                write!(f, "Query was cancelled")
+            }
         }
     }
 }
@@ -81,6 +140,7 @@ pub struct Combiner {
     pub virtualized_database: Arc<VirtualizedDatabase>,
     prepper: TimeseriesQueryPrepper,
     pub virtualized_contexts: Vec<Context>,
+    cancellation_token: CancellationToken,
 }
 
 impl Combiner {
@@ -90,12 +150,15 @@ impl Combiner {
         virtualized_database: Arc<VirtualizedDatabase>,
         basic_virtualized_queries: Vec<BasicVirtualizedQuery>,
         rewritten_filters: HashMap<Context, Expression>,
+        as_of_synchronizers: Vec<AsOfSynchronizer>,
         virtualization: Arc<Virtualization>,
+        cancellation_token: CancellationToken,
     ) -> Combiner {
         let prepper = TimeseriesQueryPrepper::new(
             pushdown_settings,
             basic_virtualized_queries,
             rewritten_filters,
+            as_of_synchronizers,
             virtualization,
         );
         Combiner {
@@ -104,6 +167,7 @@ impl Combiner {
             virtualized_database,
             prepper,
             virtualized_contexts: vec![],
+            cancellation_token,
         }
     }
 
@@ -122,8 +186,9 @@ impl Combiner {
             let solution_mappings;
             let virtualized_queries;
             if let Some(static_query) = static_query_map.remove(&context) {
-                let mut new_solution_mappings =
-                    self.execute_static_query(&static_query, None).await?;
+                let mut new_solution_mappings = self
+                    .execute_static_query(&static_query, None, &context)
+                    .await?;
                 let new_virtualized_queries =
                     self.prepper.prepare(query, &mut new_solution_mappings)?;
                 // Combination assumes there is something to combine!
@@ -1,15 +1,19 @@
 use super::Combiner;
+use crate::aggregates::CustomAggregate;
 use crate::combiner::CombinerError;
 use crate::constants::NEST;
+use crate::gap_fill::gap_fill_method;
+use oxrdf::vocab::xsd;
 use oxrdf::Variable;
-use polars::prelude::col;
+use polars::prelude::{col, lit, DataType, Expr, QuantileMethod, SortMultipleOptions};
 use query_processing::aggregates::{
     avg, count_with_expression, count_without_expression, group_concat, max, min, sample, sum,
     AggregateReturn,
 };
 use representation::query_context::{Context, PathEntry};
 use representation::solution_mapping::SolutionMappings;
-use spargebra::algebra::{AggregateExpression, AggregateFunction};
+use representation::RDFNodeType;
+use spargebra::algebra::{AggregateExpression, AggregateFunction, Expression};
 
 impl Combiner {
     pub async fn sparql_aggregate_expression_as_lazy_column_and_expression(
@@ -82,15 +86,29 @@ impl Combiner {
                     }
                     AggregateFunction::Custom(name) => {
                         let iri = name.as_str();
-                        if iri == NEST {
+                        if gap_fill_method(aggregate_expression).is_some() {
+                            // Gaps are filled after grouping
+                            (out_expr, out_rdf_node_type) = avg(
+                                &output_solution_mappings,
+                                column_context.as_ref().unwrap(),
+                                *distinct,
+                            );
+                        } else if iri == NEST {
                             out_expr = col(column_context.as_ref().unwrap().as_str());
                             out_rdf_node_type = output_solution_mappings
                                 .rdf_node_types
                                 .get(column_context.as_ref().unwrap().as_str())
                                 .unwrap()
                                 .clone();
+                        } else if let Some(custom) = CustomAggregate::from_iri(name) {
+                            (out_expr, out_rdf_node_type) = self.custom_aggregate(
+                                custom,
+                                expr,
+                                &output_solution_mappings,
+                                column_context.as_ref().unwrap(),
+                            )?;
                         } else {
-                            panic!("Custom aggregation not supported")
+                            return Err(CombinerError::UnsupportedAggregation(iri.to_string()));
                         }
                     }
                 }
@@ -104,4 +122,52 @@ impl Combiner {
             rdf_node_type: out_rdf_node_type,
         })
     }
+
+    fn custom_aggregate(
+        &self,
+        custom: CustomAggregate,
+        expr: &Expression,
+        solution_mappings: &SolutionMappings,
+        column_context: &Context,
+    ) -> Result<(Expr, RDFNodeType), CombinerError> {
+        let c = col(column_context.as_str());
+        let double = || RDFNodeType::Literal(xsd::DOUBLE.into_owned());
+        Ok(match custom {
+            CustomAggregate::Median => (c.cast(DataType::Float64).median(), double()),
+            CustomAggregate::Percentile(p) => (
+                c.cast(DataType::Float64)
+                    .quantile(lit(p), QuantileMethod::Linear),
+                double(),
+            ),
+            CustomAggregate::Stddev => (c.cast(DataType::Float64).std(1), double()),
+            CustomAggregate::Variance => (c.cast(DataType::Float64).var(1), double()),
+            CustomAggregate::First | CustomAggregate::Last => {
+                let timestamp = self
+                    .prepper
+                    .basic_virtualized_queries
+                    .iter()
+                    .filter_map(|b| b.timestamp_variable_of(expr))
+                    .find(|t| solution_mappings.rdf_node_types.contains_key(t.as_str()))
+                    .ok_or_else(|| {
+                        CombinerError::UnsupportedAggregation(
+                            "first or last value of an expression without timestamps".to_string(),
+                        )
+                    })?;
+                let sorted = c.sort_by(
+                    [col(timestamp.as_str())],
+                    SortMultipleOptions::default().with_nulls_last(true),
+                );
+                let rdf_node_type = solution_mappings
+                    .rdf_node_types
+                    .get(column_context.as_str())
+                    .unwrap()
+                    .clone();
+                if custom == CustomAggregate::First {
+                    (sorted.first(), rdf_node_type)
+                } else {
+                    (sorted.last(), rdf_node_type)
+                }
+            }
+        })
+    }
 }
@@ -1,6 +1,7 @@
 mod distinct;
 mod extend;
 mod filter;
+mod graph;
 mod group;
 mod join;
 mod left_join;
@@ -13,6 +14,7 @@ mod values;
 
 use super::Combiner;
 use crate::combiner::CombinerError;
+use crate::partial_aggregation::combine_partial_aggregations;
 use crate::preparing::graph_patterns::GPPrepReturn;
 use async_recursion::async_recursion;
 use log::debug;
@@ -40,6 +42,7 @@ impl Combiner {
         //We have to eagerly evaluate static queries contained in the group by pattern since otherwise we are unable to push down the group by into the time series database.
         let mut found_group_by_pushdown = false;
         let mut static_query_opt = static_query_map.remove(context);
+        let mut static_query_context = context.clone();
         if static_query_opt.is_none() {
             debug!("No static query found");
             let groupby_inner_context = context.extension_with(PathEntry::GroupInner);
@@ -47,6 +50,7 @@ impl Combiner {
             if static_query_opt.is_some() {
                 debug!("Found static subquery generated by group by");
                 found_group_by_pushdown = true;
+                static_query_context = groupby_inner_context;
             }
         } else {
             debug!("Found static query");
@@ -55,7 +59,7 @@ impl Combiner {
         if let Some(query) = static_query_opt {
             debug!("Executing static query");
             let mut new_solution_mappings = self
-                .execute_static_query(&query, updated_solution_mappings)
+                .execute_static_query(&query, updated_solution_mappings, &static_query_context)
                 .await?;
             debug!("Finished executing static query");
             debug!("Start preparing time series queries");
@@ -99,7 +103,12 @@ impl Combiner {
                         .is_empty()))
         {
             debug!("Will not process graph pattern further due to found static group by");
-            return Ok(updated_solution_mappings.unwrap());
+            let mut solution_mappings = updated_solution_mappings.unwrap();
+            if let Some(partial_aggregations) = self.prepper.partial_aggregations.remove(context) {
+                solution_mappings =
+                    combine_partial_aggregations(solution_mappings, partial_aggregations);
+            }
+            return Ok(solution_mappings);
         }
 
         if static_query_map.is_empty()
@@ -167,7 +176,16 @@ impl Combiner {
                 )
                 .await
             }
-            GraphPattern::Graph { name: _, inner: _ } => Ok(updated_solution_mappings.unwrap()),
+            GraphPattern::Graph { name: _, inner } => {
+                self.lazy_graph(
+                    inner,
+                    updated_solution_mappings,
+                    static_query_map,
+                    new_prepared_virtualized_queries,
+                    context,
+                )
+                .await
+            }
             GraphPattern::Extend {
                 inner,
                 variable,
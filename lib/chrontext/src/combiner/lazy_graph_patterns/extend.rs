@@ -2,6 +2,7 @@ use super::Combiner;
 use crate::combiner::static_subqueries::split_static_queries;
 use crate::combiner::virtualized_queries::split_virtualized_queries;
 use crate::combiner::CombinerError;
+use crate::window::{window, window_function, window_rdf_node_type};
 use async_recursion::async_recursion;
 use log::debug;
 use oxrdf::Variable;
@@ -14,7 +15,7 @@ use representation::{BaseRDFNodeType, RDFNodeType};
 use spargebra::algebra::{Expression, GraphPattern};
 use spargebra::Query;
 use std::collections::HashMap;
-use virtualized_query::VirtualizedQuery;
+use virtualized_query::{VirtualizedQuery, Window, WindowFunction};
 
 impl Combiner {
     #[async_recursion]
@@ -57,7 +58,17 @@ impl Combiner {
 
         let has_all =
             solution_mappings_has_all_expression_variables(&output_solution_mappings, expression);
+        // The expression was computed by the virtualized database
+        if output_solution_mappings
+            .rdf_node_types
+            .contains_key(variable.as_str())
+        {
+            return Ok(output_solution_mappings);
+        }
         if has_all {
+            if let Some((function, value)) = window_function(expression)? {
+                return self.lazy_window(output_solution_mappings, variable, function, &value);
+            }
             output_solution_mappings = self
                 .lazy_expression(
                     expression,
@@ -84,4 +95,49 @@ impl Combiner {
             Ok(output_solution_mappings)
         }
     }
+
+    fn lazy_window(
+        &self,
+        mut solution_mappings: SolutionMappings,
+        variable: &Variable,
+        function: WindowFunction,
+        value: &Variable,
+    ) -> Result<SolutionMappings, CombinerError> {
+        // The external identifiers are dropped when the results are attached, so the series are identified by the variable they are queried from
+        let (partition_by, timestamp) = self
+            .prepper
+            .basic_virtualized_queries
+            .iter()
+            .filter_map(|b| {
+                b.series_of(value)
+                    .map(|(_, timestamp)| (&b.query_source_variable, timestamp))
+            })
+            .find(|(p, t)| {
+                solution_mappings.rdf_node_types.contains_key(p.as_str())
+                    && solution_mappings.rdf_node_types.contains_key(t.as_str())
+            })
+            .ok_or_else(|| {
+                CombinerError::InvalidWindow(format!(
+                    "{value} must be a value of a time series with timestamps"
+                ))
+            })?;
+        let w = Window {
+            function,
+            value: value.clone(),
+            timestamp: timestamp.clone(),
+            partition_by: partition_by.clone(),
+        };
+        solution_mappings.mappings = window(solution_mappings.mappings, variable, &w);
+        let rdf_node_type = window_rdf_node_type(
+            &function,
+            solution_mappings
+                .rdf_node_types
+                .get(value.as_str())
+                .unwrap(),
+        );
+        solution_mappings
+            .rdf_node_types
+            .insert(variable.as_str().to_string(), rdf_node_type);
+        Ok(solution_mappings)
+    }
 }
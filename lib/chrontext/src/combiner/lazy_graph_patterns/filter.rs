@@ -41,6 +41,12 @@ impl Combiner {
             true
         });
 
+        // The filter may be pushed down with the grouping, and is applied to the grouped results either way
+        if let Some(group_context) = having_group_context(inner, &inner_context) {
+            self.prepper
+                .having_filters
+                .insert(group_context, expression.clone());
+        }
         let mut output_solution_mappings = self
             .lazy_graph_pattern(
                 inner,
@@ -68,3 +74,14 @@ impl Combiner {
         }
     }
 }
+
+// HAVING is a filter over the group, with the aggregates in SELECT bound in between
+fn having_group_context(inner: &GraphPattern, context: &Context) -> Option<Context> {
+    match inner {
+        GraphPattern::Group { .. } => Some(context.clone()),
+        GraphPattern::Extend { inner, .. } => {
+            having_group_context(inner, &context.extension_with(PathEntry::ExtendInner))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,39 @@
+use super::Combiner;
+use crate::combiner::CombinerError;
+use async_recursion::async_recursion;
+use log::debug;
+use representation::query_context::{Context, PathEntry};
+use representation::solution_mapping::SolutionMappings;
+use spargebra::algebra::GraphPattern;
+use spargebra::Query;
+use std::collections::HashMap;
+use virtualized_query::VirtualizedQuery;
+
+impl Combiner {
+    #[async_recursion]
+    pub(crate) async fn lazy_graph(
+        &mut self,
+        inner: &GraphPattern,
+        solution_mappings: Option<SolutionMappings>,
+        static_query_map: HashMap<Context, Query>,
+        prepared_virtualized_queries: Option<HashMap<Context, Vec<VirtualizedQuery>>>,
+        context: &Context,
+    ) -> Result<SolutionMappings, CombinerError> {
+        debug!("Processing graph graph pattern");
+        let has_virtualized_queries = prepared_virtualized_queries
+            .as_ref()
+            .is_some_and(|vqs| !vqs.is_empty());
+        if static_query_map.is_empty() && !has_virtualized_queries {
+            //The static query already scoped the inner pattern to the graph
+            return Ok(solution_mappings.unwrap());
+        }
+        self.lazy_graph_pattern(
+            inner,
+            solution_mappings,
+            static_query_map,
+            prepared_virtualized_queries,
+            &context.extension_with(PathEntry::GraphInner),
+        )
+        .await
+    }
+}
@@ -2,6 +2,7 @@ use super::Combiner;
 use crate::combiner::static_subqueries::split_static_queries;
 use crate::combiner::virtualized_queries::split_virtualized_queries;
 use crate::combiner::CombinerError;
+use crate::gap_fill::{find_gap_fill, gap_fill};
 use log::debug;
 use oxrdf::Variable;
 use query_processing::aggregates::AggregateReturn;
@@ -71,12 +72,24 @@ impl Combiner {
                 aggregate_expressions.push(expr);
             }
         }
-        Ok(group_by(
+        let mut solution_mappings = group_by(
             output_solution_mappings,
             aggregate_expressions,
             by,
             dummy_varname,
             new_rdf_node_types,
-        )?)
+        )?;
+        if let Some(gf) = find_gap_fill(inner, variables, aggregates)? {
+            let partition_by: Vec<_> = variables
+                .iter()
+                .filter(|v| {
+                    *v != &gf.bucket_variable
+                        && solution_mappings.rdf_node_types.contains_key(v.as_str())
+                })
+                .map(|v| v.as_str().to_string())
+                .collect();
+            solution_mappings.mappings = gap_fill(solution_mappings.mappings, &partition_by, &gf);
+        }
+        Ok(solution_mappings)
     }
 }
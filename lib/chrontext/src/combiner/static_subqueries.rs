@@ -1,47 +1,76 @@
 use super::Combiner;
-use crate::combiner::virtualized_queries::complete_basic_virtualized_queries;
+use crate::combiner::virtualized_queries::BasicVirtualizedQueryCompleter;
 use crate::combiner::CombinerError;
-use crate::sparql_result_to_polars::create_static_query_dataframe;
+use crate::sparql_result_to_polars::StaticQueryDataFrameBuilder;
 use log::debug;
 use oxrdf::{Term, Variable};
-use polars::prelude::{col, Expr, IntoLazy, JoinType, UniqueKeepStrategy};
+use polars::prelude::{col, DataFrame, Expr, IntoLazy, JoinType, UniqueKeepStrategy};
 use query_processing::graph_patterns::join;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use representation::polars_to_rdf::{df_as_result, QuerySolutions};
 use representation::query_context::Context;
 use representation::solution_mapping::SolutionMappings;
+use representation::{BaseRDFNodeType, RDFNodeType};
+use sparesults::QuerySolution;
 use spargebra::algebra::GraphPattern;
 use spargebra::term::GroundTerm;
 use spargebra::Query;
+use sparql_database::SolutionSink;
 use std::collections::HashMap;
 
+struct StaticQuerySink<'a> {
+    builder: StaticQueryDataFrameBuilder,
+    completer: BasicVirtualizedQueryCompleter<'a>,
+}
+
+impl SolutionSink for StaticQuerySink<'_> {
+    fn push(&mut self, solution: QuerySolution) {
+        self.completer.push(&solution);
+        self.builder.push(&solution);
+    }
+}
+
 impl Combiner {
     pub async fn execute_static_query(
         &mut self,
         query: &Query,
         solution_mappings: Option<SolutionMappings>,
+        context: &Context,
     ) -> Result<SolutionMappings, CombinerError> {
-        let use_query;
+        let use_queries;
         let use_solution_mappings;
         if let Some(mappings) = solution_mappings {
-            let (new_query, new_solution_mappings) = constrain_query(query, mappings);
-            use_query = new_query;
+            let (new_queries, new_solution_mappings) = constrain_query(query, mappings)?;
+            use_queries = new_queries;
             use_solution_mappings = Some(new_solution_mappings)
         } else {
-            use_query = query.clone();
+            use_queries = vec![query.clone()];
             use_solution_mappings = solution_mappings;
         }
-        debug!("Static query: {}", use_query.to_string());
-        let solutions = self
-            .sparql_database
-            .execute(&use_query)
-            .await
-            .map_err(CombinerError::StaticQueryExecutionError)?;
-        complete_basic_virtualized_queries(
-            &solutions,
-            &mut self.prepper.basic_virtualized_queries,
-        )?;
-        let (df, datatypes) = create_static_query_dataframe(&use_query, solutions);
+        // The results of all batches are collected into the same dataframe
+        let mut sink = StaticQuerySink {
+            builder: StaticQueryDataFrameBuilder::new(query)?,
+            completer: BasicVirtualizedQueryCompleter::new(
+                &mut self.prepper.basic_virtualized_queries,
+            ),
+        };
+        for use_query in &use_queries {
+            debug!("Static query: {}", use_query.to_string());
+            tokio::select! {
+                result = self.sparql_database.execute(use_query, &mut sink) => {
+                    result.map_err(|source| CombinerError::StaticQueryExecutionError {
+                        source,
+                        query: use_query.to_string(),
+                        context: context.as_str().to_string(),
+                    })?
+                }
+                _ = self.cancellation_token.cancelled() => return Err(CombinerError::Cancelled),
+            };
+        }
+        let StaticQuerySink { builder, completer } = sink;
+        completer.finish()?;
+        let (df, datatypes) = builder.finish()?;
+        let df = distinct_across_batches(query, use_queries.len(), df)?;
         let df_height = df.height();
         debug!("Static query results:\n {}", df);
         let mut out_solution_mappings = SolutionMappings::new(df.lazy(), datatypes, df_height);
@@ -83,21 +112,33 @@ pub(crate) fn split_static_queries_opt(
         .map(|static_queries| split_static_queries(static_queries, context))
 }
 
+// Limits the size of the VALUES clause of each static query, the constraint is split into several queries
+const MAX_VALUES_CELLS_PER_STATIC_QUERY: usize = 5_000;
+// Beyond this number of rows, the static query is not constrained and is instead joined client side
+const MAX_CONSTRAINT_ROWS: usize = 100_000;
+
 fn constrain_query(
     query: &Query,
     mut solution_mappings: SolutionMappings,
-) -> (Query, SolutionMappings) {
-    solution_mappings.mappings = solution_mappings.mappings.collect().unwrap().lazy();
+) -> Result<(Vec<Query>, SolutionMappings), CombinerError> {
+    solution_mappings.mappings = solution_mappings
+        .mappings
+        .collect()
+        .map_err(CombinerError::StaticQueryConstraintError)?
+        .lazy();
     let projected_variables = get_variable_set(query);
 
     let mut constrain_variables = vec![];
     for v in projected_variables {
-        if solution_mappings.rdf_node_types.contains_key(v.as_str()) {
-            constrain_variables.push(v.clone());
+        if let Some(t) = solution_mappings.rdf_node_types.get(v.as_str()) {
+            // Blank node labels are local to each query result, so they can not be used as constraints
+            if !may_be_blank_node(t) {
+                constrain_variables.push(v.clone());
+            }
         }
     }
     if constrain_variables.is_empty() {
-        return (query.clone(), solution_mappings);
+        return Ok((vec![query.clone()], solution_mappings));
     }
 
     let constrain_columns: Vec<Expr> = constrain_variables
@@ -110,36 +151,82 @@ fn constrain_query(
         .select(constrain_columns)
         .unique(None, UniqueKeepStrategy::First)
         .collect()
-        .unwrap();
+        .map_err(CombinerError::StaticQueryConstraintError)?;
+    if variable_columns.height() > MAX_CONSTRAINT_ROWS {
+        debug!(
+            "Constraint has {} rows, joining static query results client side",
+            variable_columns.height()
+        );
+        return Ok((vec![query.clone()], solution_mappings));
+    }
 
     let QuerySolutions {
         variables,
         solutions,
     } = df_as_result(variable_columns, &solution_mappings.rdf_node_types);
-    let bindings = solutions
+    let bindings: Option<Vec<Vec<Option<GroundTerm>>>> = solutions
         .into_par_iter()
         .map(|x| {
             x.into_iter()
-                .map(|y: Option<Term>| {
-                    y.map(|y| match y {
-                        Term::NamedNode(nn) => GroundTerm::NamedNode(nn),
-                        Term::BlankNode(_) => {
-                            panic!()
-                        }
-                        Term::Literal(l) => GroundTerm::Literal(l),
-                    })
+                .map(|y: Option<Term>| match y {
+                    None => Some(None),
+                    Some(Term::NamedNode(nn)) => Some(Some(GroundTerm::NamedNode(nn))),
+                    Some(Term::BlankNode(_)) => None,
+                    Some(Term::Literal(l)) => Some(Some(GroundTerm::Literal(l))),
                 })
                 .collect()
         })
         .collect();
-    let values_pattern = GraphPattern::Values {
-        variables,
-        bindings,
+    let Some(bindings) = bindings else {
+        return Ok((vec![query.clone()], solution_mappings));
     };
-    (
-        constrain_query_with_values(query, values_pattern),
-        solution_mappings,
-    )
+    let rows_per_query = (MAX_VALUES_CELLS_PER_STATIC_QUERY / variables.len()).max(1);
+    let queries = bindings
+        .chunks(rows_per_query)
+        .map(|chunk| {
+            let values_pattern = GraphPattern::Values {
+                variables: variables.clone(),
+                bindings: chunk.to_vec(),
+            };
+            constrain_query_with_values(query, values_pattern)
+        })
+        .collect();
+    Ok((queries, solution_mappings))
+}
+
+// Each batch of a DISTINCT query is only deduplicated by the SPARQL database,
+// solutions matching constraint rows in several batches are deduplicated here.
+fn distinct_across_batches(
+    query: &Query,
+    batches: usize,
+    df: DataFrame,
+) -> Result<DataFrame, CombinerError> {
+    if batches > 1
+        && matches!(
+            query,
+            Query::Select {
+                pattern: GraphPattern::Distinct { .. },
+                ..
+            }
+        )
+    {
+        df.lazy()
+            .unique_stable(None, UniqueKeepStrategy::First)
+            .collect()
+            .map_err(CombinerError::StaticQueryConstraintError)
+    } else {
+        Ok(df)
+    }
+}
+
+fn may_be_blank_node(t: &RDFNodeType) -> bool {
+    match t {
+        RDFNodeType::BlankNode => true,
+        RDFNodeType::MultiType(types) => types
+            .iter()
+            .any(|x| matches!(x, BaseRDFNodeType::BlankNode)),
+        _ => false,
+    }
 }
 
 fn constrain_query_with_values(query: &Query, values_pattern: GraphPattern) -> Query {
@@ -192,3 +279,181 @@ fn get_variable_set(query: &Query) -> Vec<&Variable> {
         panic!("Non select query not supported")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxrdf::{BlankNode, NamedNode};
+
+    fn static_query() -> Query {
+        Query::parse(
+            "SELECT ?w ?s WHERE { ?w <http://example.org/types#hasSensor> ?s }",
+            None,
+        )
+        .unwrap()
+    }
+
+    fn solution_mappings(
+        variables: &[&str],
+        rows: impl Iterator<Item = Vec<Term>>,
+    ) -> SolutionMappings {
+        let mut builder = StaticQueryDataFrameBuilder::with_variables(
+            variables
+                .iter()
+                .map(|v| Variable::new_unchecked(*v))
+                .collect(),
+        );
+        for row in rows {
+            builder.push_terms(&row.iter().map(Some).collect::<Vec<_>>());
+        }
+        let (df, rdf_node_types) = builder.finish().unwrap();
+        let height = df.height();
+        SolutionMappings::new(df.lazy(), rdf_node_types, height)
+    }
+
+    fn widget(i: usize) -> Term {
+        NamedNode::new_unchecked(format!("http://example.org/case#myWidget{i}")).into()
+    }
+
+    fn values_bindings(query: &Query) -> &Vec<Vec<Option<GroundTerm>>> {
+        if let Query::Select {
+            pattern: GraphPattern::Project { inner, .. },
+            ..
+        } = query
+        {
+            if let GraphPattern::Join { left, .. } = inner.as_ref() {
+                if let GraphPattern::Values { bindings, .. } = left.as_ref() {
+                    return bindings;
+                }
+            }
+        }
+        panic!("Query is not constrained by values: {query}")
+    }
+
+    #[test]
+    fn test_constraint_is_split_into_batches() {
+        let rows = 2 * MAX_VALUES_CELLS_PER_STATIC_QUERY + 1;
+        let mappings = solution_mappings(&["w"], (0..rows).map(|i| vec![widget(i)]));
+        let (queries, _) = constrain_query(&static_query(), mappings).unwrap();
+        let batch_sizes: Vec<_> = queries.iter().map(|q| values_bindings(q).len()).collect();
+        assert_eq!(
+            batch_sizes,
+            vec![
+                MAX_VALUES_CELLS_PER_STATIC_QUERY,
+                MAX_VALUES_CELLS_PER_STATIC_QUERY,
+                1
+            ]
+        );
+    }
+
+    #[test]
+    fn test_constraint_batches_count_cells() {
+        let rows = MAX_VALUES_CELLS_PER_STATIC_QUERY;
+        let mappings = solution_mappings(
+            &["w", "s"],
+            (0..rows).map(|i| {
+                vec![
+                    widget(i),
+                    NamedNode::new_unchecked(format!("http://example.org/case#mySensor{i}")).into(),
+                ]
+            }),
+        );
+        let (queries, _) = constrain_query(&static_query(), mappings).unwrap();
+        assert_eq!(queries.len(), 2);
+        for q in &queries {
+            assert_eq!(
+                values_bindings(q).len(),
+                MAX_VALUES_CELLS_PER_STATIC_QUERY / 2
+            );
+        }
+    }
+
+    #[test]
+    fn test_large_constraint_is_joined_client_side() {
+        let mappings = solution_mappings(
+            &["w"],
+            (0..MAX_CONSTRAINT_ROWS + 1).map(|i| vec![widget(i)]),
+        );
+        let (queries, mappings) = constrain_query(&static_query(), mappings).unwrap();
+        assert_eq!(queries, vec![static_query()]);
+        assert_eq!(
+            mappings.mappings.collect().unwrap().height(),
+            MAX_CONSTRAINT_ROWS + 1
+        );
+    }
+
+    #[test]
+    fn test_blank_nodes_are_not_constraints() {
+        let mappings = solution_mappings(
+            &["w"],
+            (0..10).map(|i| vec![BlankNode::new_unchecked(format!("b{i}")).into()]),
+        );
+        let (queries, _) = constrain_query(&static_query(), mappings).unwrap();
+        assert_eq!(queries, vec![static_query()]);
+
+        // A variable that may be a blank node is skipped, other variables still constrain the query
+        let mappings = solution_mappings(
+            &["w", "s"],
+            (0..10).map(|i| {
+                let s = if i % 2 == 0 {
+                    BlankNode::new_unchecked(format!("b{i}")).into()
+                } else {
+                    NamedNode::new_unchecked(format!("http://example.org/case#mySensor{i}")).into()
+                };
+                vec![widget(i), s]
+            }),
+        );
+        assert!(may_be_blank_node(mappings.rdf_node_types.get("s").unwrap()));
+        let (queries, _) = constrain_query(&static_query(), mappings).unwrap();
+        assert_eq!(queries.len(), 1);
+        let Query::Select {
+            pattern: GraphPattern::Project { inner, .. },
+            ..
+        } = &queries[0]
+        else {
+            panic!("Expected a projection");
+        };
+        let GraphPattern::Join { left, .. } = inner.as_ref() else {
+            panic!("Expected the query to be constrained");
+        };
+        let GraphPattern::Values {
+            variables,
+            bindings,
+        } = left.as_ref()
+        else {
+            panic!("Expected values");
+        };
+        assert_eq!(variables, &vec![Variable::new_unchecked("w")]);
+        assert_eq!(bindings.len(), 10);
+    }
+
+    #[test]
+    fn test_distinct_query_is_deduplicated_across_batches() {
+        let mut builder = StaticQueryDataFrameBuilder::with_variables(vec![
+            Variable::new_unchecked("w"),
+            Variable::new_unchecked("s"),
+        ]);
+        let row = [widget(1), widget(2)];
+        // The same solution is returned by two batches
+        builder.push_terms(&row.iter().map(Some).collect::<Vec<_>>());
+        builder.push_terms(&row.iter().map(Some).collect::<Vec<_>>());
+        let (df, _) = builder.finish().unwrap();
+        let distinct_query = Query::parse(
+            "SELECT DISTINCT ?w ?s WHERE { ?w <http://example.org/types#hasSensor> ?s }",
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            distinct_across_batches(&distinct_query, 2, df.clone())
+                .unwrap()
+                .height(),
+            1
+        );
+        assert_eq!(
+            distinct_across_batches(&static_query(), 2, df)
+                .unwrap()
+                .height(),
+            2
+        );
+    }
+}
@@ -1,12 +1,13 @@
 use super::Combiner;
 use crate::combiner::CombinerError;
+use crate::gap_fill::{gap_fill, gap_fill_pushed_down};
 use crate::preparing::grouping_col_type;
 use log::debug;
 use oxrdf::vocab::xsd;
-use oxrdf::Term;
+use oxrdf::{Term, Variable};
 use polars::prelude::{
-    col, CategoricalOrdering, Column, DataFrame, DataType, Expr, IntoLazy, JoinArgs, JoinType,
-    SortMultipleOptions,
+    col, AsOfOptions, AsofStrategy, CategoricalOrdering, Column, DataFrame, DataType, Expr,
+    IntoLazy, JoinArgs, JoinType, LazyFrame, PlSmallStr, SortMultipleOptions,
 };
 use representation::polars_to_rdf::polars_type_to_literal_type;
 use representation::query_context::Context;
@@ -14,8 +15,12 @@ use representation::solution_mapping::{EagerSolutionMappings, SolutionMappings};
 use representation::{BaseRDFNodeType, RDFNodeType};
 use sparesults::QuerySolution;
 use std::collections::{HashMap, HashSet};
+use virtualization::errors::ChrontextError as VirtualizedDatabaseError;
 use virtualized_query::pushdown_setting::PushdownSetting;
-use virtualized_query::{BasicVirtualizedQuery, VirtualizedQuery};
+use virtualized_query::{
+    AsOfStrategy, AsOfSynchronizer, BasicVirtualizedQuery, GroupedVirtualizedQuery,
+    VirtualizedQuery,
+};
 
 impl Combiner {
     pub fn attach_expected_empty_results(
@@ -80,9 +85,12 @@ impl Combiner {
             mut rdf_node_types,
         } = self
             .virtualized_database
-            .query(&vq)
+            .query(&vq, &self.cancellation_token)
             .await
-            .map_err(CombinerError::VirtualizedDatabaseError)?;
+            .map_err(|e| match e {
+                VirtualizedDatabaseError::Cancelled => CombinerError::Cancelled,
+                e => CombinerError::VirtualizedDatabaseError(e),
+            })?;
 
         // We allow empty (no columns & rows) result for compatibility with e.g. Azure Kusto.
         if mappings.height() == 0 && mappings.get_columns().is_empty() {
@@ -117,7 +125,38 @@ impl Combiner {
             };
         }
 
+        if let VirtualizedQuery::Grouped(GroupedVirtualizedQuery {
+            by,
+            gap_fill: Some(gf),
+            ..
+        }) = &vq
+        {
+            if !gap_fill_pushed_down(&self.prepper.pushdown_settings, gf) {
+                let partition_by: Vec<_> = by
+                    .iter()
+                    .filter(|v| *v != &gf.bucket_variable)
+                    .map(|v| v.as_str().to_string())
+                    .collect();
+                mappings = gap_fill(mappings, &partition_by, gf);
+            }
+        }
+
         for id_col in id_cols {
+            //Identifiers that are not strings or IRIs are joined on their native type
+            match solution_mappings.rdf_node_types.get(&id_col) {
+                Some(RDFNodeType::Literal(l)) if l.as_ref() != xsd::STRING => {
+                    let t = BaseRDFNodeType::Literal(l.clone());
+                    mappings = mappings.with_column(col(&id_col).cast(t.polars_data_type()));
+                    rdf_node_types.insert(id_col.clone(), t.as_rdf_node_type());
+                    continue;
+                }
+                //IRI identifiers keep the type from the static side
+                Some(RDFNodeType::IRI) => {
+                    mappings = mappings.with_column(col(&id_col).cast(DataType::String));
+                    rdf_node_types.insert(id_col.clone(), RDFNodeType::IRI);
+                }
+                _ => {}
+            }
             //When there are no results we need to cast to the appropriate type
             if let Some(&RDFNodeType::None) = rdf_node_types.get(&id_col) {
                 if let Some(e) = solution_mappings.rdf_node_types.get(&id_col) {
@@ -146,6 +185,22 @@ impl Combiner {
         }
 
         solution_mappings.mappings = solution_mappings.mappings.collect().unwrap().lazy();
+        if let Some((synchronizer, vq_is_left)) =
+            self.find_as_of_synchronizer(&vq, &solution_mappings)
+        {
+            solution_mappings.mappings = as_of_join(
+                solution_mappings.mappings,
+                mappings,
+                &on_cols,
+                synchronizer,
+                vq_is_left,
+            )
+            .drop(drop_cols.iter().map(col));
+            for c in &drop_cols {
+                solution_mappings.rdf_node_types.remove(c);
+            }
+            return Ok(solution_mappings);
+        }
         let sort_opts = SortMultipleOptions::new()
             .with_order_descending(false)
             .with_maintain_order(false)
@@ -171,6 +226,88 @@ impl Combiner {
     }
 }
 
+impl Combiner {
+    // Finds an as-of synchronizer between this virtualized query and the series already attached
+    fn find_as_of_synchronizer(
+        &self,
+        vq: &VirtualizedQuery,
+        solution_mappings: &SolutionMappings,
+    ) -> Option<(AsOfSynchronizer, bool)> {
+        let vq_columns = vq.expected_columns();
+        let in_mappings = |v: &Variable| solution_mappings.rdf_node_types.contains_key(v.as_str());
+        let in_vq = |v: &Variable| vq_columns.contains(v.as_str());
+        for s in &self.prepper.as_of_synchronizers {
+            if in_vq(&s.left) && !in_vq(&s.right) && in_mappings(&s.right) {
+                return Some((s.clone(), true));
+            } else if in_vq(&s.right) && !in_vq(&s.left) && in_mappings(&s.left) {
+                return Some((s.clone(), false));
+            }
+        }
+        None
+    }
+}
+
+fn as_of_join(
+    solution_mappings: LazyFrame,
+    vq_mappings: LazyFrame,
+    on_cols: &[Expr],
+    synchronizer: AsOfSynchronizer,
+    vq_is_left: bool,
+) -> LazyFrame {
+    let AsOfSynchronizer {
+        left,
+        right,
+        strategy,
+        tolerance,
+    } = synchronizer;
+    let (left_mappings, right_mappings) = if vq_is_left {
+        (vq_mappings, solution_mappings)
+    } else {
+        (solution_mappings, vq_mappings)
+    };
+    let sort_opts = SortMultipleOptions::new()
+        .with_order_descending(false)
+        .with_maintain_order(false);
+    let left_mappings = left_mappings
+        .filter(col(left.as_str()).is_not_null())
+        .sort([left.as_str()], sort_opts.clone());
+    let right_mappings = right_mappings
+        .filter(col(right.as_str()).is_not_null())
+        .sort([right.as_str()], sort_opts);
+    let by: Vec<PlSmallStr> = on_cols
+        .iter()
+        .map(|c| match c {
+            Expr::Column(name) => name.clone(),
+            _ => unreachable!("Should only join on columns"),
+        })
+        .collect();
+    let (left_by, right_by) = if by.is_empty() {
+        (None, None)
+    } else {
+        (Some(by.clone()), Some(by))
+    };
+    let options = AsOfOptions {
+        strategy: match strategy {
+            AsOfStrategy::Backward => AsofStrategy::Backward,
+            AsOfStrategy::Forward => AsofStrategy::Forward,
+            AsOfStrategy::Nearest => AsofStrategy::Nearest,
+        },
+        tolerance_str: tolerance.map(|t| format!("{}us", t.as_micros()).into()),
+        left_by,
+        right_by,
+        ..Default::default()
+    };
+    left_mappings
+        .join(
+            right_mappings,
+            [col(left.as_str())],
+            [col(right.as_str())],
+            JoinArgs::new(JoinType::AsOf(options)),
+        )
+        // Rows without a match within the tolerance are removed, as in other joins between series
+        .filter(col(right.as_str()).is_not_null())
+}
+
 pub(crate) fn split_virtualized_queries(
     virtualized_queries: &mut Option<HashMap<Context, Vec<VirtualizedQuery>>>,
     context: &Context,
@@ -211,47 +348,111 @@ fn get_drop_cols(vq: &VirtualizedQuery) -> HashSet<String> {
     drop_cols
 }
 
-pub(crate) fn complete_basic_virtualized_queries(
-    static_query_solutions: &Vec<QuerySolution>,
-    basic_virtualized_queries: &mut Vec<BasicVirtualizedQuery>,
-) -> Result<(), CombinerError> {
-    for basic_query in basic_virtualized_queries {
-        let mut ids = HashSet::new();
-        for sqs in static_query_solutions {
-            if let Some(Term::Literal(lit)) = sqs.get(&basic_query.identifier_variable) {
-                if lit.datatype() == xsd::STRING {
-                    ids.insert(lit.value().to_string());
-                } else {
-                    todo!()
-                }
+// Collects the identifiers and resource of each basic virtualized query from static query solutions as they arrive
+pub(crate) struct BasicVirtualizedQueryCompleter<'a> {
+    basic_virtualized_queries: &'a mut Vec<BasicVirtualizedQuery>,
+    ids: Vec<HashSet<String>>,
+    error: Option<CombinerError>,
+}
+
+impl<'a> BasicVirtualizedQueryCompleter<'a> {
+    pub(crate) fn new(
+        basic_virtualized_queries: &'a mut Vec<BasicVirtualizedQuery>,
+    ) -> BasicVirtualizedQueryCompleter<'a> {
+        BasicVirtualizedQueryCompleter {
+            ids: basic_virtualized_queries
+                .iter()
+                .map(|_| HashSet::new())
+                .collect(),
+            basic_virtualized_queries,
+            error: None,
+        }
+    }
+
+    pub(crate) fn push(&mut self, sqs: &QuerySolution) {
+        if self.error.is_some() {
+            return;
+        }
+        for (basic_query, ids) in self
+            .basic_virtualized_queries
+            .iter_mut()
+            .zip(self.ids.iter_mut())
+        {
+            if let Err(e) = complete_basic_virtualized_query(basic_query, ids, sqs) {
+                self.error = Some(e);
+                return;
             }
         }
+    }
 
-        for sqs in static_query_solutions {
-            if let Some(Term::Literal(lit)) = sqs.get(&basic_query.resource_variable) {
-                if basic_query.resource.is_none() {
-                    if lit.datatype() != xsd::STRING {
-                        return Err(CombinerError::ResourceIsNotString(
-                            basic_query.query_source_context.as_str().to_string(),
-                            lit.datatype().to_string(),
-                        ));
-                    }
-                    basic_query.resource = Some(lit.value().into());
-                } else if let Some(res) = &basic_query.resource {
-                    if res != lit.value() {
-                        return Err(CombinerError::InconsistentResourceName(
-                            basic_query.query_source_context.as_str().to_string(),
-                            res.clone(),
-                            lit.value().to_string(),
-                        ));
-                    }
-                }
+    pub(crate) fn finish(self) -> Result<(), CombinerError> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        for (basic_query, ids) in self.basic_virtualized_queries.iter_mut().zip(self.ids) {
+            let mut ids_vec: Vec<String> = ids.into_iter().collect();
+            ids_vec.sort();
+            basic_query.ids = Some(ids_vec);
+        }
+        Ok(())
+    }
+}
+
+fn complete_basic_virtualized_query(
+    basic_query: &mut BasicVirtualizedQuery,
+    ids: &mut HashSet<String>,
+    sqs: &QuerySolution,
+) -> Result<(), CombinerError> {
+    if let Some(term) = sqs.get(&basic_query.identifier_variable) {
+        let (id, id_type) = match term {
+            Term::NamedNode(nn) => (nn.as_str().to_string(), BaseRDFNodeType::IRI),
+            Term::Literal(lit) => (
+                lit.value().to_string(),
+                BaseRDFNodeType::Literal(lit.datatype().into_owned()),
+            ),
+            Term::BlankNode(_) => {
+                return Err(CombinerError::IdentifierIsBlankNode(
+                    basic_query.query_source_context.as_str().to_string(),
+                ));
+            }
+        };
+        if let Some(existing_type) = &basic_query.id_type {
+            if existing_type != &id_type {
+                return Err(CombinerError::InconsistentDatatype(
+                    existing_type.to_string(),
+                    id_type.to_string(),
+                    basic_query.identifier_variable.as_str().to_string(),
+                ));
             }
+        } else {
+            basic_query.id_type = Some(id_type);
         }
+        ids.insert(id);
+    }
 
-        let mut ids_vec: Vec<String> = ids.into_iter().collect();
-        ids_vec.sort();
-        basic_query.ids = Some(ids_vec);
+    let resource = match sqs.get(&basic_query.resource_variable) {
+        Some(Term::Literal(lit)) => {
+            if lit.datatype() != xsd::STRING {
+                return Err(CombinerError::ResourceIsNotString(
+                    basic_query.query_source_context.as_str().to_string(),
+                    lit.datatype().to_string(),
+                ));
+            }
+            lit.value()
+        }
+        Some(Term::NamedNode(nn)) => nn.as_str(),
+        _ => return Ok(()),
+    };
+    if let Some(res) = &basic_query.resource {
+        if res != resource {
+            return Err(CombinerError::InconsistentResourceName(
+                basic_query.query_source_context.as_str().to_string(),
+                res.clone(),
+                resource.to_string(),
+            ));
+        }
+    } else {
+        basic_query.resource = Some(resource.to_string());
     }
     Ok(())
 }
@@ -6,5 +6,31 @@ pub const HAS_RESOURCE: &str = "https://github.com/DataTreehouse/chrontext#hasRe
 pub const HAS_EXTERNAL_ID: &str = "https://github.com/DataTreehouse/chrontext#hasExternalId";
 
 pub const DATE_BIN: &str = "https://github.com/DataTreehouse/chrontext#dateBin";
+pub const FLOOR_DATE_TIME_TO_SECONDS_INTERVAL: &str =
+    "https://github.com/DataTreehouse/chrontext#FloorDateTimeToSecondsInterval";
+pub const INTERPOLATE_LINEAR: &str = "https://github.com/DataTreehouse/chrontext#interpolateLinear";
+pub const LOCF: &str = "https://github.com/DataTreehouse/chrontext#locf";
+pub const FILL_NULL: &str = "https://github.com/DataTreehouse/chrontext#fillNull";
+pub const MEDIAN: &str = "https://github.com/DataTreehouse/chrontext#median";
+// Followed by the percentile, e.g. ct:percentile95
+pub const PERCENTILE: &str = "https://github.com/DataTreehouse/chrontext#percentile";
+pub const STDDEV: &str = "https://github.com/DataTreehouse/chrontext#stddev";
+pub const VARIANCE: &str = "https://github.com/DataTreehouse/chrontext#variance";
+pub const FIRST: &str = "https://github.com/DataTreehouse/chrontext#first";
+pub const LAST: &str = "https://github.com/DataTreehouse/chrontext#last";
+pub const LAG: &str = "https://github.com/DataTreehouse/chrontext#lag";
+pub const LEAD: &str = "https://github.com/DataTreehouse/chrontext#lead";
+pub const ROLLING_MEAN: &str = "https://github.com/DataTreehouse/chrontext#rollingMean";
+pub const ROLLING_SUM: &str = "https://github.com/DataTreehouse/chrontext#rollingSum";
+pub const CUMULATIVE_SUM: &str = "https://github.com/DataTreehouse/chrontext#cumulativeSum";
+pub const DELTA: &str = "https://github.com/DataTreehouse/chrontext#delta";
+pub const DERIVATIVE: &str = "https://github.com/DataTreehouse/chrontext#derivative";
+pub const ROW_NUMBER: &str = "https://github.com/DataTreehouse/chrontext#rowNumber";
+pub const REVERSE_ROW_NUMBER: &str = "https://github.com/DataTreehouse/chrontext#reverseRowNumber";
+pub const LATEST: &str = "https://github.com/DataTreehouse/chrontext#latest";
+pub const EARLIEST: &str = "https://github.com/DataTreehouse/chrontext#earliest";
+pub const AS_OF: &str = "https://github.com/DataTreehouse/chrontext#asOf";
 pub const NEST: &str = "https://github.com/DataTreehouse/chrontext#nestAggregation";
 pub const GROUPING_COL: &str = "grouping_col";
+pub const PARTIAL_AGGREGATION_COL: &str = "partial_aggregation";
+pub const TOP_K_ROW_NUMBER_COL: &str = "top_k_row_number";
@@ -0,0 +1,114 @@
+use crate::combiner::CombinerError;
+use crate::sparql_result_to_polars::StaticQueryDataFrameBuilder;
+use oxrdf::{BlankNode, Subject, Term, Triple, Variable};
+use oxttl::{NTriplesSerializer, TurtleSerializer};
+use polars::prelude::DataFrame;
+use representation::polars_to_rdf::{df_as_result, QuerySolutions};
+use representation::RDFNodeType;
+use spargebra::term::{NamedNodePattern, TermPattern, TriplePattern};
+use std::collections::HashMap;
+
+pub const SUBJECT_COL_NAME: &str = "subject";
+pub const PREDICATE_COL_NAME: &str = "predicate";
+pub const OBJECT_COL_NAME: &str = "object";
+
+pub(crate) fn construct_triples(
+    template: &[TriplePattern],
+    df: DataFrame,
+    rdf_node_types: &HashMap<String, RDFNodeType>,
+) -> Vec<Triple> {
+    let QuerySolutions {
+        variables,
+        solutions,
+    } = df_as_result(df, rdf_node_types);
+    let variable_index: HashMap<_, _> = variables
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (v.as_str(), i))
+        .collect();
+    let mut triples = vec![];
+    for solution in &solutions {
+        //Blank nodes in the template are fresh for each solution
+        let mut blank_nodes = HashMap::new();
+        for tp in template {
+            let subject =
+                match instantiate_term(&tp.subject, solution, &variable_index, &mut blank_nodes) {
+                    Some(Term::NamedNode(nn)) => Subject::NamedNode(nn),
+                    Some(Term::BlankNode(bn)) => Subject::BlankNode(bn),
+                    _ => continue,
+                };
+            let predicate = match &tp.predicate {
+                NamedNodePattern::NamedNode(nn) => nn.clone(),
+                NamedNodePattern::Variable(v) => {
+                    if let Some(Some(Term::NamedNode(nn))) =
+                        variable_index.get(v.as_str()).map(|i| &solution[*i])
+                    {
+                        nn.clone()
+                    } else {
+                        continue;
+                    }
+                }
+            };
+            let object = if let Some(object) =
+                instantiate_term(&tp.object, solution, &variable_index, &mut blank_nodes)
+            {
+                object
+            } else {
+                continue;
+            };
+            triples.push(Triple::new(subject, predicate, object));
+        }
+    }
+    triples
+}
+
+fn instantiate_term(
+    term_pattern: &TermPattern,
+    solution: &[Option<Term>],
+    variable_index: &HashMap<&str, usize>,
+    blank_nodes: &mut HashMap<BlankNode, BlankNode>,
+) -> Option<Term> {
+    match term_pattern {
+        TermPattern::NamedNode(nn) => Some(Term::NamedNode(nn.clone())),
+        TermPattern::BlankNode(bn) => Some(Term::BlankNode(
+            blank_nodes.entry(bn.clone()).or_default().clone(),
+        )),
+        TermPattern::Literal(l) => Some(Term::Literal(l.clone())),
+        TermPattern::Variable(v) => variable_index
+            .get(v.as_str())
+            .and_then(|i| solution[*i].clone()),
+    }
+}
+
+pub fn triples_to_df(
+    triples: &[Triple],
+) -> Result<(DataFrame, HashMap<String, RDFNodeType>), CombinerError> {
+    let mut builder = StaticQueryDataFrameBuilder::with_variables(
+        [SUBJECT_COL_NAME, PREDICATE_COL_NAME, OBJECT_COL_NAME]
+            .into_iter()
+            .map(Variable::new_unchecked)
+            .collect(),
+    );
+    for t in triples {
+        let subject = Term::from(t.subject.clone());
+        let predicate = Term::from(t.predicate.clone());
+        builder.push_terms(&[Some(&subject), Some(&predicate), Some(&t.object)]);
+    }
+    builder.finish()
+}
+
+pub fn triples_to_ntriples(triples: &[Triple]) -> String {
+    let mut serializer = NTriplesSerializer::new().for_writer(Vec::new());
+    for t in triples {
+        serializer.serialize_triple(t).unwrap();
+    }
+    String::from_utf8(serializer.finish()).unwrap()
+}
+
+pub fn triples_to_turtle(triples: &[Triple]) -> String {
+    let mut serializer = TurtleSerializer::new().for_writer(Vec::new());
+    for t in triples {
+        serializer.serialize_triple(t).unwrap();
+    }
+    String::from_utf8(serializer.finish().unwrap()).unwrap()
+}
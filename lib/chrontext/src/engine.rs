@@ -1,31 +1,56 @@
-use crate::combiner::Combiner;
+use crate::as_of::extract_as_of_synchronizers;
+use crate::combiner::{Combiner, CombinerError};
+use crate::construct::construct_triples;
 use crate::errors::ChrontextError;
+use crate::prepared::{PreparedQuery, RewrittenQuery};
 use crate::preprocessing::Preprocessor;
 use crate::rename_vars::rename_query_vars;
 use crate::rewriting::StaticQueryRewriter;
-use crate::splitter::parse_sparql_select_query;
+use crate::sparql_result_to_polars::StaticQueryDataFrameBuilder;
+use crate::splitter::{
+    parse_sparql_ask_query, parse_sparql_construct_query, parse_sparql_select_query,
+    QueryParseError,
+};
+use crate::top_k::rewrite_top_k_filters;
 use log::debug;
+use oxrdf::{Triple, Variable};
 use polars::enable_string_cache;
 use polars::frame::DataFrame;
 use polars::prelude::PlSmallStr;
 use representation::query_context::Context;
 use representation::solution_mapping::SolutionMappings;
 use representation::RDFNodeType;
+use spargebra::term::GroundTerm;
+use spargebra::Query;
 use sparql_database::embedded_oxigraph::EmbeddedOxigraph;
-use sparql_database::endpoint::SparqlEndpoint;
+use sparql_database::endpoint::{SparqlEndpoint, SparqlEndpointConfig};
+use sparql_database::native_oxigraph::NativeOxigraph;
 use sparql_database::SparqlQueryable;
 use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 use virtualization::{Virtualization, VirtualizedDatabase};
 use virtualized_query::pushdown_setting::PushdownSetting;
 
 pub struct EngineConfig {
-    pub sparql_endpoint: Option<String>,
+    pub sparql_endpoint: Option<SparqlEndpointConfig>,
     pub sparql_oxigraph_config: Option<EmbeddedOxigraph>,
+    pub sparql_native_oxigraph: Option<NativeOxigraph>,
     pub virtualized_database: VirtualizedDatabase,
     pub virtualization: Virtualization,
 }
 
+// How long a timed out or cancelled query may take to clean up before it is dropped
+const CANCELLATION_GRACE_PERIOD: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, Default)]
+pub struct QueryOptions {
+    pub timeout: Option<Duration>,
+    pub cancellation_token: CancellationToken,
+}
+
 pub struct Engine {
     pushdown_settings: HashSet<PushdownSetting>,
     virtualized_database: Arc<VirtualizedDatabase>,
@@ -52,14 +77,17 @@ impl Engine {
         let EngineConfig {
             sparql_endpoint,
             sparql_oxigraph_config,
+            sparql_native_oxigraph,
             virtualized_database,
             virtualization,
         } = engine_config;
 
         let sparql_queryable: Arc<dyn SparqlQueryable> = if let Some(endpoint) = sparql_endpoint {
-            Arc::new(SparqlEndpoint { endpoint })
+            Arc::new(SparqlEndpoint::new(endpoint))
         } else if let Some(config) = sparql_oxigraph_config {
             Arc::new(config)
+        } else if let Some(store) = sparql_native_oxigraph {
+            Arc::new(store)
         } else {
             return Err(ChrontextError::NoSPARQLDatabaseDefined);
         };
@@ -74,21 +102,129 @@ impl Engine {
         ))
     }
 
+    pub fn is_hybrid(&self, query: &str) -> Result<bool, ChrontextError> {
+        let parsed_query = parse_sparql_select_query(query)?;
+        let mut preprocessor = Preprocessor::new(
+            self.virtualization.get_virtualized_iris(),
+            self.virtualization.get_first_level_virtualized_iris(),
+        );
+        preprocessor.preprocess(&parsed_query);
+        Ok(preprocessor.found_virtualized_iris())
+    }
+
     pub async fn query<'py>(
         &self,
         query: &str,
     ) -> Result<(DataFrame, HashMap<String, RDFNodeType>, Vec<Context>), ChrontextError> {
-        enable_string_cache();
+        self.query_with_options(query, &QueryOptions::default())
+            .await
+    }
+
+    pub async fn query_with_options(
+        &self,
+        query: &str,
+        options: &QueryOptions,
+    ) -> Result<(DataFrame, HashMap<String, RDFNodeType>, Vec<Context>), ChrontextError> {
         let parsed_query = parse_sparql_select_query(query)?;
+        let prepared_query = self.prepare_select_query(parsed_query)?;
+        self.execute_prepared_query(&prepared_query, &HashMap::new(), options)
+            .await
+    }
+
+    pub fn prepare(&self, query: &str) -> Result<PreparedQuery, ChrontextError> {
+        let parsed_query = parse_sparql_select_query(query)?;
+        self.prepare_select_query(parsed_query)
+    }
+
+    pub async fn query_prepared(
+        &self,
+        prepared_query: &PreparedQuery,
+        bindings: &HashMap<Variable, GroundTerm>,
+    ) -> Result<(DataFrame, HashMap<String, RDFNodeType>, Vec<Context>), ChrontextError> {
+        self.query_prepared_with_options(prepared_query, bindings, &QueryOptions::default())
+            .await
+    }
+
+    pub async fn query_prepared_with_options(
+        &self,
+        prepared_query: &PreparedQuery,
+        bindings: &HashMap<Variable, GroundTerm>,
+        options: &QueryOptions,
+    ) -> Result<(DataFrame, HashMap<String, RDFNodeType>, Vec<Context>), ChrontextError> {
+        self.execute_prepared_query(prepared_query, bindings, options)
+            .await
+    }
+
+    pub async fn construct(&self, query: &str) -> Result<Vec<Triple>, ChrontextError> {
+        self.construct_with_options(query, &QueryOptions::default())
+            .await
+    }
+
+    pub async fn construct_with_options(
+        &self,
+        query: &str,
+        options: &QueryOptions,
+    ) -> Result<Vec<Triple>, ChrontextError> {
+        let (template, select_query) = parse_sparql_construct_query(query)?;
+        let prepared_query = self.prepare_select_query(select_query)?;
+        let (df, rdf_node_types, _) = self
+            .execute_prepared_query(&prepared_query, &HashMap::new(), options)
+            .await?;
+        Ok(construct_triples(&template, df, &rdf_node_types))
+    }
+
+    pub async fn ask(&self, query: &str) -> Result<bool, ChrontextError> {
+        self.ask_with_options(query, &QueryOptions::default()).await
+    }
+
+    pub async fn ask_with_options(
+        &self,
+        query: &str,
+        options: &QueryOptions,
+    ) -> Result<bool, ChrontextError> {
+        let select_query = parse_sparql_ask_query(query)?;
+        let prepared_query = self.prepare_select_query(select_query)?;
+        let (df, _, _) = self
+            .execute_prepared_query(&prepared_query, &HashMap::new(), options)
+            .await?;
+        Ok(df.height() > 0)
+    }
+
+    // Runs the rewrite of the query up to the execution of the static queries
+    fn prepare_select_query(&self, parsed_query: Query) -> Result<PreparedQuery, ChrontextError> {
+        //Executions of a prepared query rely on it being a select query
+        if !matches!(parsed_query, Query::Select { .. }) {
+            return Err(QueryParseError::NotSelectQuery.into());
+        }
         debug!("Parsed query: {}", parsed_query.to_string());
         debug!("Parsed query algebra: {:?}", &parsed_query);
+        let static_query = parsed_query.clone();
         let (parsed_query, rename_map) = rename_query_vars(parsed_query);
+        let (parsed_query, as_of_synchronizers) = extract_as_of_synchronizers(parsed_query)?;
+        let (parsed_query, top_k_filters) = rewrite_top_k_filters(parsed_query)?;
         let virtualized_iris = self.virtualization.get_virtualized_iris();
         let first_level_virtualized_iris = self.virtualization.get_first_level_virtualized_iris();
 
         let mut preprocessor =
             Preprocessor::new(virtualized_iris, first_level_virtualized_iris.clone());
         let (preprocessed_query, variable_constraints) = preprocessor.preprocess(&parsed_query);
+        if !preprocessor.found_virtualized_iris() {
+            //The static query is sent to the store as it is, and the store does not know these functions
+            if !as_of_synchronizers.is_empty() {
+                return Err(ChrontextError::NoVirtualizedResources(
+                    "ct:asOf".to_string(),
+                ));
+            }
+            if top_k_filters > 0 {
+                return Err(ChrontextError::NoVirtualizedResources(
+                    "ct:latest or ct:earliest".to_string(),
+                ));
+            }
+            return Ok(PreparedQuery {
+                static_query,
+                rewritten: None,
+            });
+        }
         debug!("Constraints: {:?}", variable_constraints);
         let rewriter = StaticQueryRewriter::new(variable_constraints, first_level_virtualized_iris);
         let (static_queries_map, basic_virtualized_queries, rewritten_filters) =
@@ -103,14 +239,85 @@ impl Engine {
             "Produced basic time series queries: {:?}",
             basic_virtualized_queries,
         );
+        Ok(PreparedQuery {
+            static_query,
+            rewritten: Some(RewrittenQuery {
+                rename_map,
+                as_of_synchronizers,
+                preprocessed_query,
+                static_queries_map,
+                basic_virtualized_queries,
+                rewritten_filters,
+            }),
+        })
+    }
+
+    async fn execute_prepared_query(
+        &self,
+        prepared_query: &PreparedQuery,
+        bindings: &HashMap<Variable, GroundTerm>,
+        options: &QueryOptions,
+    ) -> Result<(DataFrame, HashMap<String, RDFNodeType>, Vec<Context>), ChrontextError> {
+        execute_with_options(options, |cancellation_token| async move {
+            self.execute_prepared_query_until_cancelled(
+                prepared_query,
+                bindings,
+                &cancellation_token,
+            )
+            .await
+        })
+        .await
+    }
+
+    async fn execute_prepared_query_until_cancelled(
+        &self,
+        prepared_query: &PreparedQuery,
+        bindings: &HashMap<Variable, GroundTerm>,
+        cancellation_token: &CancellationToken,
+    ) -> Result<(DataFrame, HashMap<String, RDFNodeType>, Vec<Context>), ChrontextError> {
+        enable_string_cache();
+        let (df, rdf_node_types, virtualized_contexts) =
+            if let Some(rewritten) = &prepared_query.rewritten {
+                let rewritten = prepared_query.bind_rewritten_query(rewritten, bindings)?;
+                self.execute_hybrid_query(rewritten, cancellation_token)
+                    .await?
+            } else {
+                debug!("Query does not use virtualized IRIs, executing it as a static query");
+                let static_query = prepared_query.bind_static_query(bindings)?;
+                execute_static_query(
+                    self.sparql_database.as_ref(),
+                    &static_query,
+                    cancellation_token,
+                )
+                .await?
+            };
+        let (df, rdf_node_types) =
+            prepared_query.add_projected_parameters(df, rdf_node_types, bindings)?;
+        Ok((df, rdf_node_types, virtualized_contexts))
+    }
 
+    async fn execute_hybrid_query(
+        &self,
+        rewritten_query: RewrittenQuery,
+        cancellation_token: &CancellationToken,
+    ) -> Result<(DataFrame, HashMap<String, RDFNodeType>, Vec<Context>), ChrontextError> {
+        let RewrittenQuery {
+            rename_map,
+            as_of_synchronizers,
+            preprocessed_query,
+            static_queries_map,
+            basic_virtualized_queries,
+            rewritten_filters,
+        } = rewritten_query;
         let mut combiner = Combiner::new(
             self.sparql_database.clone(),
             self.pushdown_settings.clone(),
             self.virtualized_database.clone(),
             basic_virtualized_queries,
             rewritten_filters,
+            as_of_synchronizers,
             self.virtualization.clone(),
+            cancellation_token.clone(),
         );
         let mut solution_mappings = combiner
             .combine_static_and_time_series_results(static_queries_map, &preprocessed_query)
@@ -140,3 +347,145 @@ impl Engine {
         ))
     }
 }
+
+// Runs an execution with the timeout and cancellation token of the query options
+async fn execute_with_options<T, F, Fut>(
+    options: &QueryOptions,
+    execute: F,
+) -> Result<T, ChrontextError>
+where
+    F: FnOnce(CancellationToken) -> Fut,
+    Fut: Future<Output = Result<T, ChrontextError>>,
+{
+    // Cancelling the child token on timeout leaves the caller's token untouched
+    let cancellation_token = options.cancellation_token.child_token();
+    let execution = execute(cancellation_token.clone());
+    tokio::pin!(execution);
+    let timeout = async {
+        match options.timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+    let err = tokio::select! {
+        res = &mut execution => {
+            return if cancellation_token.is_cancelled() {
+                Err(ChrontextError::Cancelled)
+            } else {
+                res
+            };
+        }
+        _ = timeout => ChrontextError::Timeout(options.timeout.unwrap()),
+        _ = cancellation_token.cancelled() => ChrontextError::Cancelled,
+    };
+    cancellation_token.cancel();
+    // Lets the static query and virtualized queries observe the cancellation, so that e.g.
+    // BigQuery jobs are cancelled as well. Steps that do not observe it are dropped.
+    let _ = tokio::time::timeout(CANCELLATION_GRACE_PERIOD, execution).await;
+    Err(err)
+}
+
+async fn execute_static_query(
+    sparql_database: &dyn SparqlQueryable,
+    query: &Query,
+    cancellation_token: &CancellationToken,
+) -> Result<(DataFrame, HashMap<String, RDFNodeType>, Vec<Context>), ChrontextError> {
+    let mut builder = StaticQueryDataFrameBuilder::new(query)?;
+    tokio::select! {
+        result = sparql_database.execute(query, &mut builder) => {
+            result.map_err(|source| {
+                ChrontextError::CombinerError(CombinerError::StaticQueryExecutionError {
+                    source,
+                    query: query.to_string(),
+                    context: Context::new().as_str().to_string(),
+                })
+            })?
+        }
+        _ = cancellation_token.cancelled() => return Err(ChrontextError::Cancelled),
+    };
+    let (df, rdf_node_types) = builder.finish()?;
+    Ok((df, rdf_node_types, vec![]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use sparesults::QuerySolution;
+    use sparql_database::{SolutionSink, SparqlQueryError};
+    use std::time::Instant;
+
+    // A static database that never answers
+    struct PendingDatabase;
+
+    #[async_trait]
+    impl SparqlQueryable for PendingDatabase {
+        async fn execute(
+            &self,
+            _query: &Query,
+            _sink: &mut dyn SolutionSink,
+        ) -> Result<(), SparqlQueryError> {
+            std::future::pending().await
+        }
+    }
+
+    fn static_query() -> Query {
+        Query::parse("SELECT ?s WHERE { ?s ?p ?o }", None).unwrap()
+    }
+
+    async fn execute_pending_static_query(
+        options: &QueryOptions,
+    ) -> Result<(DataFrame, HashMap<String, RDFNodeType>, Vec<Context>), ChrontextError> {
+        let query = static_query();
+        execute_with_options(options, |cancellation_token| async move {
+            execute_static_query(&PendingDatabase, &query, &cancellation_token).await
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_static_query_timeout() {
+        let options = QueryOptions {
+            timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let res = execute_pending_static_query(&options).await;
+        assert!(matches!(res, Err(ChrontextError::Timeout(_))));
+        assert!(start.elapsed() < CANCELLATION_GRACE_PERIOD);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_drops_execution_ignoring_cancellation() {
+        let options = QueryOptions {
+            timeout: Some(Duration::from_millis(50)),
+            ..Default::default()
+        };
+        let query = static_query();
+        let start = Instant::now();
+        // The execution does not observe the cancellation, so it is dropped after the grace period
+        let res: Result<(), ChrontextError> = execute_with_options(&options, |_| async {
+            let mut solutions: Vec<QuerySolution> = vec![];
+            PendingDatabase
+                .execute(&query, &mut solutions)
+                .await
+                .unwrap();
+            Ok(())
+        })
+        .await;
+        assert!(matches!(res, Err(ChrontextError::Timeout(_))));
+        assert!(start.elapsed() < CANCELLATION_GRACE_PERIOD + Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_static_query() {
+        let options = QueryOptions::default();
+        let cancellation_token = options.cancellation_token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            cancellation_token.cancel();
+        });
+        let res = execute_pending_static_query(&options).await;
+        assert!(matches!(res, Err(ChrontextError::Cancelled)));
+    }
+}
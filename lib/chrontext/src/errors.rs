@@ -1,5 +1,7 @@
 use crate::combiner::CombinerError;
 use crate::splitter::QueryParseError;
+use polars::prelude::PolarsError;
+use std::time::Duration;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -10,6 +12,26 @@ pub enum ChrontextError {
     CreateSPARQLDatabaseError(String),
     #[error("No timeseries database defined")]
     NoTimeseriesDatabaseDefined,
+    #[error("Query has no variable `{0}` that can be bound")]
+    UnknownQueryParameter(String),
+    #[error("Variable `{0}` must be bound to an IRI")]
+    InvalidQueryParameter(String),
+    #[error("Variable `{0}` is used to find virtualized resources and can not be bound")]
+    VirtualizedQueryParameter(String),
+    #[error("Variable `{0}` is assigned by the query and can not be bound")]
+    AssignedQueryParameter(String),
+    #[error("Error adding the bound parameters to the solutions {0}")]
+    ProjectedParametersError(PolarsError),
+    #[error("Invalid arguments to ct:asOf, {0}")]
+    InvalidAsOfArguments(String),
+    #[error("Invalid arguments to ct:latest or ct:earliest, {0}")]
+    InvalidTopKArguments(String),
+    #[error("{0} can only be used in queries over virtualized resources")]
+    NoVirtualizedResources(String),
+    #[error("Query timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Query was cancelled")]
+    Cancelled,
     #[error(transparent)]
     QueryParseError(#[from] QueryParseError),
     #[error(transparent)]
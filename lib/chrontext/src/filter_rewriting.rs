@@ -0,0 +1,111 @@
+use crate::errors::ChrontextError;
+use spargebra::algebra::{Expression, GraphPattern};
+
+// Rewrites each filter with its inner graph pattern, innermost filters first
+pub(crate) fn rewrite_filters<F>(
+    gp: GraphPattern,
+    rewrite: &mut F,
+) -> Result<GraphPattern, ChrontextError>
+where
+    F: FnMut(Expression, GraphPattern) -> Result<GraphPattern, ChrontextError>,
+{
+    Ok(match gp {
+        GraphPattern::Filter { expr, inner } => {
+            let inner = rewrite_filters(*inner, rewrite)?;
+            rewrite(expr, inner)?
+        }
+        GraphPattern::Join { left, right } => GraphPattern::Join {
+            left: Box::new(rewrite_filters(*left, rewrite)?),
+            right: Box::new(rewrite_filters(*right, rewrite)?),
+        },
+        GraphPattern::LeftJoin {
+            left,
+            right,
+            expression,
+        } => GraphPattern::LeftJoin {
+            left: Box::new(rewrite_filters(*left, rewrite)?),
+            right: Box::new(rewrite_filters(*right, rewrite)?),
+            expression,
+        },
+        GraphPattern::Union { left, right } => GraphPattern::Union {
+            left: Box::new(rewrite_filters(*left, rewrite)?),
+            right: Box::new(rewrite_filters(*right, rewrite)?),
+        },
+        GraphPattern::Minus { left, right } => GraphPattern::Minus {
+            left: Box::new(rewrite_filters(*left, rewrite)?),
+            right: Box::new(rewrite_filters(*right, rewrite)?),
+        },
+        GraphPattern::Graph { name, inner } => GraphPattern::Graph {
+            name,
+            inner: Box::new(rewrite_filters(*inner, rewrite)?),
+        },
+        GraphPattern::Extend {
+            inner,
+            variable,
+            expression,
+        } => GraphPattern::Extend {
+            inner: Box::new(rewrite_filters(*inner, rewrite)?),
+            variable,
+            expression,
+        },
+        GraphPattern::OrderBy { inner, expression } => GraphPattern::OrderBy {
+            inner: Box::new(rewrite_filters(*inner, rewrite)?),
+            expression,
+        },
+        GraphPattern::Project { inner, variables } => GraphPattern::Project {
+            inner: Box::new(rewrite_filters(*inner, rewrite)?),
+            variables,
+        },
+        GraphPattern::Distinct { inner } => GraphPattern::Distinct {
+            inner: Box::new(rewrite_filters(*inner, rewrite)?),
+        },
+        GraphPattern::Reduced { inner } => GraphPattern::Reduced {
+            inner: Box::new(rewrite_filters(*inner, rewrite)?),
+        },
+        GraphPattern::Slice {
+            inner,
+            start,
+            length,
+        } => GraphPattern::Slice {
+            inner: Box::new(rewrite_filters(*inner, rewrite)?),
+            start,
+            length,
+        },
+        GraphPattern::Group {
+            inner,
+            variables,
+            aggregates,
+        } => GraphPattern::Group {
+            inner: Box::new(rewrite_filters(*inner, rewrite)?),
+            variables,
+            aggregates,
+        },
+        gp => gp,
+    })
+}
+
+pub(crate) fn conjuncts(expr: Expression) -> Vec<Expression> {
+    match expr {
+        Expression::And(left, right) => {
+            let mut out = conjuncts(*left);
+            out.extend(conjuncts(*right));
+            out
+        }
+        e => vec![e],
+    }
+}
+
+// The conditions that are kept, or the inner graph pattern if there are none
+pub(crate) fn filter(conditions: Vec<Expression>, inner: GraphPattern) -> GraphPattern {
+    if let Some(expr) = conditions
+        .into_iter()
+        .reduce(|left, right| Expression::And(Box::new(left), Box::new(right)))
+    {
+        GraphPattern::Filter {
+            expr,
+            inner: Box::new(inner),
+        }
+    } else {
+        inner
+    }
+}
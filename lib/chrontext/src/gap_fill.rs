@@ -0,0 +1,319 @@
+use crate::as_of::parse_day_time_duration;
+use crate::combiner::CombinerError;
+use crate::constants::{
+    DATE_BIN, FILL_NULL, FLOOR_DATE_TIME_TO_SECONDS_INTERVAL, INTERPOLATE_LINEAR, LOCF,
+};
+use crate::filter_rewriting::conjuncts;
+use oxrdf::vocab::xsd;
+use oxrdf::{Literal, Variable};
+use polars::prelude::{
+    col, datetime_ranges, ClosedWindow, Duration as PolarsDuration, Expr, FillNullStrategy,
+    InterpolationMethod, JoinArgs, JoinType, LazyFrame, SortMultipleOptions,
+};
+use spargebra::algebra::{
+    AggregateExpression, AggregateFunction, Expression, Function, GraphPattern,
+};
+use std::collections::HashSet;
+use std::time::Duration;
+use virtualized_query::pushdown_setting::PushdownSetting;
+use virtualized_query::{GapFill, GapFillMethod};
+
+const GAP_FILL_START: &str = "gap_fill_start";
+const GAP_FILL_END: &str = "gap_fill_end";
+
+pub(crate) fn gap_fill_method(aggregate_expression: &AggregateExpression) -> Option<GapFillMethod> {
+    if let AggregateExpression::FunctionCall {
+        name: AggregateFunction::Custom(name),
+        ..
+    } = aggregate_expression
+    {
+        match name.as_str() {
+            INTERPOLATE_LINEAR => Some(GapFillMethod::Linear),
+            LOCF => Some(GapFillMethod::Locf),
+            FILL_NULL => Some(GapFillMethod::Null),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+// Gap filling aggregations are averages of the values in each bucket
+pub(crate) fn without_gap_fill(
+    aggregations: &[(Variable, AggregateExpression)],
+) -> Vec<(Variable, AggregateExpression)> {
+    aggregations
+        .iter()
+        .map(|(v, a)| match a {
+            AggregateExpression::FunctionCall { expr, distinct, .. }
+                if gap_fill_method(a).is_some() =>
+            {
+                (
+                    v.clone(),
+                    AggregateExpression::FunctionCall {
+                        name: AggregateFunction::Avg,
+                        expr: expr.clone(),
+                        distinct: *distinct,
+                    },
+                )
+            }
+            _ => (v.clone(), a.clone()),
+        })
+        .collect()
+}
+
+// The buckets are found from the ct:dateBin or ct:FloorDateTimeToSecondsInterval that binds a grouping variable
+pub(crate) fn find_gap_fill(
+    inner: &GraphPattern,
+    by: &[Variable],
+    aggregations: &[(Variable, AggregateExpression)],
+) -> Result<Option<GapFill>, CombinerError> {
+    let methods: Vec<_> = aggregations
+        .iter()
+        .filter_map(|(v, a)| gap_fill_method(a).map(|m| (v.clone(), m)))
+        .collect();
+    if methods.is_empty() {
+        return Ok(None);
+    }
+    if let Some((bucket_variable, timestamp_variable, bucket_width, origin)) =
+        find_bucket(inner, by)?
+    {
+        let (start, end) = if let Some(t) = &timestamp_variable {
+            find_time_range(inner, t)
+        } else {
+            (None, None)
+        };
+        Ok(Some(GapFill {
+            bucket_variable,
+            bucket_width,
+            origin,
+            methods,
+            start,
+            end,
+        }))
+    } else {
+        Err(CombinerError::InvalidGapFill(
+            "no grouping variable is bound using ct:dateBin or ct:FloorDateTimeToSecondsInterval"
+                .to_string(),
+        ))
+    }
+}
+
+// Whether the gaps are filled by the virtualized database, some databases need the time range to be known
+pub(crate) fn gap_fill_pushed_down(
+    pushdown_settings: &HashSet<PushdownSetting>,
+    gap_fill: &GapFill,
+) -> bool {
+    pushdown_settings.contains(&PushdownSetting::GapFill)
+        || (pushdown_settings.contains(&PushdownSetting::GapFillInTimeRange)
+            && gap_fill.start.is_some()
+            && gap_fill.end.is_some())
+}
+
+type Bucket = (Variable, Option<Variable>, Duration, Option<Literal>);
+
+fn find_bucket(gp: &GraphPattern, by: &[Variable]) -> Result<Option<Bucket>, CombinerError> {
+    match gp {
+        GraphPattern::Extend {
+            inner,
+            variable,
+            expression,
+        } => {
+            if by.contains(variable) {
+                if let Some((timestamp, width, origin)) = bucket_width_and_origin(expression)? {
+                    return Ok(Some((variable.clone(), timestamp, width, origin)));
+                }
+            }
+            find_bucket(inner, by)
+        }
+        GraphPattern::Join { left, right }
+        | GraphPattern::LeftJoin { left, right, .. }
+        | GraphPattern::Union { left, right }
+        | GraphPattern::Minus { left, right } => {
+            if let Some(b) = find_bucket(left, by)? {
+                Ok(Some(b))
+            } else {
+                find_bucket(right, by)
+            }
+        }
+        GraphPattern::Filter { inner, .. }
+        | GraphPattern::Graph { inner, .. }
+        | GraphPattern::OrderBy { inner, .. }
+        | GraphPattern::Project { inner, .. }
+        | GraphPattern::Distinct { inner }
+        | GraphPattern::Reduced { inner }
+        | GraphPattern::Slice { inner, .. } => find_bucket(inner, by),
+        _ => Ok(None),
+    }
+}
+
+type BucketWidthAndOrigin = (Option<Variable>, Duration, Option<Literal>);
+
+fn bucket_width_and_origin(
+    expression: &Expression,
+) -> Result<Option<BucketWidthAndOrigin>, CombinerError> {
+    let Expression::FunctionCall(Function::Custom(f), args) = expression else {
+        return Ok(None);
+    };
+    let invalid = |reason: &str| CombinerError::InvalidGapFill(reason.to_string());
+    if f.as_str() == FLOOR_DATE_TIME_TO_SECONDS_INTERVAL {
+        let Some(Expression::Literal(l)) = args.get(1) else {
+            return Err(invalid(
+                "the bucket width must be a literal number of seconds",
+            ));
+        };
+        let seconds = l
+            .value()
+            .parse::<f64>()
+            .ok()
+            .filter(|x| *x > 0.0)
+            .ok_or_else(|| invalid("the bucket width must be a positive number of seconds"))?;
+        Ok(Some((
+            timestamp_variable(args.first()),
+            Duration::from_secs_f64(seconds),
+            None,
+        )))
+    } else if f.as_str() == DATE_BIN {
+        let Some(Expression::Literal(l)) = args.first() else {
+            return Err(invalid("the bucket width must be a literal duration"));
+        };
+        let width = parse_day_time_duration(l.value())
+            .filter(|x| !x.is_zero())
+            .ok_or_else(|| {
+                invalid("the bucket width must be a duration without years or months")
+            })?;
+        let origin = if let Some(Expression::Literal(origin)) = args.get(2) {
+            Some(origin.clone())
+        } else {
+            None
+        };
+        Ok(Some((timestamp_variable(args.get(1)), width, origin)))
+    } else {
+        Ok(None)
+    }
+}
+
+fn timestamp_variable(arg: Option<&Expression>) -> Option<Variable> {
+    if let Some(Expression::Variable(v)) = arg {
+        Some(v.clone())
+    } else {
+        None
+    }
+}
+
+// Bounds on the timestamps from filters that apply to all solutions of the graph pattern.
+// Any bound will do, as the buckets are only completed between the first and last bucket of each group.
+fn find_time_range(gp: &GraphPattern, timestamp: &Variable) -> (Option<Literal>, Option<Literal>) {
+    match gp {
+        GraphPattern::Filter { expr, inner } => {
+            let (mut start, mut end) = find_time_range(inner, timestamp);
+            for condition in conjuncts(expr.clone()) {
+                match time_bound(&condition, timestamp) {
+                    Some((true, l)) if start.is_none() => start = Some(l),
+                    Some((false, l)) if end.is_none() => end = Some(l),
+                    _ => {}
+                }
+            }
+            (start, end)
+        }
+        GraphPattern::Join { left, right } => {
+            let (left_start, left_end) = find_time_range(left, timestamp);
+            let (right_start, right_end) = find_time_range(right, timestamp);
+            (left_start.or(right_start), left_end.or(right_end))
+        }
+        GraphPattern::LeftJoin { left: inner, .. }
+        | GraphPattern::Extend { inner, .. }
+        | GraphPattern::Graph { inner, .. }
+        | GraphPattern::OrderBy { inner, .. }
+        | GraphPattern::Project { inner, .. }
+        | GraphPattern::Distinct { inner }
+        | GraphPattern::Reduced { inner }
+        | GraphPattern::Slice { inner, .. } => find_time_range(inner, timestamp),
+        _ => (None, None),
+    }
+}
+
+// Whether the condition is a lower (true) or an upper (false) bound of the timestamp
+fn time_bound(condition: &Expression, timestamp: &Variable) -> Option<(bool, Literal)> {
+    let (lower, left, right) = match condition {
+        Expression::Greater(left, right) | Expression::GreaterOrEqual(left, right) => {
+            (true, left, right)
+        }
+        Expression::Less(left, right) | Expression::LessOrEqual(left, right) => {
+            (false, left, right)
+        }
+        _ => return None,
+    };
+    match (left.as_ref(), right.as_ref()) {
+        (Expression::Variable(v), Expression::Literal(l)) if v == timestamp => {
+            is_date_time(l).then(|| (lower, l.clone()))
+        }
+        (Expression::Literal(l), Expression::Variable(v)) if v == timestamp => {
+            is_date_time(l).then(|| (!lower, l.clone()))
+        }
+        _ => None,
+    }
+}
+
+fn is_date_time(l: &Literal) -> bool {
+    l.datatype() == xsd::DATE_TIME
+}
+
+// Adds the missing buckets between the first and last bucket of each partition and fills their values
+pub(crate) fn gap_fill(
+    mappings: LazyFrame,
+    partition_by: &[String],
+    gap_fill: &GapFill,
+) -> LazyFrame {
+    let bucket = gap_fill.bucket_variable.as_str();
+    let bounds = [
+        col(bucket).min().alias(GAP_FILL_START),
+        col(bucket).max().alias(GAP_FILL_END),
+    ];
+    let grid = if partition_by.is_empty() {
+        mappings.clone().select(bounds)
+    } else {
+        mappings
+            .clone()
+            .group_by(partition_by.iter().map(col).collect::<Vec<_>>())
+            .agg(bounds)
+    };
+    let interval = PolarsDuration::parse(&format!("{}us", gap_fill.bucket_width.as_micros()));
+    let grid = grid
+        .with_column(
+            datetime_ranges(
+                col(GAP_FILL_START),
+                col(GAP_FILL_END),
+                interval,
+                ClosedWindow::Both,
+                None,
+                None,
+            )
+            .alias(bucket),
+        )
+        .explode([bucket])
+        .drop([GAP_FILL_START, GAP_FILL_END]);
+
+    let mut on: Vec<Expr> = partition_by.iter().map(col).collect();
+    on.push(col(bucket));
+    let mut filled = grid
+        .join(mappings, &on, &on, JoinArgs::new(JoinType::Left))
+        .sort_by_exprs(&on, SortMultipleOptions::new().with_order_descending(false));
+    let partition: Vec<Expr> = partition_by.iter().map(col).collect();
+    for (v, method) in &gap_fill.methods {
+        let fill = match method {
+            GapFillMethod::Null => continue,
+            GapFillMethod::Locf => {
+                col(v.as_str()).fill_null_with_strategy(FillNullStrategy::Forward(None))
+            }
+            GapFillMethod::Linear => col(v.as_str()).interpolate(InterpolationMethod::Linear),
+        };
+        let fill = if partition.is_empty() {
+            fill
+        } else {
+            fill.over(&partition)
+        };
+        filled = filled.with_column(fill.alias(v.as_str()));
+    }
+    filled
+}
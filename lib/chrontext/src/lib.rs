@@ -1,12 +1,21 @@
+mod aggregates;
+pub mod as_of;
 pub mod change_types;
 pub mod combiner;
 pub mod constants;
 pub mod constraints;
+pub mod construct;
 pub mod engine;
 pub mod errors;
+mod filter_rewriting;
+mod gap_fill;
+mod partial_aggregation;
+pub mod prepared;
 mod preparing;
 pub mod preprocessing;
 mod rename_vars;
 pub mod rewriting;
 mod sparql_result_to_polars;
 pub mod splitter;
+pub mod top_k;
+mod window;
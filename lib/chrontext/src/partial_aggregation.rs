@@ -0,0 +1,182 @@
+use crate::constants::PARTIAL_AGGREGATION_COL;
+use oxrdf::vocab::xsd;
+use oxrdf::Variable;
+use polars::prelude::{col, DataType, Expr};
+use query_processing::find_query_variables::find_all_used_variables_in_expression;
+use representation::query_context::Context;
+use representation::solution_mapping::SolutionMappings;
+use representation::RDFNodeType;
+use spargebra::algebra::{AggregateExpression, AggregateFunction, Expression};
+use std::collections::HashSet;
+use virtualized_query::VirtualizedQuery;
+
+// Independent series in a group are combined as a cross product, so each row of one series is repeated once for each row of the other series
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PartialAggregation {
+    // Minimums and maximums are not changed by the repetition
+    Unchanged(Variable),
+    // Sums and counts are multiplied by the number of rows of the other series
+    Repeated(Variable, Vec<Variable>),
+    // The number of solutions is the product of the number of rows of each series
+    Product(Vec<Variable>),
+    // The repetition cancels out in averages, which are computed from a sum and a count
+    Mean(Variable, Variable),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct PartialAggregations {
+    pub(crate) aggregations: Vec<(Variable, PartialAggregation)>,
+    pub(crate) partial_variables: Vec<Variable>,
+}
+
+// Finds the aggregations to push down for each series, or None if some aggregation cannot be computed from them
+pub(crate) fn decompose_aggregations(
+    vqs: &[VirtualizedQuery],
+    context: &Context,
+    aggregations: &[(Variable, AggregateExpression)],
+) -> Option<(
+    Vec<Vec<(Variable, AggregateExpression)>>,
+    PartialAggregations,
+)> {
+    let mut rows = vec![];
+    let mut vq_aggregations = vec![];
+    for (i, vq) in vqs.iter().enumerate() {
+        let timestamp = vq.get_timestamp_variables().into_iter().next()?;
+        let rows_variable = partial_variable(&format!("rows_{i}"));
+        vq_aggregations.push(vec![(
+            rows_variable.clone(),
+            AggregateExpression::FunctionCall {
+                name: AggregateFunction::Count,
+                expr: Expression::Variable(timestamp),
+                distinct: false,
+            },
+        )]);
+        rows.push(rows_variable);
+    }
+    let mut partial_variables = rows.clone();
+    let mut partial_aggregations = vec![];
+    for (v, ae) in aggregations {
+        let (name, expr, distinct) = match ae {
+            AggregateExpression::CountSolutions { distinct: false } => {
+                partial_aggregations.push((v.clone(), PartialAggregation::Product(rows.clone())));
+                continue;
+            }
+            AggregateExpression::CountSolutions { distinct: true } => return None,
+            AggregateExpression::FunctionCall {
+                name,
+                expr,
+                distinct,
+            } => (name, expr, *distinct),
+        };
+        let mut used_vars = HashSet::new();
+        find_all_used_variables_in_expression(expr, &mut used_vars, true, true);
+        if used_vars.is_empty() {
+            return None;
+        }
+        let i = vqs.iter().position(|vq| {
+            used_vars
+                .iter()
+                .all(|u| vq.has_equivalent_variable(u, context))
+        })?;
+        let other_rows: Vec<_> = rows
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, r)| r.clone())
+            .collect();
+        let partial = partial_variable(v.as_str());
+        let partial_aggregation = match (name, distinct) {
+            (AggregateFunction::Min, _) | (AggregateFunction::Max, _) => {
+                vq_aggregations[i].push((partial.clone(), ae.clone()));
+                partial_variables.push(partial.clone());
+                PartialAggregation::Unchanged(partial)
+            }
+            (AggregateFunction::Sum, false) | (AggregateFunction::Count, false) => {
+                vq_aggregations[i].push((partial.clone(), ae.clone()));
+                partial_variables.push(partial.clone());
+                PartialAggregation::Repeated(partial, other_rows)
+            }
+            (AggregateFunction::Avg, false) => {
+                let sum = partial_variable(&format!("{}_sum", v.as_str()));
+                let count = partial_variable(&format!("{}_count", v.as_str()));
+                for (p, name) in [
+                    (&sum, AggregateFunction::Sum),
+                    (&count, AggregateFunction::Count),
+                ] {
+                    vq_aggregations[i].push((
+                        p.clone(),
+                        AggregateExpression::FunctionCall {
+                            name,
+                            expr: expr.clone(),
+                            distinct: false,
+                        },
+                    ));
+                    partial_variables.push(p.clone());
+                }
+                PartialAggregation::Mean(sum, count)
+            }
+            _ => return None,
+        };
+        partial_aggregations.push((v.clone(), partial_aggregation));
+    }
+    Some((
+        vq_aggregations,
+        PartialAggregations {
+            aggregations: partial_aggregations,
+            partial_variables,
+        },
+    ))
+}
+
+fn partial_variable(name: &str) -> Variable {
+    Variable::new_unchecked(format!("{}_{}", PARTIAL_AGGREGATION_COL, name))
+}
+
+pub(crate) fn combine_partial_aggregations(
+    mut solution_mappings: SolutionMappings,
+    partial_aggregations: PartialAggregations,
+) -> SolutionMappings {
+    let rdf_node_type_of = |solution_mappings: &SolutionMappings, v: &Variable| {
+        solution_mappings
+            .rdf_node_types
+            .get(v.as_str())
+            .cloned()
+            .unwrap_or(RDFNodeType::None)
+    };
+    let multiply = |e: Expr, vs: &[Variable]| vs.iter().fold(e, |e, v| e * col(v.as_str()));
+    let mut exprs = vec![];
+    for (v, pa) in &partial_aggregations.aggregations {
+        let (expr, rdf_node_type) = match pa {
+            PartialAggregation::Unchanged(p) => {
+                (col(p.as_str()), rdf_node_type_of(&solution_mappings, p))
+            }
+            PartialAggregation::Repeated(p, rows) => (
+                multiply(col(p.as_str()), rows),
+                rdf_node_type_of(&solution_mappings, p),
+            ),
+            PartialAggregation::Product(rows) => (
+                multiply(col(rows[0].as_str()), &rows[1..]),
+                rdf_node_type_of(&solution_mappings, &rows[0]),
+            ),
+            PartialAggregation::Mean(sum, count) => (
+                col(sum.as_str()).cast(DataType::Float64)
+                    / col(count.as_str()).cast(DataType::Float64),
+                RDFNodeType::Literal(xsd::DOUBLE.into_owned()),
+            ),
+        };
+        exprs.push(expr.alias(v.as_str()));
+        solution_mappings
+            .rdf_node_types
+            .insert(v.as_str().to_string(), rdf_node_type);
+    }
+    solution_mappings.mappings = solution_mappings.mappings.with_columns(exprs).drop(
+        partial_aggregations
+            .partial_variables
+            .iter()
+            .map(|v| col(v.as_str())),
+    );
+    for v in &partial_aggregations.partial_variables {
+        solution_mappings.rdf_node_types.remove(v.as_str());
+    }
+    solution_mappings
+}
@@ -0,0 +1,318 @@
+use crate::errors::ChrontextError;
+use crate::rename_vars::{rewrite_expression_vars, rewrite_gp_vars, VariableRewriter};
+use crate::sparql_result_to_polars::{get_projected_variables, StaticQueryDataFrameBuilder};
+use crate::splitter::QueryParseError;
+use oxrdf::{Term, Variable};
+use polars::frame::DataFrame;
+use representation::query_context::Context;
+use representation::RDFNodeType;
+use spargebra::algebra::{Expression, GraphPattern};
+use spargebra::term::{GroundTerm, NamedNodePattern, TermPattern};
+use spargebra::Query;
+use std::collections::{HashMap, HashSet};
+use virtualized_query::{AsOfSynchronizer, BasicVirtualizedQuery};
+
+// A query that has been rewritten once, so that executions only bind parameters.
+// Bound variables are substituted into the cached static queries, virtualized query filters and
+// preprocessed query so that constraints on virtualized variables can still be pushed down.
+#[derive(Clone, Debug)]
+pub struct PreparedQuery {
+    pub(crate) static_query: Query,
+    pub(crate) rewritten: Option<RewrittenQuery>,
+}
+
+// The output of the rewrite of a hybrid query, before any virtualized queries are executed
+#[derive(Clone, Debug)]
+pub(crate) struct RewrittenQuery {
+    pub(crate) rename_map: HashMap<String, String>,
+    pub(crate) as_of_synchronizers: Vec<AsOfSynchronizer>,
+    pub(crate) preprocessed_query: Query,
+    pub(crate) static_queries_map: HashMap<Context, Query>,
+    pub(crate) basic_virtualized_queries: Vec<BasicVirtualizedQuery>,
+    pub(crate) rewritten_filters: HashMap<Context, Expression>,
+}
+
+impl PreparedQuery {
+    pub fn is_hybrid(&self) -> bool {
+        self.rewritten.is_some()
+    }
+
+    pub(crate) fn bind_static_query(
+        &self,
+        bindings: &HashMap<Variable, GroundTerm>,
+    ) -> Result<Query, ChrontextError> {
+        if bindings.is_empty() {
+            return Ok(self.static_query.clone());
+        }
+        let mut binder = ParameterBinder::new(bindings);
+        let query = binder.bind_query(&self.static_query)?;
+        binder.check_none_assigned(|v| v.as_str().to_string())?;
+        binder.check_all_bound(bindings.keys())?;
+        Ok(query)
+    }
+
+    pub(crate) fn bind_rewritten_query(
+        &self,
+        rewritten: &RewrittenQuery,
+        bindings: &HashMap<Variable, GroundTerm>,
+    ) -> Result<RewrittenQuery, ChrontextError> {
+        if bindings.is_empty() {
+            return Ok(rewritten.clone());
+        }
+        //Parameters are given by their original names
+        let renamed_bindings: HashMap<_, _> = bindings
+            .iter()
+            .map(|(v, t)| (renamed_variable(v, &rewritten.rename_map), t.clone()))
+            .collect();
+        for bvq in &rewritten.basic_virtualized_queries {
+            for v in [
+                &bvq.query_source_variable,
+                &bvq.identifier_variable,
+                &bvq.resource_variable,
+            ] {
+                if renamed_bindings.contains_key(v) {
+                    return Err(ChrontextError::VirtualizedQueryParameter(
+                        original_variable_name(v, &rewritten.rename_map),
+                    ));
+                }
+            }
+        }
+        let mut binder = ParameterBinder::new(&renamed_bindings);
+        let preprocessed_query = binder.bind_query(&rewritten.preprocessed_query)?;
+        let mut static_queries_map = HashMap::new();
+        for (context, query) in &rewritten.static_queries_map {
+            static_queries_map.insert(context.clone(), binder.bind_query(query)?);
+        }
+        let mut rewritten_filters = HashMap::new();
+        for (context, expression) in &rewritten.rewritten_filters {
+            rewritten_filters.insert(
+                context.clone(),
+                rewrite_expression_vars(expression.clone(), &mut binder)?,
+            );
+        }
+        binder.check_none_assigned(|v| original_variable_name(v, &rewritten.rename_map))?;
+        binder.check_all_bound(renamed_bindings.keys())?;
+        Ok(RewrittenQuery {
+            rename_map: rewritten.rename_map.clone(),
+            as_of_synchronizers: rewritten.as_of_synchronizers.clone(),
+            preprocessed_query,
+            static_queries_map,
+            basic_virtualized_queries: rewritten.basic_virtualized_queries.clone(),
+            rewritten_filters,
+        })
+    }
+
+    // Projected parameters are removed from the bound queries, so they are added back as constants
+    pub(crate) fn add_projected_parameters(
+        &self,
+        df: DataFrame,
+        mut rdf_node_types: HashMap<String, RDFNodeType>,
+        bindings: &HashMap<Variable, GroundTerm>,
+    ) -> Result<(DataFrame, HashMap<String, RDFNodeType>), ChrontextError> {
+        let Query::Select { pattern, .. } = &self.static_query else {
+            return Err(QueryParseError::NotSelectQuery.into());
+        };
+        let projected_variables = get_projected_variables(pattern).unwrap_or_default();
+        let mut parameter_variables = vec![];
+        let mut parameter_terms = vec![];
+        for v in &projected_variables {
+            if let Some(t) = bindings.get(v) {
+                parameter_variables.push(v.clone());
+                parameter_terms.push(match t {
+                    GroundTerm::NamedNode(nn) => Term::NamedNode(nn.clone()),
+                    GroundTerm::Literal(l) => Term::Literal(l.clone()),
+                });
+            }
+        }
+        if parameter_variables.is_empty() {
+            return Ok((df, rdf_node_types));
+        }
+        let mut builder = StaticQueryDataFrameBuilder::with_variables(parameter_variables);
+        builder.push_terms(&parameter_terms.iter().map(Some).collect::<Vec<_>>());
+        let (parameters_df, parameter_types) = builder.finish()?;
+        let height = df.height();
+        let mut columns = df.take_columns();
+        for c in parameters_df.get_columns() {
+            columns.push(c.new_from_index(0, height));
+        }
+        rdf_node_types.extend(parameter_types);
+        let df = DataFrame::new(columns)
+            .and_then(|df| df.select(projected_variables.iter().map(|v| v.as_str())))
+            .map_err(ChrontextError::ProjectedParametersError)?;
+        Ok((df, rdf_node_types))
+    }
+}
+
+fn renamed_variable(v: &Variable, rename_map: &HashMap<String, String>) -> Variable {
+    if let Some(renamed) = rename_map.get(v.as_str()) {
+        Variable::new_unchecked(renamed)
+    } else {
+        v.clone()
+    }
+}
+
+fn original_variable_name(v: &Variable, rename_map: &HashMap<String, String>) -> String {
+    rename_map
+        .iter()
+        .find(|(_, renamed)| renamed.as_str() == v.as_str())
+        .map(|(original, _)| original.clone())
+        .unwrap_or_else(|| v.as_str().to_string())
+}
+
+struct ParameterBinder<'a> {
+    bindings: &'a HashMap<Variable, GroundTerm>,
+    bound: HashSet<Variable>,
+    assigned: Option<Variable>,
+}
+
+impl<'a> ParameterBinder<'a> {
+    fn new(bindings: &'a HashMap<Variable, GroundTerm>) -> ParameterBinder<'a> {
+        ParameterBinder {
+            bindings,
+            bound: HashSet::new(),
+            assigned: None,
+        }
+    }
+
+    fn bind_query(&mut self, query: &Query) -> Result<Query, ChrontextError> {
+        match query {
+            Query::Select {
+                dataset,
+                pattern,
+                base_iri,
+            } => Ok(Query::Select {
+                dataset: dataset.clone(),
+                pattern: rewrite_gp_vars(pattern.clone(), self)?,
+                base_iri: base_iri.clone(),
+            }),
+            _ => Err(QueryParseError::NotSelectQuery.into()),
+        }
+    }
+
+    fn check_all_bound<'b>(
+        &self,
+        mut parameters: impl Iterator<Item = &'b Variable>,
+    ) -> Result<(), ChrontextError> {
+        if let Some(v) = parameters.find(|v| !self.bound.contains(*v)) {
+            Err(ChrontextError::UnknownQueryParameter(
+                v.as_str().to_string(),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    // Variables assigned by BIND or by aggregates are computed by the query, so they can not be bound
+    fn check_none_assigned(
+        &self,
+        original_name: impl Fn(&Variable) -> String,
+    ) -> Result<(), ChrontextError> {
+        if let Some(v) = &self.assigned {
+            Err(ChrontextError::AssignedQueryParameter(original_name(v)))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn get_binding(&mut self, v: &Variable) -> Option<&'a GroundTerm> {
+        let t = self.bindings.get(v);
+        if t.is_some() {
+            self.bound.insert(v.clone());
+        }
+        t
+    }
+}
+
+impl VariableRewriter for ParameterBinder<'_> {
+    type Error = ChrontextError;
+
+    //Only reached for the variables assigned by Extend and Group
+    fn rewrite_variable(&mut self, v: Variable) -> Variable {
+        if self.assigned.is_none() && self.bindings.contains_key(&v) {
+            self.assigned = Some(v.clone());
+        }
+        v
+    }
+
+    fn rewrite_expression_variable(&mut self, v: Variable) -> Expression {
+        match self.get_binding(&v) {
+            Some(GroundTerm::NamedNode(nn)) => Expression::NamedNode(nn.clone()),
+            Some(GroundTerm::Literal(l)) => Expression::Literal(l.clone()),
+            None => Expression::Variable(v),
+        }
+    }
+
+    fn rewrite_bound(&mut self, v: Variable) -> Expression {
+        if self.get_binding(&v).is_some() {
+            Expression::Literal(true.into())
+        } else {
+            Expression::Bound(v)
+        }
+    }
+
+    fn rewrite_term_pattern_variable(&mut self, v: Variable) -> TermPattern {
+        match self.get_binding(&v) {
+            Some(GroundTerm::NamedNode(nn)) => TermPattern::NamedNode(nn.clone()),
+            Some(GroundTerm::Literal(l)) => TermPattern::Literal(l.clone()),
+            None => TermPattern::Variable(v),
+        }
+    }
+
+    fn rewrite_named_node_pattern_variable(
+        &mut self,
+        v: Variable,
+    ) -> Result<NamedNodePattern, ChrontextError> {
+        match self.get_binding(&v) {
+            Some(GroundTerm::NamedNode(nn)) => Ok(NamedNodePattern::NamedNode(nn.clone())),
+            Some(GroundTerm::Literal(_)) => Err(ChrontextError::InvalidQueryParameter(
+                v.as_str().to_string(),
+            )),
+            None => Ok(NamedNodePattern::Variable(v)),
+        }
+    }
+
+    // Bound variables are constant, so they are dropped from projections and groupings
+    fn rewrite_variables(&mut self, variables: Vec<Variable>) -> Vec<Variable> {
+        variables
+            .into_iter()
+            .filter(|v| self.get_binding(v).is_none())
+            .collect()
+    }
+
+    fn rewrite_values(
+        &mut self,
+        variables: Vec<Variable>,
+        bindings: Vec<Vec<Option<GroundTerm>>>,
+    ) -> GraphPattern {
+        let mut keep = vec![];
+        let mut new_variables = vec![];
+        for (i, v) in variables.into_iter().enumerate() {
+            if let Some(t) = self.get_binding(&v) {
+                keep.push((i, Some(t)));
+            } else {
+                keep.push((i, None));
+                new_variables.push(v);
+            }
+        }
+        let mut new_bindings = vec![];
+        'rows: for row in bindings {
+            let mut new_row = vec![];
+            for (i, t) in &keep {
+                if let Some(t) = t {
+                    if let Some(value) = &row[*i] {
+                        if &value != t {
+                            continue 'rows;
+                        }
+                    }
+                } else {
+                    new_row.push(row[*i].clone());
+                }
+            }
+            new_bindings.push(new_row);
+        }
+        GraphPattern::Values {
+            variables: new_variables,
+            bindings: new_bindings,
+        }
+    }
+}
@@ -3,6 +3,7 @@ pub(crate) mod graph_patterns;
 mod synchronization;
 
 use crate::combiner::CombinerError;
+use crate::partial_aggregation::PartialAggregations;
 use polars::prelude::DataType;
 use representation::query_context::Context;
 use representation::solution_mapping::SolutionMappings;
@@ -12,7 +13,7 @@ use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use virtualization::Virtualization;
 use virtualized_query::pushdown_setting::PushdownSetting;
-use virtualized_query::{BasicVirtualizedQuery, VirtualizedQuery};
+use virtualized_query::{AsOfSynchronizer, BasicVirtualizedQuery, VirtualizedQuery};
 
 #[derive(Debug)]
 pub struct TimeseriesQueryPrepper {
@@ -20,6 +21,13 @@ pub struct TimeseriesQueryPrepper {
     pub(crate) basic_virtualized_queries: Vec<BasicVirtualizedQuery>,
     grouping_counter: u16,
     rewritten_filters: HashMap<Context, Expression>,
+    pub(crate) as_of_synchronizers: Vec<AsOfSynchronizer>,
+    // Group by pushdowns over several series, by the context of the group
+    pub(crate) partial_aggregations: HashMap<Context, PartialAggregations>,
+    // HAVING filters, by the context of the group they filter
+    pub(crate) having_filters: HashMap<Context, Expression>,
+    // Windows computed by chrontext, by the context of the graph pattern they are computed over
+    unpushed_windows: HashSet<Context>,
     virtualization: Arc<Virtualization>,
 }
 
@@ -28,6 +36,7 @@ impl TimeseriesQueryPrepper {
         pushdown_settings: HashSet<PushdownSetting>,
         basic_virtualized_queries: Vec<BasicVirtualizedQuery>,
         rewritten_filters: HashMap<Context, Expression>,
+        as_of_synchronizers: Vec<AsOfSynchronizer>,
         virtualization: Arc<Virtualization>,
     ) -> TimeseriesQueryPrepper {
         TimeseriesQueryPrepper {
@@ -35,10 +44,29 @@ impl TimeseriesQueryPrepper {
             basic_virtualized_queries,
             grouping_counter: 0,
             rewritten_filters,
+            as_of_synchronizers,
+            partial_aggregations: HashMap::new(),
+            having_filters: HashMap::new(),
+            unpushed_windows: HashSet::new(),
             virtualization,
         }
     }
 
+    // The right side of an as-of synchronizer must not be restricted by conditions before it is matched
+    pub(crate) fn is_as_of_right_side(&self, vq: &VirtualizedQuery) -> bool {
+        let columns = vq.expected_columns();
+        self.as_of_synchronizers
+            .iter()
+            .any(|s| columns.contains(s.right.as_str()) && !columns.contains(s.left.as_str()))
+    }
+
+    // Conditions above a window computed by chrontext must not restrict the series below it
+    pub(crate) fn is_below_unpushed_window(&self, vq_context: &Context, context: &Context) -> bool {
+        self.unpushed_windows
+            .iter()
+            .any(|w| w.path.len() > context.path.len() && vq_context.path.starts_with(&w.path))
+    }
+
     pub fn prepare(
         &mut self,
         query: &Query,
@@ -1,10 +1,13 @@
 use super::TimeseriesQueryPrepper;
 use crate::combiner::CombinerError;
 use crate::preparing::graph_patterns::GPPrepReturn;
-use crate::preparing::synchronization::create_identity_synchronized_queries;
+use crate::preparing::synchronization::{
+    create_as_of_synchronized_queries, create_identity_synchronized_queries,
+};
 use representation::query_context::{Context, PathEntry};
 use spargebra::term::TriplePattern;
 use std::collections::HashMap;
+use virtualized_query::pushdown_setting::PushdownSetting;
 use virtualized_query::VirtualizedQuery;
 
 impl TimeseriesQueryPrepper {
@@ -31,6 +34,9 @@ impl TimeseriesQueryPrepper {
         }
         if try_groupby_complex_query {
             local_vqs = create_identity_synchronized_queries(local_vqs);
+            if self.pushdown_settings.contains(&PushdownSetting::AsOfJoin) {
+                local_vqs = create_as_of_synchronized_queries(local_vqs, &self.as_of_synchronizers);
+            }
         }
         let mut vqs_map = HashMap::new();
         if !local_vqs.is_empty() {
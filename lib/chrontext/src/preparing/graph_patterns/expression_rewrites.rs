@@ -1,7 +1,9 @@
 use crate::change_types::ChangeType;
+use crate::constants::FLOOR_DATE_TIME_TO_SECONDS_INTERVAL;
+use oxrdf::vocab::xsd;
 use oxrdf::Literal;
 use representation::query_context::{Context, PathEntry};
-use spargebra::algebra::{Expression, OrderExpression};
+use spargebra::algebra::{Expression, Function, OrderExpression};
 use std::collections::HashSet;
 use virtualized_query::pushdown_setting::PushdownSetting;
 use virtualized_query::VirtualizedQuery;
@@ -1000,3 +1002,48 @@ pub(crate) fn try_recursive_rewrite_expression(
 fn or_lost_value(rewrites: Vec<&RecursiveRewriteReturn>) -> bool {
     rewrites.iter().any(|b| b.lost_value)
 }
+
+// The subset of expressions that can be translated to SQL by all the virtualized databases
+pub(crate) fn translatable_expression(expr: &Expression) -> bool {
+    match expr {
+        Expression::Variable(_) | Expression::Literal(_) | Expression::Bound(_) => true,
+        Expression::Greater(l, r)
+        | Expression::Less(l, r)
+        | Expression::GreaterOrEqual(l, r)
+        | Expression::LessOrEqual(l, r)
+        | Expression::Equal(l, r)
+        | Expression::And(l, r)
+        | Expression::Or(l, r)
+        | Expression::Multiply(l, r)
+        | Expression::Divide(l, r)
+        | Expression::Add(l, r)
+        | Expression::Subtract(l, r) => translatable_expression(l) && translatable_expression(r),
+        Expression::Not(e) => translatable_expression(e),
+        Expression::If(c, t, f) => {
+            translatable_expression(c) && translatable_expression(t) && translatable_expression(f)
+        }
+        Expression::In(e, es) => {
+            translatable_expression(e) && es.iter().all(translatable_expression)
+        }
+        Expression::Coalesce(es) => es.iter().all(translatable_expression),
+        Expression::FunctionCall(f, args) => {
+            let supported = match f {
+                Function::Seconds
+                | Function::Minutes
+                | Function::Hours
+                | Function::Day
+                | Function::Month
+                | Function::Year
+                | Function::Floor
+                | Function::Ceil => true,
+                Function::Custom(iri) => {
+                    iri.as_ref() == xsd::INTEGER
+                        || iri.as_str() == FLOOR_DATE_TIME_TO_SECONDS_INTERVAL
+                }
+                _ => false,
+            };
+            supported && args.iter().all(translatable_expression)
+        }
+        _ => false,
+    }
+}
@@ -1,13 +1,16 @@
 use super::TimeseriesQueryPrepper;
 use crate::combiner::CombinerError;
+use crate::preparing::graph_patterns::expression_rewrites::translatable_expression;
 use crate::preparing::graph_patterns::GPPrepReturn;
+use crate::window::window_function;
 use oxrdf::Variable;
 use query_processing::find_query_variables::find_all_used_variables_in_expression;
 use representation::query_context::{Context, PathEntry};
 use representation::solution_mapping::SolutionMappings;
 use spargebra::algebra::{Expression, GraphPattern};
 use std::collections::HashSet;
-use virtualized_query::VirtualizedQuery;
+use virtualized_query::pushdown_setting::PushdownSetting;
+use virtualized_query::{VirtualizedQuery, Window, WindowFunction};
 
 impl TimeseriesQueryPrepper {
     pub(crate) fn prepare_extend(
@@ -26,7 +29,12 @@ impl TimeseriesQueryPrepper {
             solution_mappings,
             &inner_context,
         )?;
+        let window = window_function(expr)?;
         if try_groupby_complex_query {
+            // Windows are pushed down when the grouping is not
+            if window.is_some() {
+                return Ok(GPPrepReturn::fail_groupby_complex_query());
+            }
             let mut expression_vars = HashSet::new();
             find_all_used_variables_in_expression(expr, &mut expression_vars, true, true);
             let mut found_i = None;
@@ -82,7 +90,107 @@ impl TimeseriesQueryPrepper {
                 Ok(GPPrepReturn::fail_groupby_complex_query())
             }
         } else {
+            if let Some((function, value)) = window {
+                let pushed = self.pushdown_settings.contains(&PushdownSetting::Window)
+                    && self.push_down_window(
+                        &mut inner_prepare,
+                        var,
+                        function,
+                        &value,
+                        &inner_context,
+                        context,
+                    );
+                if pushed {
+                    self.unpushed_windows.remove(&inner_context);
+                } else {
+                    self.unpushed_windows.insert(inner_context);
+                }
+            } else if self.pushdown_settings.contains(&PushdownSetting::Extend)
+                && translatable_expression(expr)
+            {
+                push_down_extend(&mut inner_prepare, var, expr, &inner_context, context);
+            }
             Ok(inner_prepare)
         }
     }
+
+    // The window is only pushed down when the series is the only virtualized query directly below, as rows removed later would change it
+    fn push_down_window(
+        &self,
+        inner_prepare: &mut GPPrepReturn,
+        var: &Variable,
+        function: WindowFunction,
+        value: &Variable,
+        inner_context: &Context,
+        context: &Context,
+    ) -> bool {
+        let Some(vqs) = inner_prepare.virtualized_queries.get(inner_context) else {
+            return false;
+        };
+        if vqs.len() != 1 || self.is_as_of_right_side(&vqs[0]) {
+            return false;
+        }
+        let Some((partition_by, timestamp)) = vqs[0].get_series_of(value) else {
+            return false;
+        };
+        let window = Window {
+            function,
+            value: value.clone(),
+            timestamp: timestamp.clone(),
+            partition_by: partition_by.clone(),
+        };
+        let vq = inner_prepare
+            .virtualized_queries
+            .remove(inner_context)
+            .unwrap()
+            .remove(0);
+        inner_prepare
+            .virtualized_queries
+            .entry(context.clone())
+            .or_default()
+            .push(VirtualizedQuery::Windowed(
+                Box::new(vq),
+                var.clone(),
+                window,
+            ));
+        true
+    }
+}
+
+// The expression is computed by the virtualized query directly below that has all the variables it uses
+fn push_down_extend(
+    inner_prepare: &mut GPPrepReturn,
+    var: &Variable,
+    expr: &Expression,
+    inner_context: &Context,
+    context: &Context,
+) {
+    let mut used_vars = HashSet::new();
+    find_all_used_variables_in_expression(expr, &mut used_vars, true, true);
+    if used_vars.is_empty() {
+        return;
+    }
+    let Some(vqs) = inner_prepare.virtualized_queries.get_mut(inner_context) else {
+        return;
+    };
+    let Some(i) = vqs.iter().position(|vq| {
+        used_vars
+            .iter()
+            .all(|v| vq.has_equivalent_variable(v, context))
+    }) else {
+        return;
+    };
+    let vq = vqs.remove(i);
+    if vqs.is_empty() {
+        inner_prepare.virtualized_queries.remove(inner_context);
+    }
+    inner_prepare
+        .virtualized_queries
+        .entry(context.clone())
+        .or_default()
+        .push(VirtualizedQuery::ExpressionAs(
+            Box::new(vq),
+            var.clone(),
+            expr.clone(),
+        ));
 }
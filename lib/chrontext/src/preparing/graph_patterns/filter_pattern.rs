@@ -44,6 +44,17 @@ impl TimeseriesQueryPrepper {
         for (inner_context, vqs) in inner_prepare.virtualized_queries {
             let mut out_vq_vec = vec![];
             for t in vqs {
+                // Filtering before the window is computed would change it
+                if self.is_as_of_right_side(&t)
+                    || self.is_below_unpushed_window(&inner_context, context)
+                {
+                    if try_groupby_complex_query {
+                        return Ok(GPPrepReturn::fail_groupby_complex_query());
+                    }
+                    lost_any = true;
+                    out_vq_vec.push(t);
+                    continue;
+                }
                 let use_change_type = if try_groupby_complex_query {
                     ChangeType::NoChange
                 } else {
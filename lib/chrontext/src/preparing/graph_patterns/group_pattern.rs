@@ -3,17 +3,24 @@ use representation::query_context::{Context, PathEntry};
 use std::collections::{HashMap, HashSet};
 
 use super::TimeseriesQueryPrepper;
+use crate::aggregates::CustomAggregate;
 use crate::combiner::CombinerError;
 use crate::constants::GROUPING_COL;
+use crate::filter_rewriting::conjuncts;
+use crate::gap_fill::{find_gap_fill, gap_fill_method, gap_fill_pushed_down, without_gap_fill};
+use crate::partial_aggregation::decompose_aggregations;
+use crate::preparing::graph_patterns::expression_rewrites::translatable_expression;
 use crate::preparing::graph_patterns::GPPrepReturn;
 use crate::preparing::grouping_col_type;
 use oxrdf::Variable;
 use polars::prelude::{
     col, DataFrameJoinOps, IntoLazy, JoinArgs, JoinType, PlSmallStr, Series, UniqueKeepStrategy,
 };
-use query_processing::find_query_variables::find_all_used_variables_in_aggregate_expression;
+use query_processing::find_query_variables::{
+    find_all_used_variables_in_aggregate_expression, find_all_used_variables_in_expression,
+};
 use representation::solution_mapping::SolutionMappings;
-use spargebra::algebra::{AggregateExpression, GraphPattern};
+use spargebra::algebra::{AggregateExpression, AggregateFunction, Expression, GraphPattern};
 use virtualized_query::pushdown_setting::PushdownSetting;
 use virtualized_query::{GroupedVirtualizedQuery, VirtualizedQuery};
 
@@ -34,81 +41,180 @@ impl TimeseriesQueryPrepper {
         if try_groupby_complex_query {
             return Ok(GPPrepReturn::fail_groupby_complex_query());
         }
+        self.partial_aggregations.remove(context);
         let inner_context = &context.extension_with(PathEntry::GroupInner);
+        let gap_fill = find_gap_fill(graph_pattern, by, aggregations)?;
         let mut try_graph_pattern_prepare =
             self.prepare_graph_pattern(graph_pattern, true, solution_mappings, inner_context)?;
         if !try_graph_pattern_prepare.fail_groupby_complex_query
             && self.pushdown_settings.contains(&PushdownSetting::GroupBy)
-            && try_graph_pattern_prepare.virtualized_queries.len() == 1
         {
-            let (_c, mut vqs) = try_graph_pattern_prepare
+            let mut vqs: Vec<_> = try_graph_pattern_prepare
                 .virtualized_queries
                 .drain()
-                .next()
-                .unwrap();
+                .flat_map(|(_, vqs)| vqs)
+                .collect();
             if vqs.len() == 1 {
                 let mut vq = vqs.remove(0);
                 let in_scope = check_aggregations_are_in_scope(&vq, inner_context, aggregations);
+                let tsfuncs = vq.get_virtualized_functions(context);
+                // The buckets must be computed in the virtualized database to fill the gaps between them
+                let buckets_in_scope = gap_fill.as_ref().is_none_or(|gf| {
+                    tsfuncs
+                        .iter()
+                        .any(|(v, _)| v.as_str() == gf.bucket_variable.as_str())
+                });
+
+                let aggregations_supported = aggregations
+                    .iter()
+                    .all(|(_, a)| custom_aggregate_supported(&vq, a));
 
-                if in_scope {
-                    let grouping_col = self.add_grouping_col(solution_mappings, by);
+                if in_scope && buckets_in_scope && aggregations_supported {
+                    let grouping_col = self
+                        .add_grouping_col(solution_mappings, by, false)?
+                        .unwrap();
                     vq = add_basic_groupby_mapping_values(vq, solution_mappings, &grouping_col);
-                    let tsfuncs = vq.get_virtualized_functions(context);
-                    let mut keep_by = vec![Variable::new_unchecked(&grouping_col)];
-                    for v in by {
-                        for (v2, _) in &tsfuncs {
-                            if v2.as_str() == v.as_str() {
-                                keep_by.push(v.clone())
-                            }
-                        }
-                    }
+                    let keep_by = keep_by(&grouping_col, by, &tsfuncs);
+                    let aggregations = if gap_fill
+                        .as_ref()
+                        .is_some_and(|gf| !gap_fill_pushed_down(&self.pushdown_settings, gf))
+                    {
+                        without_gap_fill(aggregations)
+                    } else {
+                        aggregations.clone()
+                    };
                     //TODO: For OPC UA we must ensure that mapping df is 1:1 with identities, or alternatively group on these
-                    vq = VirtualizedQuery::Grouped(GroupedVirtualizedQuery {
+                    let grouped = GroupedVirtualizedQuery {
                         context: context.clone(),
                         vq: Box::new(vq),
                         by: keep_by,
-                        aggregations: aggregations.clone(),
-                    });
+                        aggregations,
+                        gap_fill,
+                    };
+                    vq = if let Some(having) = self.having_condition(&grouped, context) {
+                        VirtualizedQuery::Filtered(
+                            Box::new(VirtualizedQuery::Grouped(grouped)),
+                            having,
+                        )
+                    } else {
+                        VirtualizedQuery::Grouped(grouped)
+                    };
                     return Ok(GPPrepReturn::new(HashMap::from([(
                         context.clone(),
                         vec![vq],
                     )])));
                 }
+            } else if vqs.len() > 1 && gap_fill.is_none() {
+                // The partial aggregates of independent series can only be combined when each group has a single static solution
+                if let Some((vq_aggregations, partial_aggregations)) =
+                    decompose_aggregations(&vqs, inner_context, aggregations)
+                {
+                    if let Some(grouping_col) =
+                        self.add_grouping_col(solution_mappings, by, true)?
+                    {
+                        let mut grouped_vqs = vec![];
+                        for (vq, aggregations) in vqs.into_iter().zip(vq_aggregations) {
+                            let keep_by =
+                                keep_by(&grouping_col, by, &vq.get_virtualized_functions(context));
+                            let vq = add_basic_groupby_mapping_values(
+                                vq,
+                                solution_mappings,
+                                &grouping_col,
+                            );
+                            grouped_vqs.push(VirtualizedQuery::Grouped(GroupedVirtualizedQuery {
+                                context: context.clone(),
+                                vq: Box::new(vq),
+                                by: keep_by,
+                                aggregations,
+                                gap_fill: None,
+                            }));
+                        }
+                        self.partial_aggregations
+                            .insert(context.clone(), partial_aggregations);
+                        return Ok(GPPrepReturn::new(HashMap::from([(
+                            context.clone(),
+                            grouped_vqs,
+                        )])));
+                    }
+                }
             }
         }
         debug!("Group by pushdown failed at context {:?}", context);
         self.prepare_graph_pattern(graph_pattern, false, solution_mappings, inner_context)
     }
 
+    // The conjuncts of the HAVING filter that only use aggregates and grouping variables computed by the virtualized database
+    fn having_condition(
+        &self,
+        grouped: &GroupedVirtualizedQuery,
+        context: &Context,
+    ) -> Option<Expression> {
+        if !self.pushdown_settings.contains(&PushdownSetting::Having) || grouped.gap_fill.is_some()
+        {
+            return None;
+        }
+        let having = self.having_filters.get(context)?;
+        let computed: HashSet<_> = grouped
+            .by
+            .iter()
+            .chain(grouped.aggregations.iter().map(|(v, _)| v))
+            .filter(|v| !v.as_str().starts_with(GROUPING_COL))
+            .collect();
+        conjuncts(having.clone())
+            .into_iter()
+            .filter(|e| {
+                let mut used_vars = HashSet::new();
+                find_all_used_variables_in_expression(e, &mut used_vars, true, true);
+                !used_vars.is_empty()
+                    && used_vars.iter().all(|v| computed.contains(v))
+                    && translatable_expression(e)
+            })
+            .reduce(|left, right| Expression::And(Box::new(left), Box::new(right)))
+    }
+
+    // When one solution per group is required and there are several, the solution mappings are left unchanged
     fn add_grouping_col(
         &mut self,
         solution_mappings: &mut SolutionMappings,
         by: &[Variable],
-    ) -> String {
-        let grouping_col = format!("{}_{}", GROUPING_COL, self.grouping_counter);
-        self.grouping_counter += 1;
+        one_solution_per_group: bool,
+    ) -> Result<Option<String>, CombinerError> {
         let by_names: Vec<String> = by
             .iter()
             .filter(|x| solution_mappings.rdf_node_types.contains_key(x.as_str()))
             .map(|x| x.as_str().to_string())
             .collect();
-        solution_mappings.mappings = solution_mappings.mappings.clone().collect().unwrap().lazy();
-        let mut df = solution_mappings
+        let mappings = solution_mappings
             .mappings
             .clone()
+            .collect()
+            .map_err(CombinerError::GroupingError)?;
+        let mut df = mappings
+            .clone()
+            .lazy()
             .select(by_names.iter().map(col).collect::<Vec<_>>())
             .unique(None, UniqueKeepStrategy::First)
             .collect()
-            .unwrap();
+            .map_err(CombinerError::GroupingError)?;
+        if one_solution_per_group {
+            let n_groups = if by_names.is_empty() {
+                mappings.height().min(1)
+            } else {
+                df.height()
+            };
+            if n_groups != mappings.height() {
+                solution_mappings.mappings = mappings.lazy();
+                return Ok(None);
+            }
+        }
+        let grouping_col = format!("{}_{}", GROUPING_COL, self.grouping_counter);
+        self.grouping_counter += 1;
         let mut series = Series::from_iter(0..(df.height() as i64));
         series.rename(PlSmallStr::from_str(&grouping_col));
         assert_eq!(series.dtype(), &grouping_col_type());
-        df.with_column(series).unwrap();
-        solution_mappings.mappings = solution_mappings
-            .mappings
-            .clone()
-            .collect()
-            .unwrap()
+        df.with_column(series)
+            .map_err(CombinerError::GroupingError)?;
+        solution_mappings.mappings = mappings
             .join(
                 &df,
                 by_names.as_slice(),
@@ -116,12 +222,29 @@ impl TimeseriesQueryPrepper {
                 JoinArgs::new(JoinType::Inner),
                 None,
             )
-            .unwrap()
+            .map_err(CombinerError::GroupingError)?
             .lazy();
-        grouping_col
+        Ok(Some(grouping_col))
     }
 }
 
+// The grouping column and the grouping variables computed by the virtualized database
+fn keep_by(
+    grouping_col: &str,
+    by: &[Variable],
+    tsfuncs: &[(&Variable, &Expression)],
+) -> Vec<Variable> {
+    let mut keep_by = vec![Variable::new_unchecked(grouping_col)];
+    for v in by {
+        for (v2, _) in tsfuncs {
+            if v2.as_str() == v.as_str() {
+                keep_by.push(v.clone())
+            }
+        }
+    }
+    keep_by
+}
+
 fn check_aggregations_are_in_scope(
     vq: &VirtualizedQuery,
     context: &Context,
@@ -208,8 +331,39 @@ fn add_basic_groupby_mapping_values(
             offset,
             limit,
         ),
+        VirtualizedQuery::Windowed(vq, v, window) => VirtualizedQuery::Windowed(
+            Box::new(add_basic_groupby_mapping_values(
+                *vq,
+                solution_mappings,
+                grouping_col,
+            )),
+            v,
+            window,
+        ),
         VirtualizedQuery::Grouped(_) => {
             panic!("Should never happen")
         }
     }
 }
+
+// Custom aggregates are only pushed down when the virtualized database can be expected to know them
+fn custom_aggregate_supported(vq: &VirtualizedQuery, aggregate: &AggregateExpression) -> bool {
+    let AggregateExpression::FunctionCall {
+        name: AggregateFunction::Custom(name),
+        expr,
+        ..
+    } = aggregate
+    else {
+        return true;
+    };
+    if gap_fill_method(aggregate).is_some() {
+        return true;
+    }
+    match CustomAggregate::from_iri(name) {
+        Some(CustomAggregate::First) | Some(CustomAggregate::Last) => {
+            vq.get_timestamp_variable_of(expr).is_some()
+        }
+        Some(_) => true,
+        None => false,
+    }
+}
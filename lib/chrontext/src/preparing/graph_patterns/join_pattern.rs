@@ -1,9 +1,13 @@
 use super::TimeseriesQueryPrepper;
 use crate::combiner::CombinerError;
 use crate::preparing::graph_patterns::GPPrepReturn;
+use crate::preparing::synchronization::{
+    create_as_of_synchronized_queries, create_identity_synchronized_queries,
+};
 use representation::query_context::{Context, PathEntry};
 use representation::solution_mapping::SolutionMappings;
 use spargebra::algebra::GraphPattern;
+use virtualized_query::pushdown_setting::PushdownSetting;
 
 impl TimeseriesQueryPrepper {
     pub fn prepare_join(
@@ -36,8 +40,19 @@ impl TimeseriesQueryPrepper {
 
         left_prepare.with_virtualized_queries_from(right_prepare);
         if try_groupby_complex_query && left_prepare.virtualized_queries.len() > 1 {
-            return Ok(GPPrepReturn::fail_groupby_complex_query());
-            //TODO: Fix synchronized queries
+            // Series on both sides with the same timestamps are joined in the virtualized database
+            let vqs = left_prepare
+                .virtualized_queries
+                .drain()
+                .flat_map(|(_, vqs)| vqs)
+                .collect();
+            let mut vqs = create_identity_synchronized_queries(vqs);
+            if self.pushdown_settings.contains(&PushdownSetting::AsOfJoin) {
+                vqs = create_as_of_synchronized_queries(vqs, &self.as_of_synchronizers);
+            }
+            left_prepare
+                .virtualized_queries
+                .insert(context.clone(), vqs);
         }
         Ok(left_prepare)
     }
@@ -1,6 +1,6 @@
 use oxrdf::Variable;
 use std::collections::HashSet;
-use virtualized_query::{Synchronizer, VirtualizedQuery};
+use virtualized_query::{AsOfStrategy, AsOfSynchronizer, Synchronizer, VirtualizedQuery};
 
 pub fn create_identity_synchronized_queries(
     mut vqs: Vec<VirtualizedQuery>,
@@ -52,3 +52,45 @@ pub fn create_identity_synchronized_queries(
     out_queries.extend(vqs);
     out_queries
 }
+
+// Series linked by an as-of synchronizer are joined in the virtualized database
+pub fn create_as_of_synchronized_queries(
+    mut vqs: Vec<VirtualizedQuery>,
+    synchronizers: &[AsOfSynchronizer],
+) -> Vec<VirtualizedQuery> {
+    for s in synchronizers {
+        if s.strategy == AsOfStrategy::Nearest {
+            continue;
+        }
+        let find = |vqs: &Vec<VirtualizedQuery>, v: &Variable| {
+            vqs.iter()
+                .position(|vq| vq.expected_columns().contains(v.as_str()))
+        };
+        let (Some(left_i), Some(right_i)) = (find(&vqs, &s.left), find(&vqs, &s.right)) else {
+            continue;
+        };
+        if left_i == right_i {
+            continue;
+        }
+        let (first, second) = if left_i < right_i {
+            (left_i, right_i)
+        } else {
+            (right_i, left_i)
+        };
+        let second_vq = vqs.remove(second);
+        let first_vq = vqs.remove(first);
+        let mut queries = vec![];
+        let mut synchronizers = vec![];
+        for vq in [first_vq, second_vq] {
+            if let VirtualizedQuery::InnerJoin(inner_vqs, inner_synchronizers) = vq {
+                queries.extend(inner_vqs);
+                synchronizers.extend(inner_synchronizers);
+            } else {
+                queries.push(vq);
+            }
+        }
+        synchronizers.push(Synchronizer::AsOf(s.clone()));
+        vqs.push(VirtualizedQuery::InnerJoin(queries, synchronizers));
+    }
+    vqs
+}
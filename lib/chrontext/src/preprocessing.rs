@@ -13,6 +13,7 @@ use std::collections::{HashMap, HashSet};
 
 pub struct Preprocessor {
     counter: u16,
+    found_virtualized_iris: bool,
     blank_node_rename: HashMap<BlankNode, Variable>,
     variable_constraints: VariableConstraints,
     virtual_predicate_iris: HashSet<NamedNode>,
@@ -26,6 +27,7 @@ impl Preprocessor {
     ) -> Preprocessor {
         Preprocessor {
             counter: 0,
+            found_virtualized_iris: false,
             blank_node_rename: Default::default(),
             variable_constraints: VariableConstraints::new(),
             virtual_predicate_iris,
@@ -53,6 +55,10 @@ impl Preprocessor {
         }
     }
 
+    pub fn found_virtualized_iris(&self) -> bool {
+        self.found_virtualized_iris
+    }
+
     fn preprocess_graph_pattern(
         &mut self,
         graph_pattern: &GraphPattern,
@@ -345,6 +351,9 @@ impl Preprocessor {
         let new_subject = self.rename_if_blank(&triple_pattern.subject);
         let new_object = self.rename_if_blank(&triple_pattern.object);
         if let NamedNodePattern::NamedNode(named_predicate_node) = &triple_pattern.predicate {
+            if self.virtual_predicate_iris.contains(named_predicate_node) {
+                self.found_virtualized_iris = true;
+            }
             if let (
                 TermPattern::Variable(new_subject_variable),
                 TermPattern::Variable(new_object_variable),
@@ -435,6 +444,161 @@ impl Preprocessor {
                     self.preprocess_expression(right, &context.extension_with(PathEntry::IfRight)),
                 ),
             ),
+            Expression::Equal(left, right) => Expression::Equal(
+                Box::new(
+                    self.preprocess_expression(left, &context.extension_with(PathEntry::EqualLeft)),
+                ),
+                Box::new(
+                    self.preprocess_expression(
+                        right,
+                        &context.extension_with(PathEntry::EqualRight),
+                    ),
+                ),
+            ),
+            Expression::SameTerm(left, right) => {
+                Expression::SameTerm(
+                    Box::new(self.preprocess_expression(
+                        left,
+                        &context.extension_with(PathEntry::SameTermLeft),
+                    )),
+                    Box::new(self.preprocess_expression(
+                        right,
+                        &context.extension_with(PathEntry::SameTermRight),
+                    )),
+                )
+            }
+            Expression::Greater(left, right) => {
+                Expression::Greater(
+                    Box::new(self.preprocess_expression(
+                        left,
+                        &context.extension_with(PathEntry::GreaterLeft),
+                    )),
+                    Box::new(self.preprocess_expression(
+                        right,
+                        &context.extension_with(PathEntry::GreaterRight),
+                    )),
+                )
+            }
+            Expression::GreaterOrEqual(left, right) => Expression::GreaterOrEqual(
+                Box::new(self.preprocess_expression(
+                    left,
+                    &context.extension_with(PathEntry::GreaterOrEqualLeft),
+                )),
+                Box::new(self.preprocess_expression(
+                    right,
+                    &context.extension_with(PathEntry::GreaterOrEqualRight),
+                )),
+            ),
+            Expression::Less(left, right) => Expression::Less(
+                Box::new(
+                    self.preprocess_expression(left, &context.extension_with(PathEntry::LessLeft)),
+                ),
+                Box::new(
+                    self.preprocess_expression(
+                        right,
+                        &context.extension_with(PathEntry::LessRight),
+                    ),
+                ),
+            ),
+            Expression::LessOrEqual(left, right) => Expression::LessOrEqual(
+                Box::new(self.preprocess_expression(
+                    left,
+                    &context.extension_with(PathEntry::LessOrEqualLeft),
+                )),
+                Box::new(self.preprocess_expression(
+                    right,
+                    &context.extension_with(PathEntry::LessOrEqualRight),
+                )),
+            ),
+            Expression::Add(left, right) => Expression::Add(
+                Box::new(
+                    self.preprocess_expression(left, &context.extension_with(PathEntry::AddLeft)),
+                ),
+                Box::new(
+                    self.preprocess_expression(right, &context.extension_with(PathEntry::AddRight)),
+                ),
+            ),
+            Expression::Subtract(left, right) => {
+                Expression::Subtract(
+                    Box::new(self.preprocess_expression(
+                        left,
+                        &context.extension_with(PathEntry::SubtractLeft),
+                    )),
+                    Box::new(self.preprocess_expression(
+                        right,
+                        &context.extension_with(PathEntry::SubtractRight),
+                    )),
+                )
+            }
+            Expression::Multiply(left, right) => {
+                Expression::Multiply(
+                    Box::new(self.preprocess_expression(
+                        left,
+                        &context.extension_with(PathEntry::MultiplyLeft),
+                    )),
+                    Box::new(self.preprocess_expression(
+                        right,
+                        &context.extension_with(PathEntry::MultiplyRight),
+                    )),
+                )
+            }
+            Expression::Divide(left, right) => {
+                Expression::Divide(
+                    Box::new(self.preprocess_expression(
+                        left,
+                        &context.extension_with(PathEntry::DivideLeft),
+                    )),
+                    Box::new(self.preprocess_expression(
+                        right,
+                        &context.extension_with(PathEntry::DivideRight),
+                    )),
+                )
+            }
+            Expression::In(left, right) => Expression::In(
+                Box::new(
+                    self.preprocess_expression(left, &context.extension_with(PathEntry::InLeft)),
+                ),
+                right
+                    .iter()
+                    .enumerate()
+                    .map(|(i, e)| {
+                        self.preprocess_expression(
+                            e,
+                            &context.extension_with(PathEntry::InRight(i as u16)),
+                        )
+                    })
+                    .collect(),
+            ),
+            Expression::UnaryPlus(inner) => Expression::UnaryPlus(Box::new(
+                self.preprocess_expression(inner, &context.extension_with(PathEntry::UnaryPlus)),
+            )),
+            Expression::UnaryMinus(inner) => Expression::UnaryMinus(Box::new(
+                self.preprocess_expression(inner, &context.extension_with(PathEntry::UnaryMinus)),
+            )),
+            Expression::Coalesce(inner) => Expression::Coalesce(
+                inner
+                    .iter()
+                    .enumerate()
+                    .map(|(i, e)| {
+                        self.preprocess_expression(
+                            e,
+                            &context.extension_with(PathEntry::Coalesce(i as u16)),
+                        )
+                    })
+                    .collect(),
+            ),
+            Expression::FunctionCall(func, args) => Expression::FunctionCall(
+                func.clone(),
+                args.iter()
+                    .enumerate()
+                    .map(|(i, e)| {
+                        self.preprocess_expression(
+                            e,
+                            &context.extension_with(PathEntry::FunctionCall(i as u16)),
+                        )
+                    })
+                    .collect(),
+            ),
             _ => expression.clone(),
         }
     }
@@ -447,6 +611,9 @@ impl Preprocessor {
     ) -> GraphPattern {
         let new_subject = self.rename_if_blank(subject);
         let new_object = self.rename_if_blank(object);
+        if path_has_virtualized_iri(path, &self.virtual_predicate_iris) {
+            self.found_virtualized_iris = true;
+        }
         GraphPattern::Path {
             subject: new_subject,
             path: path.clone(),
@@ -502,3 +669,24 @@ impl Preprocessor {
         }
     }
 }
+
+fn path_has_virtualized_iri(
+    path: &PropertyPathExpression,
+    virtual_predicate_iris: &HashSet<NamedNode>,
+) -> bool {
+    match path {
+        PropertyPathExpression::NamedNode(nn) => virtual_predicate_iris.contains(nn),
+        PropertyPathExpression::Reverse(inner)
+        | PropertyPathExpression::ZeroOrMore(inner)
+        | PropertyPathExpression::OneOrMore(inner)
+        | PropertyPathExpression::ZeroOrOne(inner) => {
+            path_has_virtualized_iri(inner, virtual_predicate_iris)
+        }
+        PropertyPathExpression::Sequence(left, right)
+        | PropertyPathExpression::Alternative(left, right) => {
+            path_has_virtualized_iri(left, virtual_predicate_iris)
+                || path_has_virtualized_iri(right, virtual_predicate_iris)
+        }
+        PropertyPathExpression::NegatedPropertySet(_) => false,
+    }
+}
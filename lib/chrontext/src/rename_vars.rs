@@ -1,123 +1,188 @@
 use oxrdf::Variable;
 use spargebra::algebra::{AggregateExpression, Expression, GraphPattern, OrderExpression};
-use spargebra::term::{NamedNodePattern, TermPattern, TriplePattern};
+use spargebra::term::{GroundTerm, NamedNodePattern, TermPattern, TriplePattern};
 use spargebra::Query;
 use std::collections::HashMap;
+use std::convert::Infallible;
+
+// Decides what happens to the variables met when walking a query with rewrite_gp_vars.
+// By default variables are rewritten with rewrite_variable in every position.
+pub(crate) trait VariableRewriter {
+    type Error;
+
+    fn rewrite_variable(&mut self, v: Variable) -> Variable;
+
+    fn rewrite_expression_variable(&mut self, v: Variable) -> Expression {
+        Expression::Variable(self.rewrite_variable(v))
+    }
+
+    fn rewrite_bound(&mut self, v: Variable) -> Expression {
+        Expression::Bound(self.rewrite_variable(v))
+    }
+
+    fn rewrite_term_pattern_variable(&mut self, v: Variable) -> TermPattern {
+        TermPattern::Variable(self.rewrite_variable(v))
+    }
+
+    fn rewrite_named_node_pattern_variable(
+        &mut self,
+        v: Variable,
+    ) -> Result<NamedNodePattern, Self::Error> {
+        Ok(NamedNodePattern::Variable(self.rewrite_variable(v)))
+    }
+
+    // Variables of projections and groupings
+    fn rewrite_variables(&mut self, variables: Vec<Variable>) -> Vec<Variable> {
+        variables
+            .into_iter()
+            .map(|v| self.rewrite_variable(v))
+            .collect()
+    }
+
+    fn rewrite_values(
+        &mut self,
+        variables: Vec<Variable>,
+        bindings: Vec<Vec<Option<GroundTerm>>>,
+    ) -> GraphPattern {
+        GraphPattern::Values {
+            variables: self.rewrite_variables(variables),
+            bindings,
+        }
+    }
+}
+
+struct VariableRenamer<'a> {
+    rename_map: &'a mut HashMap<String, String>,
+}
+
+impl VariableRewriter for VariableRenamer<'_> {
+    type Error = Infallible;
+
+    fn rewrite_variable(&mut self, v: Variable) -> Variable {
+        maybe_rename_variable(v, self.rename_map)
+    }
+}
 
 // Purpose is to rename vars so that they can be virtualized as columns in most dbs..
 pub fn rename_query_vars(query: Query) -> (Query, HashMap<String, String>) {
     let mut map = HashMap::new();
+    let mut renamer = VariableRenamer {
+        rename_map: &mut map,
+    };
     let q = match query {
         Query::Select {
             dataset,
             pattern: q,
             base_iri,
-        } => Query::Select {
-            pattern: rename_gp_vars(q, &mut map),
-            dataset,
-            base_iri,
-        },
+        } => {
+            let pattern = match rewrite_gp_vars(q, &mut renamer) {
+                Ok(pattern) => pattern,
+                Err(e) => match e {},
+            };
+            Query::Select {
+                pattern,
+                dataset,
+                base_iri,
+            }
+        }
         _ => unimplemented!("Not supported by chrontext"),
     };
     (q, map)
 }
 
-fn rename_gp_vars(gp: GraphPattern, rename_map: &mut HashMap<String, String>) -> GraphPattern {
-    match gp {
+pub(crate) fn rewrite_gp_vars<R: VariableRewriter>(
+    gp: GraphPattern,
+    r: &mut R,
+) -> Result<GraphPattern, R::Error> {
+    Ok(match gp {
         GraphPattern::Bgp { patterns } => GraphPattern::Bgp {
-            patterns: rename_triple_patterns_vars(patterns, rename_map),
+            patterns: rewrite_triple_patterns_vars(patterns, r)?,
         },
         GraphPattern::Path {
             subject,
             path,
             object,
         } => GraphPattern::Path {
-            subject: rename_term_pattern(subject, rename_map),
+            subject: rewrite_term_pattern(subject, r),
             path,
-            object: rename_term_pattern(object, rename_map),
+            object: rewrite_term_pattern(object, r),
         },
         GraphPattern::Join { left, right } => GraphPattern::Join {
-            left: Box::new(rename_gp_vars(*left, rename_map)),
-            right: Box::new(rename_gp_vars(*right, rename_map)),
+            left: Box::new(rewrite_gp_vars(*left, r)?),
+            right: Box::new(rewrite_gp_vars(*right, r)?),
         },
         GraphPattern::LeftJoin {
             left,
             right,
             expression,
         } => GraphPattern::LeftJoin {
-            left: Box::new(rename_gp_vars(*left, rename_map)),
-            right: Box::new(rename_gp_vars(*right, rename_map)),
-            expression: expression.map(|expression| rename_expression_vars(expression, rename_map)),
+            left: Box::new(rewrite_gp_vars(*left, r)?),
+            right: Box::new(rewrite_gp_vars(*right, r)?),
+            expression: expression
+                .map(|expression| rewrite_expression_vars(expression, r))
+                .transpose()?,
         },
         GraphPattern::Filter { expr, inner } => GraphPattern::Filter {
-            expr: rename_expression_vars(expr, rename_map),
-            inner: Box::new(rename_gp_vars(*inner, rename_map)),
+            expr: rewrite_expression_vars(expr, r)?,
+            inner: Box::new(rewrite_gp_vars(*inner, r)?),
         },
         GraphPattern::Union { left, right } => GraphPattern::Union {
-            left: Box::new(rename_gp_vars(*left, rename_map)),
-            right: Box::new(rename_gp_vars(*right, rename_map)),
+            left: Box::new(rewrite_gp_vars(*left, r)?),
+            right: Box::new(rewrite_gp_vars(*right, r)?),
+        },
+        GraphPattern::Graph { name, inner } => GraphPattern::Graph {
+            name: rewrite_named_node_pattern(name, r)?,
+            inner: Box::new(rewrite_gp_vars(*inner, r)?),
         },
-        GraphPattern::Graph { name, inner } => GraphPattern::Graph { name, inner },
         GraphPattern::Extend {
             inner,
             variable,
             expression,
         } => GraphPattern::Extend {
-            inner: Box::new(rename_gp_vars(*inner, rename_map)),
-            variable: maybe_rename_variable(variable, rename_map),
-            expression: rename_expression_vars(expression, rename_map),
+            inner: Box::new(rewrite_gp_vars(*inner, r)?),
+            variable: r.rewrite_variable(variable),
+            expression: rewrite_expression_vars(expression, r)?,
         },
         GraphPattern::Minus { left, right } => GraphPattern::Minus {
-            left: Box::new(rename_gp_vars(*left, rename_map)),
-            right: Box::new(rename_gp_vars(*right, rename_map)),
+            left: Box::new(rewrite_gp_vars(*left, r)?),
+            right: Box::new(rewrite_gp_vars(*right, r)?),
         },
         GraphPattern::Values {
             variables,
             bindings,
-        } => GraphPattern::Values {
-            variables: variables
-                .into_iter()
-                .map(|x| maybe_rename_variable(x, rename_map))
-                .collect(),
-            bindings,
-        },
+        } => r.rewrite_values(variables, bindings),
         GraphPattern::PValues { .. } => unimplemented!("Not supported by chrontext"),
         GraphPattern::OrderBy { inner, expression } => {
             let mut new_expressions = Vec::with_capacity(expression.len());
             for e in expression {
                 new_expressions.push(match e {
-                    OrderExpression::Asc(e) => {
-                        OrderExpression::Asc(rename_expression_vars(e, rename_map))
-                    }
+                    OrderExpression::Asc(e) => OrderExpression::Asc(rewrite_expression_vars(e, r)?),
                     OrderExpression::Desc(e) => {
-                        OrderExpression::Desc(rename_expression_vars(e, rename_map))
+                        OrderExpression::Desc(rewrite_expression_vars(e, r)?)
                     }
                 })
             }
             GraphPattern::OrderBy {
-                inner: Box::new(rename_gp_vars(*inner, rename_map)),
+                inner: Box::new(rewrite_gp_vars(*inner, r)?),
                 expression: new_expressions,
             }
         }
         GraphPattern::Project { inner, variables } => GraphPattern::Project {
-            inner: Box::new(rename_gp_vars(*inner, rename_map)),
-            variables: variables
-                .into_iter()
-                .map(|x| maybe_rename_variable(x, rename_map))
-                .collect(),
+            inner: Box::new(rewrite_gp_vars(*inner, r)?),
+            variables: r.rewrite_variables(variables),
         },
         GraphPattern::Distinct { inner } => GraphPattern::Distinct {
-            inner: Box::new(rename_gp_vars(*inner, rename_map)),
+            inner: Box::new(rewrite_gp_vars(*inner, r)?),
         },
         GraphPattern::Reduced { inner } => GraphPattern::Reduced {
-            inner: Box::new(rename_gp_vars(*inner, rename_map)),
+            inner: Box::new(rewrite_gp_vars(*inner, r)?),
         },
         GraphPattern::Slice {
             inner,
             start,
             length,
         } => GraphPattern::Slice {
-            inner: Box::new(rename_gp_vars(*inner, rename_map)),
+            inner: Box::new(rewrite_gp_vars(*inner, r)?),
             start,
             length,
         },
@@ -127,28 +192,24 @@ fn rename_gp_vars(gp: GraphPattern, rename_map: &mut HashMap<String, String>) ->
             aggregates,
         } => {
             let mut new_aggregates = Vec::with_capacity(aggregates.len());
-            for (mut v, mut a) in aggregates {
-                v = maybe_rename_variable(v, rename_map);
-                a = match a {
+            for (v, a) in aggregates {
+                let a = match a {
                     AggregateExpression::FunctionCall {
                         name,
                         expr,
                         distinct,
                     } => AggregateExpression::FunctionCall {
                         name,
-                        expr: rename_expression_vars(expr, rename_map),
+                        expr: rewrite_expression_vars(expr, r)?,
                         distinct,
                     },
                     a => a,
                 };
-                new_aggregates.push((v, a));
+                new_aggregates.push((r.rewrite_variable(v), a));
             }
             GraphPattern::Group {
-                inner: Box::new(rename_gp_vars(*inner, rename_map)),
-                variables: variables
-                    .into_iter()
-                    .map(|x| maybe_rename_variable(x, rename_map))
-                    .collect(),
+                inner: Box::new(rewrite_gp_vars(*inner, r)?),
+                variables: r.rewrite_variables(variables),
                 aggregates: new_aggregates,
             }
         }
@@ -157,106 +218,103 @@ fn rename_gp_vars(gp: GraphPattern, rename_map: &mut HashMap<String, String>) ->
             inner,
             silent,
         } => GraphPattern::Service {
-            name,
-            inner: Box::new(rename_gp_vars(*inner, rename_map)),
+            name: rewrite_named_node_pattern(name, r)?,
+            inner: Box::new(rewrite_gp_vars(*inner, r)?),
             silent,
         },
         GraphPattern::DT { .. } => unimplemented!("Should not reach chrontext"),
-    }
+    })
 }
 
-fn rename_expression_vars(
+pub(crate) fn rewrite_expression_vars<R: VariableRewriter>(
     expression: Expression,
-    rename_map: &mut HashMap<String, String>,
-) -> Expression {
-    match expression {
+    r: &mut R,
+) -> Result<Expression, R::Error> {
+    Ok(match expression {
         Expression::NamedNode(nn) => Expression::NamedNode(nn),
         Expression::Literal(l) => Expression::Literal(l),
-        Expression::Variable(v) => Expression::Variable(maybe_rename_variable(v, rename_map)),
+        Expression::Variable(v) => r.rewrite_expression_variable(v),
         Expression::Or(left, right) => Expression::Or(
-            Box::new(rename_expression_vars(*left, rename_map)),
-            Box::new(rename_expression_vars(*right, rename_map)),
+            Box::new(rewrite_expression_vars(*left, r)?),
+            Box::new(rewrite_expression_vars(*right, r)?),
         ),
         Expression::And(left, right) => Expression::And(
-            Box::new(rename_expression_vars(*left, rename_map)),
-            Box::new(rename_expression_vars(*right, rename_map)),
+            Box::new(rewrite_expression_vars(*left, r)?),
+            Box::new(rewrite_expression_vars(*right, r)?),
         ),
         Expression::Equal(left, right) => Expression::Equal(
-            Box::new(rename_expression_vars(*left, rename_map)),
-            Box::new(rename_expression_vars(*right, rename_map)),
+            Box::new(rewrite_expression_vars(*left, r)?),
+            Box::new(rewrite_expression_vars(*right, r)?),
         ),
         Expression::SameTerm(left, right) => Expression::SameTerm(
-            Box::new(rename_expression_vars(*left, rename_map)),
-            Box::new(rename_expression_vars(*right, rename_map)),
+            Box::new(rewrite_expression_vars(*left, r)?),
+            Box::new(rewrite_expression_vars(*right, r)?),
         ),
         Expression::Greater(left, right) => Expression::Greater(
-            Box::new(rename_expression_vars(*left, rename_map)),
-            Box::new(rename_expression_vars(*right, rename_map)),
+            Box::new(rewrite_expression_vars(*left, r)?),
+            Box::new(rewrite_expression_vars(*right, r)?),
         ),
         Expression::GreaterOrEqual(left, right) => Expression::GreaterOrEqual(
-            Box::new(rename_expression_vars(*left, rename_map)),
-            Box::new(rename_expression_vars(*right, rename_map)),
+            Box::new(rewrite_expression_vars(*left, r)?),
+            Box::new(rewrite_expression_vars(*right, r)?),
         ),
         Expression::Less(left, right) => Expression::Less(
-            Box::new(rename_expression_vars(*left, rename_map)),
-            Box::new(rename_expression_vars(*right, rename_map)),
+            Box::new(rewrite_expression_vars(*left, r)?),
+            Box::new(rewrite_expression_vars(*right, r)?),
         ),
         Expression::LessOrEqual(left, right) => Expression::LessOrEqual(
-            Box::new(rename_expression_vars(*left, rename_map)),
-            Box::new(rename_expression_vars(*right, rename_map)),
+            Box::new(rewrite_expression_vars(*left, r)?),
+            Box::new(rewrite_expression_vars(*right, r)?),
         ),
         Expression::In(left, exprs) => Expression::In(
-            Box::new(rename_expression_vars(*left, rename_map)),
-            exprs
-                .into_iter()
-                .map(|x| rename_expression_vars(x, rename_map))
-                .collect(),
+            Box::new(rewrite_expression_vars(*left, r)?),
+            rewrite_expressions_vars(exprs, r)?,
         ),
         Expression::Add(left, right) => Expression::Add(
-            Box::new(rename_expression_vars(*left, rename_map)),
-            Box::new(rename_expression_vars(*right, rename_map)),
+            Box::new(rewrite_expression_vars(*left, r)?),
+            Box::new(rewrite_expression_vars(*right, r)?),
         ),
         Expression::Subtract(left, right) => Expression::Subtract(
-            Box::new(rename_expression_vars(*left, rename_map)),
-            Box::new(rename_expression_vars(*right, rename_map)),
+            Box::new(rewrite_expression_vars(*left, r)?),
+            Box::new(rewrite_expression_vars(*right, r)?),
         ),
         Expression::Multiply(left, right) => Expression::Multiply(
-            Box::new(rename_expression_vars(*left, rename_map)),
-            Box::new(rename_expression_vars(*right, rename_map)),
+            Box::new(rewrite_expression_vars(*left, r)?),
+            Box::new(rewrite_expression_vars(*right, r)?),
         ),
         Expression::Divide(left, right) => Expression::Divide(
-            Box::new(rename_expression_vars(*left, rename_map)),
-            Box::new(rename_expression_vars(*right, rename_map)),
+            Box::new(rewrite_expression_vars(*left, r)?),
+            Box::new(rewrite_expression_vars(*right, r)?),
         ),
         Expression::UnaryPlus(expr) => {
-            Expression::UnaryPlus(Box::new(rename_expression_vars(*expr, rename_map)))
+            Expression::UnaryPlus(Box::new(rewrite_expression_vars(*expr, r)?))
         }
         Expression::UnaryMinus(expr) => {
-            Expression::UnaryMinus(Box::new(rename_expression_vars(*expr, rename_map)))
+            Expression::UnaryMinus(Box::new(rewrite_expression_vars(*expr, r)?))
         }
-        Expression::Not(expr) => {
-            Expression::Not(Box::new(rename_expression_vars(*expr, rename_map)))
-        }
-        Expression::Exists(gp) => Expression::Exists(Box::new(rename_gp_vars(*gp, rename_map))),
-        Expression::Bound(v) => Expression::Bound(maybe_rename_variable(v, rename_map)),
+        Expression::Not(expr) => Expression::Not(Box::new(rewrite_expression_vars(*expr, r)?)),
+        Expression::Exists(gp) => Expression::Exists(Box::new(rewrite_gp_vars(*gp, r)?)),
+        Expression::Bound(v) => r.rewrite_bound(v),
         Expression::If(left, middle, right) => Expression::If(
-            Box::new(rename_expression_vars(*left, rename_map)),
-            Box::new(rename_expression_vars(*middle, rename_map)),
-            Box::new(rename_expression_vars(*right, rename_map)),
-        ),
-        Expression::Coalesce(exprs) => Expression::Coalesce(
-            exprs
-                .into_iter()
-                .map(|x| rename_expression_vars(x, rename_map))
-                .collect(),
-        ),
-        Expression::FunctionCall(func, args) => Expression::FunctionCall(
-            func,
-            args.into_iter()
-                .map(|x| rename_expression_vars(x, rename_map))
-                .collect(),
+            Box::new(rewrite_expression_vars(*left, r)?),
+            Box::new(rewrite_expression_vars(*middle, r)?),
+            Box::new(rewrite_expression_vars(*right, r)?),
         ),
-    }
+        Expression::Coalesce(exprs) => Expression::Coalesce(rewrite_expressions_vars(exprs, r)?),
+        Expression::FunctionCall(func, args) => {
+            Expression::FunctionCall(func, rewrite_expressions_vars(args, r)?)
+        }
+    })
+}
+
+fn rewrite_expressions_vars<R: VariableRewriter>(
+    expressions: Vec<Expression>,
+    r: &mut R,
+) -> Result<Vec<Expression>, R::Error> {
+    expressions
+        .into_iter()
+        .map(|x| rewrite_expression_vars(x, r))
+        .collect()
 }
 
 fn maybe_rename_variable(v: Variable, rename_map: &mut HashMap<String, String>) -> Variable {
@@ -271,43 +329,38 @@ fn maybe_rename_variable(v: Variable, rename_map: &mut HashMap<String, String>)
     }
 }
 
-fn rename_triple_patterns_vars(
+fn rewrite_triple_patterns_vars<R: VariableRewriter>(
     patterns: Vec<TriplePattern>,
-    rename_map: &mut HashMap<String, String>,
-) -> Vec<TriplePattern> {
+    r: &mut R,
+) -> Result<Vec<TriplePattern>, R::Error> {
     let mut out_patterns = Vec::with_capacity(patterns.len());
     for p in patterns {
-        let subject = rename_term_pattern(p.subject, rename_map);
-        let predicate = rename_named_node_pattern(p.predicate, rename_map);
-        let object = rename_term_pattern(p.object, rename_map);
+        let subject = rewrite_term_pattern(p.subject, r);
+        let predicate = rewrite_named_node_pattern(p.predicate, r)?;
+        let object = rewrite_term_pattern(p.object, r);
         out_patterns.push(TriplePattern {
             subject,
             predicate,
             object,
         });
     }
-    out_patterns
+    Ok(out_patterns)
 }
 
-fn rename_term_pattern(
-    term_pattern: TermPattern,
-    rename_map: &mut HashMap<String, String>,
-) -> TermPattern {
+fn rewrite_term_pattern<R: VariableRewriter>(term_pattern: TermPattern, r: &mut R) -> TermPattern {
     match term_pattern {
-        TermPattern::Variable(v) => TermPattern::Variable(maybe_rename_variable(v, rename_map)),
+        TermPattern::Variable(v) => r.rewrite_term_pattern_variable(v),
         tp => tp,
     }
 }
 
-fn rename_named_node_pattern(
+fn rewrite_named_node_pattern<R: VariableRewriter>(
     named_node_pattern: NamedNodePattern,
-    rename_map: &mut HashMap<String, String>,
-) -> NamedNodePattern {
+    r: &mut R,
+) -> Result<NamedNodePattern, R::Error> {
     match named_node_pattern {
-        NamedNodePattern::Variable(v) => {
-            NamedNodePattern::Variable(maybe_rename_variable(v, rename_map))
-        }
-        nnp => nnp,
+        NamedNodePattern::Variable(v) => r.rewrite_named_node_pattern_variable(v),
+        nnp => Ok(nnp),
     }
 }
 
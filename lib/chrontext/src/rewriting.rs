@@ -9,7 +9,7 @@ use crate::constraints::{Constraint, VariableConstraints};
 use crate::rewriting::expressions::ExReturn;
 use oxrdf::NamedNode;
 use representation::query_context::Context;
-use spargebra::algebra::Expression;
+use spargebra::algebra::{Expression, QueryDataset};
 use spargebra::term::Variable;
 use spargebra::Query;
 use std::collections::{HashMap, HashSet};
@@ -23,8 +23,8 @@ pub struct StaticQueryRewriter {
     basic_virtualized_queries: Vec<BasicVirtualizedQuery>,
     first_level_virtualized_predicates: HashSet<NamedNode>,
     static_subqueries: HashMap<Context, Query>,
+    dataset: Option<QueryDataset>,
     rewritten_filters: HashMap<Context, Expression>,
-    is_hybrid: bool,
 }
 
 impl StaticQueryRewriter {
@@ -39,8 +39,8 @@ impl StaticQueryRewriter {
             first_level_virtualized_predicates,
             basic_virtualized_queries: vec![],
             static_subqueries: HashMap::new(),
+            dataset: None,
             rewritten_filters: HashMap::new(),
-            is_hybrid: true, //TODO!
         }
     }
 
@@ -52,17 +52,13 @@ impl StaticQueryRewriter {
         Vec<BasicVirtualizedQuery>,
         HashMap<Context, Expression>,
     ) {
-        if !self.is_hybrid {
-            let mut map = HashMap::new();
-            map.insert(Context::new(), query);
-            return (map, vec![], HashMap::new());
-        }
         if let Query::Select {
             dataset,
             pattern,
             base_iri,
         } = query
         {
+            self.dataset.clone_from(&dataset);
             let pattern_rewrite = self.rewrite_graph_pattern(&pattern, &Context::new());
             if let Some(p) = pattern_rewrite.graph_pattern {
                 self.static_subqueries.insert(
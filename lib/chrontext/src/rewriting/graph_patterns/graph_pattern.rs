@@ -1,6 +1,6 @@
 use super::StaticQueryRewriter;
 use crate::rewriting::graph_patterns::GPReturn;
-use representation::query_context::Context;
+use representation::query_context::{Context, PathEntry};
 use spargebra::algebra::GraphPattern;
 use spargebra::term::NamedNodePattern;
 
@@ -11,15 +11,20 @@ impl StaticQueryRewriter {
         inner: &GraphPattern,
         context: &Context,
     ) -> GPReturn {
-        let mut inner_gpr = self.rewrite_graph_pattern(inner, context);
+        let inner_context = context.extension_with(PathEntry::GraphInner);
+        let mut inner_gpr = self.rewrite_graph_pattern(inner, &inner_context);
         if !inner_gpr.is_subquery {
             let inner_rewrite = inner_gpr.graph_pattern.take().unwrap();
+            if let NamedNodePattern::Variable(v) = name {
+                inner_gpr.variables_in_scope.insert(v.clone());
+            }
             inner_gpr.with_graph_pattern(GraphPattern::Graph {
                 name: name.clone(),
                 inner: Box::new(inner_rewrite),
             });
             return inner_gpr;
         }
-        unimplemented!("No support for rewritten graph graph pattern")
+        self.add_graph_to_subqueries(name, &inner_context);
+        GPReturn::subquery()
     }
 }
@@ -3,6 +3,7 @@ use crate::rewriting::StaticQueryRewriter;
 use oxrdf::Variable;
 use representation::query_context::Context;
 use spargebra::algebra::GraphPattern;
+use spargebra::term::NamedNodePattern;
 use spargebra::Query;
 
 impl StaticQueryRewriter {
@@ -26,10 +27,38 @@ impl StaticQueryRewriter {
         self.static_subqueries.insert(
             context.clone(),
             Query::Select {
-                dataset: None,
+                dataset: self.dataset.clone(),
                 pattern: gp,
                 base_iri: None,
             },
         );
     }
+
+    // Static subqueries created inside a GRAPH pattern must be evaluated against the same graph.
+    pub(crate) fn add_graph_to_subqueries(&mut self, name: &NamedNodePattern, context: &Context) {
+        for (c, q) in self.static_subqueries.iter_mut() {
+            if c.path.len() < context.path.len()
+                || !c.path.iter().zip(&context.path).all(|(x, y)| x == y)
+            {
+                continue;
+            }
+            if let Query::Select {
+                pattern: GraphPattern::Project { inner, variables },
+                ..
+            } = q
+            {
+                let graph_inner =
+                    std::mem::replace(inner, Box::new(GraphPattern::Bgp { patterns: vec![] }));
+                *inner = Box::new(GraphPattern::Graph {
+                    name: name.clone(),
+                    inner: graph_inner,
+                });
+                if let NamedNodePattern::Variable(v) = name {
+                    if !variables.contains(v) {
+                        variables.push(v.clone());
+                    }
+                }
+            }
+        }
+    }
 }
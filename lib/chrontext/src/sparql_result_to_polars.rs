@@ -1,11 +1,15 @@
+use crate::combiner::CombinerError;
 use oxrdf::{NamedNode, Term, Variable};
-use polars::prelude::{as_struct, col, DataFrame, IntoColumn, IntoLazy, LiteralValue};
-use representation::multitype::{
-    all_multi_cols, base_col_name, MULTI_BLANK_DT, MULTI_IRI_DT, MULTI_NONE_DT,
+use polars::prelude::{
+    as_struct, col, polars_err, AnyValue, BooleanChunkedBuilder, ChunkedBuilder, DataFrame,
+    Float32Type, Float64Type, Int32Type, Int64Type, IntoColumn, IntoLazy, IntoSeries, PolarsError,
+    PolarsResult, PrimitiveChunkedBuilder, Series, StringChunkedBuilder, StructChunked, TimeUnit,
+    TimeZone, UInt32Type, UInt64Type,
 };
+use representation::multitype::{all_multi_cols, base_col_name, MULTI_BLANK_DT, MULTI_IRI_DT};
 use representation::rdf_to_polars::{
-    polars_literal_values_to_series, rdf_blank_node_to_polars_literal_value,
-    rdf_literal_to_polars_literal_value, rdf_named_node_to_polars_literal_value,
+    rdf_blank_node_to_polars_literal_value, rdf_literal_to_polars_literal_value,
+    rdf_named_node_to_polars_literal_value,
 };
 use representation::{
     BaseRDFNodeType, RDFNodeType, LANG_STRING_LANG_FIELD, LANG_STRING_VALUE_FIELD,
@@ -13,159 +17,569 @@ use representation::{
 use sparesults::QuerySolution;
 use spargebra::algebra::GraphPattern;
 use spargebra::Query;
+use sparql_database::SolutionSink;
 use std::collections::{HashMap, HashSet};
 
-pub(crate) fn create_static_query_dataframe(
-    static_query: &Query,
-    static_query_solutions: Vec<QuerySolution>,
-) -> (DataFrame, HashMap<String, RDFNodeType>) {
-    let column_variables;
-    if let Query::Select {
-        dataset: _,
-        pattern,
-        base_iri: _,
-    } = static_query
-    {
-        column_variables = get_projected_variables(pattern)
-    } else {
-        panic!("");
-    }
-    let mut var_col_map = HashMap::new();
-    for v in &column_variables {
-        let mut i = 0;
-        let mut col_map: HashMap<String, Vec<LiteralValue>> = HashMap::new();
-        for s in &static_query_solutions {
-            let (k, litval) = if let Some(term) = s.get(v) {
-                match term {
-                    Term::NamedNode(n) => (MULTI_IRI_DT, rdf_named_node_to_polars_literal_value(n)),
-                    Term::BlankNode(b) => {
-                        (MULTI_BLANK_DT, rdf_blank_node_to_polars_literal_value(b))
-                    }
-                    Term::Literal(l) => (
-                        l.datatype().as_str(),
-                        rdf_literal_to_polars_literal_value(l),
-                    ),
-                }
-            } else {
-                (MULTI_NONE_DT, LiteralValue::untyped_null())
-            };
-
-            if let Some(v) = col_map.get_mut(k) {
-                v.push(litval)
-            } else if k != MULTI_NONE_DT {
-                let mut v: Vec<_> = (0..i).map(|_| LiteralValue::untyped_null()).collect();
-                v.push(litval);
-                col_map.insert(k.to_string(), v);
-            }
-            push_none_all_others(k, &mut col_map);
-            i += 1;
-        }
-        if col_map.is_empty() {
-            col_map.insert(
-                MULTI_NONE_DT.to_string(),
-                (0..i).map(|_| LiteralValue::untyped_null()).collect(),
-            );
-        }
-        let mut new_col_map = HashMap::new();
-        for (c, v) in col_map {
-            let dt = if c == MULTI_IRI_DT {
-                BaseRDFNodeType::IRI
-            } else if c == MULTI_BLANK_DT {
-                BaseRDFNodeType::BlankNode
-            } else if c == MULTI_NONE_DT {
-                BaseRDFNodeType::None
-            } else {
-                BaseRDFNodeType::Literal(NamedNode::new_unchecked(c))
-            };
-            new_col_map.insert(dt, v);
-        }
-
-        var_col_map.insert(v.as_str().to_string(), new_col_map);
-    }
-    let mut rdf_node_types = HashMap::new();
-    let mut all_columns: Vec<_> = vec![];
-    for (c, m) in var_col_map {
-        let mlen = m.len();
-        let mut columns = vec![];
-        let mut types = vec![];
-        for (t, v) in m {
-            let name = if mlen > 1 {
-                base_col_name(&t)
-            } else {
-                c.clone()
-            };
-
-            let ser = polars_literal_values_to_series(v, &name);
-            if mlen > 1 && t.is_lang_string() {
-                columns.push(
-                    ser.struct_()
-                        .unwrap()
-                        .field_by_name(LANG_STRING_VALUE_FIELD)
-                        .unwrap()
-                        .into_column(),
-                );
-                columns.push(
-                    ser.struct_()
-                        .unwrap()
-                        .field_by_name(LANG_STRING_LANG_FIELD)
-                        .unwrap()
+pub(crate) struct StaticQueryDataFrameBuilder {
+    column_variables: Vec<Variable>,
+    // Per variable, the values of each datatype
+    columns: Vec<HashMap<String, TypedColumnBuilder>>,
+    height: usize,
+    // Solutions can not be rejected when they are pushed, so the first failure is returned when finishing
+    error: Option<PolarsError>,
+}
+
+impl StaticQueryDataFrameBuilder {
+    pub(crate) fn new(static_query: &Query) -> Result<StaticQueryDataFrameBuilder, CombinerError> {
+        let column_variables = if let Query::Select { pattern, .. } = static_query {
+            get_projected_variables(pattern)
+        } else {
+            None
+        };
+        let Some(column_variables) = column_variables else {
+            return Err(CombinerError::UnsupportedStaticQuery(
+                static_query.to_string(),
+            ));
+        };
+        Ok(StaticQueryDataFrameBuilder::with_variables(
+            column_variables,
+        ))
+    }
+
+    pub(crate) fn with_variables(column_variables: Vec<Variable>) -> StaticQueryDataFrameBuilder {
+        StaticQueryDataFrameBuilder {
+            columns: column_variables.iter().map(|_| HashMap::new()).collect(),
+            height: 0,
+            error: None,
+            column_variables,
+        }
+    }
+
+    pub(crate) fn push(&mut self, solution: &QuerySolution) {
+        let terms: Vec<_> = self
+            .column_variables
+            .iter()
+            .map(|v| solution.get(v))
+            .collect();
+        self.push_terms(&terms);
+    }
+
+    // Terms are given in the order of the column variables
+    pub(crate) fn push_terms(&mut self, terms: &[Option<&Term>]) {
+        if self.error.is_some() {
+            return;
+        }
+        for (term, col_map) in terms.iter().zip(self.columns.iter_mut()) {
+            if let Err(e) = push_term(col_map, self.height, *term) {
+                self.error = Some(e);
+                return;
+            }
+        }
+        self.height += 1;
+    }
+
+    pub(crate) fn finish(self) -> Result<(DataFrame, HashMap<String, RDFNodeType>), CombinerError> {
+        if let Some(e) = self.error {
+            return Err(CombinerError::StaticQueryResultsError(e));
+        }
+        self.finish_columns()
+            .map_err(CombinerError::StaticQueryResultsError)
+    }
+
+    fn finish_columns(self) -> PolarsResult<(DataFrame, HashMap<String, RDFNodeType>)> {
+        let height = self.height;
+        let mut rdf_node_types = HashMap::new();
+        let mut all_columns: Vec<_> = vec![];
+        for (v, col_map) in self.column_variables.iter().zip(self.columns) {
+            let c = v.as_str().to_string();
+            if col_map.is_empty() {
+                let t = BaseRDFNodeType::None;
+                all_columns.push(
+                    Series::full_null(c.as_str().into(), height, &t.polars_data_type())
                         .into_column(),
                 );
-            } else if matches!(t, BaseRDFNodeType::None) {
-                columns.push(ser.cast(&t.polars_data_type()).unwrap().into_column());
+                rdf_node_types.insert(c, t.as_rdf_node_type());
+                continue;
+            }
+            let mlen = col_map.len();
+            let mut columns = vec![];
+            let mut types = vec![];
+            for (k, builder) in col_map {
+                let t = if k == MULTI_IRI_DT {
+                    BaseRDFNodeType::IRI
+                } else if k == MULTI_BLANK_DT {
+                    BaseRDFNodeType::BlankNode
+                } else {
+                    BaseRDFNodeType::Literal(NamedNode::new_unchecked(k))
+                };
+                let name = if mlen > 1 {
+                    base_col_name(&t)
+                } else {
+                    c.clone()
+                };
+                if mlen > 1 && t.is_lang_string() {
+                    let ser = builder.finish(name.as_str())?;
+                    let fields = ser.struct_()?;
+                    columns.push(fields.field_by_name(LANG_STRING_VALUE_FIELD)?.into_column());
+                    columns.push(fields.field_by_name(LANG_STRING_LANG_FIELD)?.into_column());
+                } else {
+                    columns.push(builder.finish(name.as_str())?.into_column());
+                }
+                types.push(t);
+            }
+            if columns.len() == 1 {
+                all_columns.push(columns.pop().unwrap());
+                rdf_node_types.insert(c, types.pop().unwrap().as_rdf_node_type());
             } else {
-                columns.push(ser.into_column());
+                let struct_exprs: Vec<_> = all_multi_cols(&types).iter().map(col).collect();
+                let mut df = DataFrame::new(columns)?
+                    .lazy()
+                    .with_column(as_struct(struct_exprs).alias(&c))
+                    .select([col(&c)])
+                    .collect()?;
+                all_columns.push(df.drop_in_place(&c)?);
+                types.sort();
+                rdf_node_types.insert(c, RDFNodeType::MultiType(types));
             }
-            types.push(t);
         }
-        if columns.len() == 1 {
-            all_columns.push(columns.pop().unwrap().into_column());
-            rdf_node_types.insert(c.to_string(), types.pop().unwrap().as_rdf_node_type());
-        } else {
-            let mut lf = DataFrame::new(columns).unwrap().lazy();
-            let mut struct_exprs = vec![];
-            for c in all_multi_cols(&types) {
-                struct_exprs.push(col(&c));
+        Ok((DataFrame::new(all_columns)?, rdf_node_types))
+    }
+}
+
+impl SolutionSink for StaticQueryDataFrameBuilder {
+    fn push(&mut self, solution: QuerySolution) {
+        StaticQueryDataFrameBuilder::push(self, &solution);
+    }
+}
+
+// The values of one datatype of a variable, appended directly to a builder of its polars type.
+// Rows where the variable has another datatype or is unbound are nulls.
+enum TypedColumnBuilder {
+    Boolean(BooleanChunkedBuilder),
+    Int32(PrimitiveChunkedBuilder<Int32Type>),
+    Int64(PrimitiveChunkedBuilder<Int64Type>),
+    UInt32(PrimitiveChunkedBuilder<UInt32Type>),
+    UInt64(PrimitiveChunkedBuilder<UInt64Type>),
+    Float32(PrimitiveChunkedBuilder<Float32Type>),
+    Float64(PrimitiveChunkedBuilder<Float64Type>),
+    String(StringChunkedBuilder),
+    Date(PrimitiveChunkedBuilder<Int32Type>),
+    Datetime(
+        PrimitiveChunkedBuilder<Int64Type>,
+        TimeUnit,
+        Option<TimeZone>,
+    ),
+    Duration(PrimitiveChunkedBuilder<Int64Type>, TimeUnit),
+    Time(PrimitiveChunkedBuilder<Int64Type>),
+    // The values and the language tags
+    LangString(Box<(StringChunkedBuilder, StringChunkedBuilder)>),
+    // Values without a builder of their own, e.g. decimals
+    Other(Vec<AnyValue<'static>>),
+}
+
+impl TypedColumnBuilder {
+    fn new(value: &AnyValue, capacity: usize) -> TypedColumnBuilder {
+        match value {
+            AnyValue::Boolean(_) => {
+                TypedColumnBuilder::Boolean(BooleanChunkedBuilder::new("".into(), capacity))
             }
-            lf = lf
-                .with_column(as_struct(struct_exprs).alias(&c))
-                .select([col(&c)]);
-            let mut df = lf.collect().unwrap();
+            AnyValue::Int32(_) => {
+                TypedColumnBuilder::Int32(PrimitiveChunkedBuilder::new("".into(), capacity))
+            }
+            AnyValue::Int64(_) => {
+                TypedColumnBuilder::Int64(PrimitiveChunkedBuilder::new("".into(), capacity))
+            }
+            AnyValue::UInt32(_) => {
+                TypedColumnBuilder::UInt32(PrimitiveChunkedBuilder::new("".into(), capacity))
+            }
+            AnyValue::UInt64(_) => {
+                TypedColumnBuilder::UInt64(PrimitiveChunkedBuilder::new("".into(), capacity))
+            }
+            AnyValue::Float32(_) => {
+                TypedColumnBuilder::Float32(PrimitiveChunkedBuilder::new("".into(), capacity))
+            }
+            AnyValue::Float64(_) => {
+                TypedColumnBuilder::Float64(PrimitiveChunkedBuilder::new("".into(), capacity))
+            }
+            AnyValue::String(_) | AnyValue::StringOwned(_) => {
+                TypedColumnBuilder::String(StringChunkedBuilder::new("".into(), capacity))
+            }
+            AnyValue::Date(_) => {
+                TypedColumnBuilder::Date(PrimitiveChunkedBuilder::new("".into(), capacity))
+            }
+            AnyValue::Datetime(_, tu, tz) => TypedColumnBuilder::Datetime(
+                PrimitiveChunkedBuilder::new("".into(), capacity),
+                *tu,
+                tz.cloned(),
+            ),
+            AnyValue::DatetimeOwned(_, tu, tz) => TypedColumnBuilder::Datetime(
+                PrimitiveChunkedBuilder::new("".into(), capacity),
+                *tu,
+                tz.as_deref().cloned(),
+            ),
+            AnyValue::Duration(_, tu) => {
+                TypedColumnBuilder::Duration(PrimitiveChunkedBuilder::new("".into(), capacity), *tu)
+            }
+            AnyValue::Time(_) => {
+                TypedColumnBuilder::Time(PrimitiveChunkedBuilder::new("".into(), capacity))
+            }
+            _ => TypedColumnBuilder::Other(Vec::with_capacity(capacity)),
+        }
+    }
 
-            types.sort();
-            rdf_node_types.insert(c.to_string(), RDFNodeType::MultiType(types));
-            all_columns.push(df.drop_in_place(&c).unwrap());
+    fn new_lang_string(capacity: usize) -> TypedColumnBuilder {
+        TypedColumnBuilder::LangString(Box::new((
+            StringChunkedBuilder::new(LANG_STRING_VALUE_FIELD.into(), capacity),
+            StringChunkedBuilder::new(LANG_STRING_LANG_FIELD.into(), capacity),
+        )))
+    }
+
+    fn append_null(&mut self) {
+        match self {
+            TypedColumnBuilder::Boolean(b) => b.append_null(),
+            TypedColumnBuilder::Int32(b) | TypedColumnBuilder::Date(b) => b.append_null(),
+            TypedColumnBuilder::Int64(b)
+            | TypedColumnBuilder::Datetime(b, ..)
+            | TypedColumnBuilder::Duration(b, _)
+            | TypedColumnBuilder::Time(b) => b.append_null(),
+            TypedColumnBuilder::UInt32(b) => b.append_null(),
+            TypedColumnBuilder::UInt64(b) => b.append_null(),
+            TypedColumnBuilder::Float32(b) => b.append_null(),
+            TypedColumnBuilder::Float64(b) => b.append_null(),
+            TypedColumnBuilder::String(b) => b.append_null(),
+            TypedColumnBuilder::LangString(b) => {
+                b.0.append_null();
+                b.1.append_null();
+            }
+            TypedColumnBuilder::Other(values) => values.push(AnyValue::Null),
         }
     }
-    let mut df = DataFrame::new(all_columns).expect("Create df problem");
-    df = df
-        .select(column_variables.iter().map(|x| x.as_str()))
-        .unwrap();
-    (df, rdf_node_types)
+
+    fn append_value(&mut self, value: AnyValue) -> PolarsResult<()> {
+        match (self, value) {
+            (TypedColumnBuilder::Boolean(b), AnyValue::Boolean(v)) => b.append_value(v),
+            (TypedColumnBuilder::Int32(b), AnyValue::Int32(v)) => b.append_value(v),
+            (TypedColumnBuilder::Int64(b), AnyValue::Int64(v)) => b.append_value(v),
+            (TypedColumnBuilder::UInt32(b), AnyValue::UInt32(v)) => b.append_value(v),
+            (TypedColumnBuilder::UInt64(b), AnyValue::UInt64(v)) => b.append_value(v),
+            (TypedColumnBuilder::Float32(b), AnyValue::Float32(v)) => b.append_value(v),
+            (TypedColumnBuilder::Float64(b), AnyValue::Float64(v)) => b.append_value(v),
+            (TypedColumnBuilder::String(b), AnyValue::String(v)) => b.append_value(v),
+            (TypedColumnBuilder::String(b), AnyValue::StringOwned(v)) => b.append_value(v),
+            (TypedColumnBuilder::Date(b), AnyValue::Date(v)) => b.append_value(v),
+            (TypedColumnBuilder::Datetime(b, tu, tz), AnyValue::Datetime(v, tu2, tz2))
+                if *tu == tu2 && tz.as_ref() == tz2 =>
+            {
+                b.append_value(v)
+            }
+            (TypedColumnBuilder::Datetime(b, tu, tz), AnyValue::DatetimeOwned(v, tu2, tz2))
+                if *tu == tu2 && tz.as_ref() == tz2.as_deref() =>
+            {
+                b.append_value(v)
+            }
+            (TypedColumnBuilder::Duration(b, tu), AnyValue::Duration(v, tu2)) if *tu == tu2 => {
+                b.append_value(v)
+            }
+            (TypedColumnBuilder::Time(b), AnyValue::Time(v)) => b.append_value(v),
+            (TypedColumnBuilder::Other(values), v) => values.push(v.into_static()),
+            (builder, v) => {
+                return Err(polars_err!(
+                    SchemaMismatch: "value {} of type {} can not be added to a column of type {}",
+                    v, v.dtype(), builder.name()
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    fn append_lang_string(&mut self, value: &str, lang: &str) -> PolarsResult<()> {
+        let TypedColumnBuilder::LangString(b) = self else {
+            return Err(polars_err!(
+                SchemaMismatch: "language tagged string can not be added to a column of type {}",
+                self.name()
+            ));
+        };
+        b.0.append_value(value);
+        b.1.append_value(lang);
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            TypedColumnBuilder::Boolean(_) => "boolean",
+            TypedColumnBuilder::Int32(_) => "i32",
+            TypedColumnBuilder::Int64(_) => "i64",
+            TypedColumnBuilder::UInt32(_) => "u32",
+            TypedColumnBuilder::UInt64(_) => "u64",
+            TypedColumnBuilder::Float32(_) => "f32",
+            TypedColumnBuilder::Float64(_) => "f64",
+            TypedColumnBuilder::String(_) => "string",
+            TypedColumnBuilder::Date(_) => "date",
+            TypedColumnBuilder::Datetime(..) => "datetime",
+            TypedColumnBuilder::Duration(..) => "duration",
+            TypedColumnBuilder::Time(_) => "time",
+            TypedColumnBuilder::LangString(_) => "language tagged string",
+            TypedColumnBuilder::Other(_) => "any value",
+        }
+    }
+
+    fn finish(self, name: &str) -> PolarsResult<Series> {
+        let ser = match self {
+            TypedColumnBuilder::Boolean(b) => b.finish().into_series(),
+            TypedColumnBuilder::Int32(b) => b.finish().into_series(),
+            TypedColumnBuilder::Int64(b) => b.finish().into_series(),
+            TypedColumnBuilder::UInt32(b) => b.finish().into_series(),
+            TypedColumnBuilder::UInt64(b) => b.finish().into_series(),
+            TypedColumnBuilder::Float32(b) => b.finish().into_series(),
+            TypedColumnBuilder::Float64(b) => b.finish().into_series(),
+            TypedColumnBuilder::String(b) => b.finish().into_series(),
+            TypedColumnBuilder::Date(b) => b.finish().into_date().into_series(),
+            TypedColumnBuilder::Datetime(b, tu, tz) => {
+                b.finish().into_datetime(tu, tz).into_series()
+            }
+            TypedColumnBuilder::Duration(b, tu) => b.finish().into_duration(tu).into_series(),
+            TypedColumnBuilder::Time(b) => b.finish().into_time().into_series(),
+            TypedColumnBuilder::LangString(b) => {
+                let (values, langs) = *b;
+                let values = values.finish().into_series();
+                let langs = langs.finish().into_series();
+                StructChunked::from_series(name.into(), values.len(), [values, langs].iter())?
+                    .into_series()
+            }
+            TypedColumnBuilder::Other(values) => {
+                Series::from_any_values(name.into(), &values, true)?
+            }
+        };
+        Ok(ser.with_name(name.into()))
+    }
 }
 
-fn get_projected_variables(g: &GraphPattern) -> Vec<Variable> {
+pub(crate) fn get_projected_variables(g: &GraphPattern) -> Option<Vec<Variable>> {
     match g {
         GraphPattern::Union { left, right } => {
-            let left_vars = get_projected_variables(left);
-            let right_vars = get_projected_variables(right);
+            let left_vars = get_projected_variables(left)?;
+            let right_vars = get_projected_variables(right)?;
             let mut all_vars = HashSet::new();
             all_vars.extend(left_vars);
             all_vars.extend(right_vars);
-            all_vars.into_iter().collect()
+            Some(all_vars.into_iter().collect())
         }
-        GraphPattern::Project { variables, .. } => variables.clone(),
+        GraphPattern::Project { variables, .. } => Some(variables.clone()),
         GraphPattern::Distinct { inner } => get_projected_variables(inner),
         GraphPattern::Reduced { inner } => get_projected_variables(inner),
         GraphPattern::Slice { inner, .. } => get_projected_variables(inner),
-        _ => panic!("Should not happen!"),
+        _ => None,
     }
 }
 
-fn push_none_all_others(k_not: &str, map: &mut HashMap<String, Vec<LiteralValue>>) {
-    for (k, v) in map.iter_mut() {
-        if k != k_not {
-            v.push(LiteralValue::untyped_null());
+// Appends the term to the builder of its datatype, and a null to the builders of the other datatypes
+fn push_term(
+    col_map: &mut HashMap<String, TypedColumnBuilder>,
+    height: usize,
+    term: Option<&Term>,
+) -> PolarsResult<()> {
+    let Some(term) = term else {
+        for builder in col_map.values_mut() {
+            builder.append_null();
+        }
+        return Ok(());
+    };
+    let (k, litval, lang_string) = match term {
+        Term::NamedNode(n) => (
+            MULTI_IRI_DT,
+            Some(rdf_named_node_to_polars_literal_value(n)),
+            None,
+        ),
+        Term::BlankNode(b) => (
+            MULTI_BLANK_DT,
+            Some(rdf_blank_node_to_polars_literal_value(b)),
+            None,
+        ),
+        // Language tagged strings are appended as a value and a language tag
+        Term::Literal(l) => match l.language() {
+            Some(lang) => (l.datatype().as_str(), None, Some((l.value(), lang))),
+            None => (
+                l.datatype().as_str(),
+                Some(rdf_literal_to_polars_literal_value(l)),
+                None,
+            ),
+        },
+    };
+    let value = litval
+        .as_ref()
+        .map(|litval| {
+            litval.to_any_value().ok_or_else(
+                || polars_err!(ComputeError: "no value for term {} of datatype {}", term, k),
+            )
+        })
+        .transpose()?;
+    if !col_map.contains_key(k) {
+        // Earlier rows did not have this datatype
+        let mut builder = match &value {
+            Some(value) => TypedColumnBuilder::new(value, height + 1),
+            None => TypedColumnBuilder::new_lang_string(height + 1),
+        };
+        for _ in 0..height {
+            builder.append_null();
+        }
+        col_map.insert(k.to_string(), builder);
+    }
+    for (other_k, builder) in col_map.iter_mut() {
+        if other_k != k {
+            builder.append_null();
+        } else if let Some((value, lang)) = lang_string {
+            builder.append_lang_string(value, lang)?;
+        } else if let Some(value) = &value {
+            builder.append_value(value.clone())?;
         }
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use oxrdf::vocab::{rdf, xsd};
+    use oxrdf::Literal;
+    use sparesults::{QueryResultsFormat, QueryResultsParser, SliceQueryResultsParserOutput};
+
+    fn build(
+        column_variables: Vec<Variable>,
+        rows: Vec<Vec<Option<Term>>>,
+    ) -> StaticQueryDataFrameBuilder {
+        let mut builder = StaticQueryDataFrameBuilder::with_variables(column_variables);
+        for row in &rows {
+            builder.push_terms(&row.iter().map(|t| t.as_ref()).collect::<Vec<_>>());
+        }
+        builder
+    }
+
+    fn integer(i: usize) -> Option<Term> {
+        Some(Literal::from(i as i64).into())
+    }
+
+    // Enough rows for the builders to grow several times
+    const MANY_ROWS: usize = 100_000;
+
+    #[test]
+    fn test_many_results() {
+        let height = MANY_ROWS * 2 + 10;
+        let rows = (0..height).map(|i| vec![integer(i)]).collect();
+        let (df, rdf_node_types) = build(vec![Variable::new_unchecked("v")], rows)
+            .finish()
+            .unwrap();
+        assert_eq!(df.height(), height);
+        assert_eq!(
+            rdf_node_types.get("v").unwrap(),
+            &RDFNodeType::Literal(xsd::INTEGER.into_owned())
+        );
+        let v = df.column("v").unwrap().i64().unwrap();
+        assert_eq!(v.get(0), Some(0));
+        assert_eq!(v.get(MANY_ROWS), Some(MANY_ROWS as i64));
+        assert_eq!(v.get(height - 1), Some(height as i64 - 1));
+    }
+
+    #[test]
+    fn test_types_changing_after_many_rows() {
+        let mut rows: Vec<_> = (0..MANY_ROWS).map(|i| vec![integer(i), None]).collect();
+        for i in 0..10 {
+            rows.push(vec![
+                Some(Literal::new_simple_literal(format!("s{i}")).into()),
+                Some(NamedNode::new_unchecked(format!("http://example.org/{i}")).into()),
+            ]);
+        }
+        let (df, rdf_node_types) = build(
+            vec![Variable::new_unchecked("v"), Variable::new_unchecked("w")],
+            rows,
+        )
+        .finish()
+        .unwrap();
+        assert_eq!(df.height(), MANY_ROWS + 10);
+        let mut expected_v = vec![
+            BaseRDFNodeType::Literal(xsd::INTEGER.into_owned()),
+            BaseRDFNodeType::Literal(xsd::STRING.into_owned()),
+        ];
+        expected_v.sort();
+        assert_eq!(
+            rdf_node_types.get("v").unwrap(),
+            &RDFNodeType::MultiType(expected_v)
+        );
+        // Unbound values before the first IRI are nulls of the IRI column
+        assert_eq!(rdf_node_types.get("w").unwrap(), &RDFNodeType::IRI);
+        assert_eq!(df.column("w").unwrap().null_count(), MANY_ROWS);
+    }
+
+    fn parse(
+        format: QueryResultsFormat,
+        results: &str,
+    ) -> (DataFrame, HashMap<String, RDFNodeType>) {
+        let SliceQueryResultsParserOutput::Solutions(solutions) =
+            QueryResultsParser::from_format(format)
+                .for_slice(results.as_bytes())
+                .unwrap()
+        else {
+            panic!("Expected solutions");
+        };
+        let mut builder =
+            StaticQueryDataFrameBuilder::with_variables(solutions.variables().to_vec());
+        for s in solutions {
+            builder.push(&s.unwrap());
+        }
+        builder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_tsv_and_json_results_are_equal() {
+        let tsv = "?s\t?v\t?t\n\
+            <http://example.org/a>\t1\t\"2022-06-01T08:46:52Z\"^^<http://www.w3.org/2001/XMLSchema#dateTime>\n\
+            <http://example.org/b>\t2\t\n";
+        let json = r#"{"head": {"vars": ["s", "v", "t"]}, "results": {"bindings": [
+            {"s": {"type": "uri", "value": "http://example.org/a"},
+             "v": {"type": "literal", "datatype": "http://www.w3.org/2001/XMLSchema#integer", "value": "1"},
+             "t": {"type": "literal", "datatype": "http://www.w3.org/2001/XMLSchema#dateTime", "value": "2022-06-01T08:46:52Z"}},
+            {"s": {"type": "uri", "value": "http://example.org/b"},
+             "v": {"type": "literal", "datatype": "http://www.w3.org/2001/XMLSchema#integer", "value": "2"}}
+        ]}}"#;
+        let (tsv_df, tsv_types) = parse(QueryResultsFormat::Tsv, tsv);
+        let (json_df, json_types) = parse(QueryResultsFormat::Json, json);
+        assert!(tsv_df.equals_missing(&json_df));
+        assert_eq!(tsv_types, json_types);
+        assert_eq!(
+            json_types.get("t").unwrap(),
+            &RDFNodeType::Literal(xsd::DATE_TIME.into_owned())
+        );
+    }
+
+    #[test]
+    fn test_language_tagged_strings() {
+        let rows = vec![
+            vec![Some(
+                Literal::new_language_tagged_literal_unchecked("hei", "no").into(),
+            )],
+            vec![Some(Literal::new_simple_literal("hello").into())],
+            vec![None],
+        ];
+        let (df, rdf_node_types) = build(vec![Variable::new_unchecked("v")], rows)
+            .finish()
+            .unwrap();
+        assert_eq!(df.height(), 3);
+        let mut expected = vec![
+            BaseRDFNodeType::Literal(rdf::LANG_STRING.into_owned()),
+            BaseRDFNodeType::Literal(xsd::STRING.into_owned()),
+        ];
+        expected.sort();
+        assert_eq!(
+            rdf_node_types.get("v").unwrap(),
+            &RDFNodeType::MultiType(expected)
+        );
+    }
+
+    #[test]
+    fn test_non_select_static_query_is_an_error() {
+        let query = Query::parse("ASK { ?s ?p ?o }", None).unwrap();
+        assert!(matches!(
+            StaticQueryDataFrameBuilder::new(&query),
+            Err(CombinerError::UnsupportedStaticQuery(_))
+        ));
+    }
 }
@@ -1,3 +1,5 @@
+use spargebra::algebra::GraphPattern;
+use spargebra::term::{NamedNodePattern, TermPattern, TriplePattern, Variable};
 use spargebra::{Query, SparqlSyntaxError};
 use thiserror::Error;
 
@@ -7,6 +9,10 @@ pub enum QueryParseError {
     Parse(SparqlSyntaxError),
     #[error("Not a select query")]
     NotSelectQuery,
+    #[error("Not a construct query")]
+    NotConstructQuery,
+    #[error("Not an ask query")]
+    NotAskQuery,
     #[error("Unsupported construct: `{0}`")]
     Unsupported(String),
 }
@@ -15,32 +21,106 @@ pub fn parse_sparql_select_query(query_str: &str) -> Result<Query, QueryParseErr
     let q_res = Query::parse(query_str, None);
     match q_res {
         Ok(q) => match q {
+            //Relative IRIs are resolved against BASE by the parser
             Query::Select {
                 dataset,
                 pattern,
-                base_iri,
+                base_iri: _,
+            } => Ok(Query::Select {
+                dataset,
+                pattern,
+                base_iri: None,
+            }),
+            _ => Err(QueryParseError::NotSelectQuery),
+        },
+        Err(e) => Err(QueryParseError::Parse(e)),
+    }
+}
+
+pub fn parse_sparql_construct_query(
+    query_str: &str,
+) -> Result<(Vec<TriplePattern>, Query), QueryParseError> {
+    let q_res = Query::parse(query_str, None);
+    match q_res {
+        Ok(q) => match q {
+            //The WHERE clause is evaluated as a select query projecting the template variables
+            Query::Construct {
+                template,
+                dataset,
+                pattern,
+                base_iri: _,
             } => {
-                let mut unsupported_constructs = vec![];
-                if dataset.is_some() {
-                    unsupported_constructs.push("Dataset")
-                }
-                if base_iri.is_some() {
-                    unsupported_constructs.push("BaseIri")
-                }
-                if !unsupported_constructs.is_empty() {
-                    Err(QueryParseError::Unsupported(
-                        unsupported_constructs.join(","),
-                    ))
-                } else {
-                    Ok(Query::Select {
-                        dataset,
-                        pattern,
-                        base_iri,
-                    })
+                let mut in_scope = vec![];
+                pattern.on_in_scope_variable(|v| in_scope.push(v.clone()));
+                let mut variables = vec![];
+                for t in &template {
+                    for v in triple_pattern_variables(t) {
+                        if in_scope.contains(v) && !variables.contains(v) {
+                            variables.push(v.clone());
+                        }
+                    }
                 }
+                let select = Query::Select {
+                    dataset,
+                    pattern: GraphPattern::Project {
+                        inner: Box::new(pattern),
+                        variables,
+                    },
+                    base_iri: None,
+                };
+                Ok((template, select))
             }
-            _ => Err(QueryParseError::NotSelectQuery),
+            _ => Err(QueryParseError::NotConstructQuery),
         },
         Err(e) => Err(QueryParseError::Parse(e)),
     }
 }
+
+pub fn parse_sparql_ask_query(query_str: &str) -> Result<Query, QueryParseError> {
+    let q_res = Query::parse(query_str, None);
+    match q_res {
+        Ok(q) => match q {
+            //The pattern is evaluated as a select query limited to a single solution
+            Query::Ask {
+                dataset,
+                pattern,
+                base_iri: _,
+            } => {
+                let mut variables = vec![];
+                pattern.on_in_scope_variable(|v| {
+                    if !variables.contains(v) {
+                        variables.push(v.clone())
+                    }
+                });
+                Ok(Query::Select {
+                    dataset,
+                    pattern: GraphPattern::Slice {
+                        inner: Box::new(GraphPattern::Project {
+                            inner: Box::new(pattern),
+                            variables,
+                        }),
+                        start: 0,
+                        length: Some(1),
+                    },
+                    base_iri: None,
+                })
+            }
+            _ => Err(QueryParseError::NotAskQuery),
+        },
+        Err(e) => Err(QueryParseError::Parse(e)),
+    }
+}
+
+fn triple_pattern_variables(triple_pattern: &TriplePattern) -> Vec<&Variable> {
+    let mut variables = vec![];
+    if let TermPattern::Variable(v) = &triple_pattern.subject {
+        variables.push(v);
+    }
+    if let NamedNodePattern::Variable(v) = &triple_pattern.predicate {
+        variables.push(v);
+    }
+    if let TermPattern::Variable(v) = &triple_pattern.object {
+        variables.push(v);
+    }
+    variables
+}
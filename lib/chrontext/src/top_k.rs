@@ -0,0 +1,86 @@
+use crate::constants::{EARLIEST, LATEST, REVERSE_ROW_NUMBER, ROW_NUMBER, TOP_K_ROW_NUMBER_COL};
+use crate::errors::ChrontextError;
+use crate::filter_rewriting::{conjuncts, filter, rewrite_filters};
+use crate::splitter::QueryParseError;
+use oxrdf::vocab::xsd;
+use oxrdf::{Literal, NamedNode, Variable};
+use spargebra::algebra::{Expression, Function, GraphPattern};
+use spargebra::Query;
+
+// Rewrites ct:latest(?t, k) and ct:earliest(?t, k) conditions in filters, which keep the k latest or earliest values of each series.
+// The values are numbered by a window over each series and the condition is on the row number, so both can be pushed down.
+// Also returns the number of conditions that were rewritten.
+pub fn rewrite_top_k_filters(query: Query) -> Result<(Query, usize), ChrontextError> {
+    let mut counter = 0;
+    let query = match query {
+        Query::Select {
+            dataset,
+            pattern,
+            base_iri,
+        } => Query::Select {
+            dataset,
+            pattern: rewrite_filters(pattern, &mut |expr, mut inner| {
+                let mut keep = vec![];
+                let mut row_number_conditions = vec![];
+                for e in conjuncts(expr) {
+                    let Some((row_number_function, timestamp, k)) = top_k(&e)? else {
+                        keep.push(e);
+                        continue;
+                    };
+                    let row_number =
+                        Variable::new_unchecked(format!("{TOP_K_ROW_NUMBER_COL}_{counter}"));
+                    counter += 1;
+                    inner = GraphPattern::Extend {
+                        inner: Box::new(inner),
+                        variable: row_number.clone(),
+                        expression: Expression::FunctionCall(
+                            Function::Custom(NamedNode::new_unchecked(row_number_function)),
+                            vec![Expression::Variable(timestamp)],
+                        ),
+                    };
+                    row_number_conditions.push(Expression::LessOrEqual(
+                        Box::new(Expression::Variable(row_number)),
+                        Box::new(Expression::Literal(Literal::new_typed_literal(
+                            k.to_string(),
+                            xsd::INTEGER,
+                        ))),
+                    ));
+                }
+                // The other conditions apply to the values that are kept
+                Ok(filter(keep, filter(row_number_conditions, inner)))
+            })?,
+            base_iri,
+        },
+        _ => return Err(QueryParseError::NotSelectQuery.into()),
+    };
+    Ok((query, counter))
+}
+
+fn top_k(expr: &Expression) -> Result<Option<(&'static str, Variable, usize)>, ChrontextError> {
+    let Expression::FunctionCall(Function::Custom(nn), args) = expr else {
+        return Ok(None);
+    };
+    let row_number_function = match nn.as_str() {
+        LATEST => REVERSE_ROW_NUMBER,
+        EARLIEST => ROW_NUMBER,
+        _ => return Ok(None),
+    };
+    let invalid = |reason: &str| ChrontextError::InvalidTopKArguments(reason.to_string());
+    let Some(Expression::Variable(timestamp)) = args.first() else {
+        return Err(invalid("the first argument must be a timestamp variable"));
+    };
+    if args.len() > 2 {
+        return Err(invalid("expected at most two arguments"));
+    }
+    let k = match args.get(1) {
+        None => 1,
+        Some(Expression::Literal(l)) => l
+            .value()
+            .parse::<usize>()
+            .ok()
+            .filter(|x| *x > 0)
+            .ok_or_else(|| invalid("the number of values must be a positive integer"))?,
+        Some(_) => return Err(invalid("the number of values must be a literal integer")),
+    };
+    Ok(Some((row_number_function, timestamp.clone(), k)))
+}
@@ -0,0 +1,154 @@
+use crate::as_of::parse_day_time_duration;
+use crate::combiner::CombinerError;
+use crate::constants::{
+    CUMULATIVE_SUM, DELTA, DERIVATIVE, LAG, LEAD, REVERSE_ROW_NUMBER, ROLLING_MEAN, ROLLING_SUM,
+    ROW_NUMBER,
+};
+use oxrdf::vocab::xsd;
+use oxrdf::Variable;
+use polars::prelude::{
+    col, lit, ClosedWindow, DataType, Duration as PolarsDuration, LazyFrame,
+    RollingOptionsDynamicWindow, SortMultipleOptions,
+};
+use representation::RDFNodeType;
+use spargebra::algebra::{Expression, Function};
+use virtualized_query::{Window, WindowFunction};
+
+// Recognizes e.g. ct:lag(?v, 2) and ct:rollingMean(?v, "PT10S"^^xsd:duration), returning the function and the value variable
+pub(crate) fn window_function(
+    expression: &Expression,
+) -> Result<Option<(WindowFunction, Variable)>, CombinerError> {
+    let Expression::FunctionCall(Function::Custom(f), args) = expression else {
+        return Ok(None);
+    };
+    if ![
+        LAG,
+        LEAD,
+        ROLLING_MEAN,
+        ROLLING_SUM,
+        CUMULATIVE_SUM,
+        DELTA,
+        DERIVATIVE,
+        ROW_NUMBER,
+        REVERSE_ROW_NUMBER,
+    ]
+    .contains(&f.as_str())
+    {
+        return Ok(None);
+    }
+    let invalid = |reason: &str| CombinerError::InvalidWindow(reason.to_string());
+    let Some(Expression::Variable(value)) = args.first() else {
+        return Err(invalid("the first argument must be a variable"));
+    };
+    let function = match f.as_str() {
+        LAG | LEAD => {
+            let offset = match args.get(1) {
+                None => 1,
+                Some(Expression::Literal(l)) => l
+                    .value()
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|x| *x > 0)
+                    .ok_or_else(|| invalid("the offset must be a positive integer"))?,
+                Some(_) => return Err(invalid("the offset must be a literal integer")),
+            };
+            if args.len() > 2 {
+                return Err(invalid("expected at most two arguments"));
+            }
+            if f.as_str() == LAG {
+                WindowFunction::Lag(offset)
+            } else {
+                WindowFunction::Lead(offset)
+            }
+        }
+        ROLLING_MEAN | ROLLING_SUM => {
+            let (Some(Expression::Literal(l)), 2) = (args.get(1), args.len()) else {
+                return Err(invalid("the second argument must be a literal duration"));
+            };
+            let window_size = parse_day_time_duration(l.value())
+                .filter(|x| !x.is_zero())
+                .ok_or_else(|| {
+                    invalid("the window size must be a duration without years or months")
+                })?;
+            if f.as_str() == ROLLING_MEAN {
+                WindowFunction::RollingMean(window_size)
+            } else {
+                WindowFunction::RollingSum(window_size)
+            }
+        }
+        f => {
+            if args.len() > 1 {
+                return Err(invalid("expected a single argument"));
+            }
+            match f {
+                CUMULATIVE_SUM => WindowFunction::CumulativeSum,
+                DELTA => WindowFunction::Delta,
+                DERIVATIVE => WindowFunction::Derivative,
+                ROW_NUMBER => WindowFunction::RowNumber,
+                _ => WindowFunction::ReverseRowNumber,
+            }
+        }
+    };
+    Ok(Some((function, value.clone())))
+}
+
+pub(crate) fn window_rdf_node_type(
+    function: &WindowFunction,
+    value_type: &RDFNodeType,
+) -> RDFNodeType {
+    match function {
+        WindowFunction::RollingMean(_) | WindowFunction::Derivative => {
+            RDFNodeType::Literal(xsd::DOUBLE.into_owned())
+        }
+        WindowFunction::RowNumber | WindowFunction::ReverseRowNumber => {
+            RDFNodeType::Literal(xsd::LONG.into_owned())
+        }
+        _ => value_type.clone(),
+    }
+}
+
+// The rows are sorted by series and timestamp, and the window is computed over each series
+pub(crate) fn window(mappings: LazyFrame, variable: &Variable, window: &Window) -> LazyFrame {
+    let value = col(window.value.as_str());
+    let timestamp = col(window.timestamp.as_str());
+    let partition = [col(window.partition_by.as_str())];
+    let expr = match &window.function {
+        WindowFunction::Lag(n) => value.shift(lit(*n as i64)),
+        WindowFunction::Lead(n) => value.shift(lit(-(*n as i64))),
+        WindowFunction::RollingMean(d) => value
+            .cast(DataType::Float64)
+            .rolling_mean_by(timestamp.clone(), rolling_options(d)),
+        WindowFunction::RollingSum(d) => {
+            value.rolling_sum_by(timestamp.clone(), rolling_options(d))
+        }
+        WindowFunction::CumulativeSum => value.cum_sum(false),
+        WindowFunction::Delta => value.clone() - value.shift(lit(1)),
+        WindowFunction::Derivative => {
+            let value = value.cast(DataType::Float64);
+            let seconds = (timestamp.clone() - timestamp.clone().shift(lit(1)))
+                .dt()
+                .total_microseconds()
+                .cast(DataType::Float64)
+                / lit(1_000_000.0);
+            (value.clone() - value.shift(lit(1))) / seconds
+        }
+        WindowFunction::RowNumber => timestamp.clone().cum_count(false).cast(DataType::Int64),
+        WindowFunction::ReverseRowNumber => timestamp.clone().cum_count(true).cast(DataType::Int64),
+    };
+    mappings
+        .sort_by_exprs(
+            [partition[0].clone(), timestamp],
+            SortMultipleOptions::default(),
+        )
+        .with_column(expr.over(partition).alias(variable.as_str()))
+}
+
+// Matches RANGE BETWEEN <window size> PRECEDING AND CURRENT ROW in SQL
+fn rolling_options(window_size: &std::time::Duration) -> RollingOptionsDynamicWindow {
+    RollingOptionsDynamicWindow {
+        window_size: PolarsDuration::parse(&format!("{}us", window_size.as_micros())),
+        min_periods: 1,
+        closed_window: ClosedWindow::Both,
+        fn_params: None,
+    }
+}
@@ -1,14 +1,16 @@
-use super::{parse_json_text, SparqlQueryError, SparqlQueryable};
+use super::{parse_results_slice, SolutionSink, SparqlQueryError, SparqlQueryable};
 use async_trait::async_trait;
 use pyo3::types::{PyAnyMethods, PyNone};
-use pyo3::{Py, PyAny, Python};
-use sparesults::QuerySolution;
+use pyo3::{Py, PyAny, PyResult, Python};
+use sparesults::QueryResultsFormat;
 use spargebra::Query;
 use thiserror::Error;
 #[derive(Debug, Error)]
 pub enum EmbeddedOxigraphError {
-    #[error("Oxigraph evaluation error")]
+    #[error("Oxigraph evaluation error: {0}")]
     EvaluationError(String),
+    #[error("Error serializing Oxigraph results: {0}")]
+    SerializationError(String),
 }
 
 pub struct EmbeddedOxigraph {
@@ -17,24 +19,29 @@ pub struct EmbeddedOxigraph {
 
 #[async_trait]
 impl SparqlQueryable for EmbeddedOxigraph {
-    async fn execute(&self, query: &Query) -> Result<Vec<QuerySolution>, SparqlQueryError> {
+    async fn execute(
+        &self,
+        query: &Query,
+        sink: &mut dyn SolutionSink,
+    ) -> Result<(), SparqlQueryError> {
         Python::with_gil(|py| {
-            let json_format = py
-                .import("pyoxigraph")
-                .unwrap()
-                .getattr("QueryResultsFormat")
-                .unwrap()
-                .getattr("JSON")
-                .unwrap();
-            let json = self
+            let results = self
                 .store
                 .call_method1(py, "query", (query.to_string(),))
-                .unwrap()
-                .call_method1(py, "serialize", (PyNone::get(py), json_format))
-                .unwrap();
-            let json_bytes: Vec<u8> = json.extract(py).unwrap();
-            let json_string = String::from_utf8(json_bytes).unwrap();
-            parse_json_text(&json_string)
+                .map_err(|e| EmbeddedOxigraphError::EvaluationError(e.to_string()))?;
+            let json_bytes: Vec<u8> = serialize_json(py, &results)
+                .map_err(|e| EmbeddedOxigraphError::SerializationError(e.to_string()))?;
+            parse_results_slice(QueryResultsFormat::Json, &json_bytes, sink)
         })
     }
 }
+
+fn serialize_json(py: Python, results: &Py<PyAny>) -> PyResult<Vec<u8>> {
+    let json_format = py
+        .import("pyoxigraph")?
+        .getattr("QueryResultsFormat")?
+        .getattr("JSON")?;
+    results
+        .call_method1(py, "serialize", (PyNone::get(py), json_format))?
+        .extract(py)
+}
@@ -1,51 +1,404 @@
-use super::{parse_json_text, SparqlQueryError, SparqlQueryable};
+use super::{SolutionSink, SparqlQueryError, SparqlQueryable};
 use async_trait::async_trait;
-use reqwest::header::{ACCEPT, USER_AGENT};
-use sparesults::{QueryResultsSyntaxError, QuerySolution};
+use futures::TryStreamExt;
+use reqwest::header::{ACCEPT, CONTENT_TYPE, USER_AGENT};
+use reqwest::RequestBuilder;
+use secrecy::{ExposeSecret, SecretString};
+use sparesults::{
+    QueryResultsFormat, QueryResultsParseError, QueryResultsParser,
+    TokioAsyncReaderQueryResultsParserOutput,
+};
 use spargebra::Query;
+use std::time::Duration;
 use thiserror::Error;
+use tokio_util::io::StreamReader;
+
+// Formats we can parse, most efficient first
+const NEGOTIATED_RESULTS_ACCEPT: &str = "text/tab-separated-values,application/sparql-results+json;q=0.9,application/json;q=0.8,application/sparql-results+xml;q=0.7,text/javascript;q=0.5,application/javascript;q=0.5";
+const JSON_RESULTS_ACCEPT: &str =
+    "application/sparql-results+json,application/json,text/javascript,application/javascript";
 
 #[derive(Debug, Error)]
 pub enum SparqlEndpointQueryExecutionError {
     #[error(transparent)]
     RequestError(reqwest::Error),
-    #[error("Bad status code `{0}`")]
-    BadStatusCode(String),
+    #[error("Bad status code `{0}`: {1}")]
+    BadStatusCode(String, String),
     #[error("Results parse error `{0}`")]
-    ResultsParseError(QueryResultsSyntaxError),
+    ResultsParseError(QueryResultsParseError),
     #[error("Solution parse error `{0}`")]
-    SolutionParseError(QueryResultsSyntaxError),
+    SolutionParseError(QueryResultsParseError),
     #[error("Wrong result type, expected solutions")]
     WrongResultType,
+    #[error("Unsupported results format `{0}`")]
+    UnsupportedResultsFormat(String),
 }
 
-pub struct SparqlEndpoint {
+#[derive(Clone, Debug, Default)]
+pub enum SparqlEndpointMethod {
+    #[default]
+    Get,
+    // The query is the body, with content type application/sparql-query
+    Post,
+    // The query is sent as application/x-www-form-urlencoded
+    PostForm,
+}
+
+// Secrets are redacted when the config is debug printed
+#[derive(Clone, Debug)]
+pub enum SparqlEndpointAuth {
+    Basic {
+        username: String,
+        password: Option<SecretString>,
+    },
+    Bearer(SecretString),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SparqlEndpointConfig {
     pub endpoint: String,
+    pub method: SparqlEndpointMethod,
+    pub auth: Option<SparqlEndpointAuth>,
+    // Header values may carry API keys, so they are kept secret as well
+    pub headers: Vec<(String, SecretString)>,
+    pub default_graph_uris: Vec<String>,
+    pub named_graph_uris: Vec<String>,
+    pub timeout: Option<Duration>,
+    // When not set, the format is negotiated with the endpoint
+    pub results_format: Option<QueryResultsFormat>,
+    // Sends format=json, output=json and results=json with GET requests,
+    // for endpoints that ignore the Accept header
+    pub json_format_parameters: bool,
+}
+
+impl SparqlEndpointConfig {
+    pub fn new(endpoint: String) -> SparqlEndpointConfig {
+        SparqlEndpointConfig {
+            endpoint,
+            ..Default::default()
+        }
+    }
+}
+
+pub struct SparqlEndpoint {
+    pub config: SparqlEndpointConfig,
+    client: reqwest::Client,
+}
+
+impl SparqlEndpoint {
+    pub fn new(config: SparqlEndpointConfig) -> SparqlEndpoint {
+        // The client keeps a connection pool, so it is reused across queries
+        SparqlEndpoint {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn request(&self, query: &Query) -> RequestBuilder {
+        let config = &self.config;
+        let mut dataset_params = vec![];
+        for g in &config.default_graph_uris {
+            dataset_params.push(("default-graph-uri", g.as_str()));
+        }
+        for g in &config.named_graph_uris {
+            dataset_params.push(("named-graph-uri", g.as_str()));
+        }
+        let query_string = query.to_string();
+        let mut request = match config.method {
+            SparqlEndpointMethod::Get => {
+                let request = self
+                    .client
+                    .get(&config.endpoint)
+                    .query(&[("query", query_string.as_str())])
+                    .query(&dataset_params);
+                if config.json_format_parameters
+                    || matches!(config.results_format, Some(QueryResultsFormat::Json))
+                {
+                    request.query(&[("format", "json"), ("output", "json"), ("results", "json")])
+                } else {
+                    request
+                }
+            }
+            SparqlEndpointMethod::Post => self
+                .client
+                .post(&config.endpoint)
+                .query(&dataset_params)
+                .header(CONTENT_TYPE, "application/sparql-query")
+                .body(query_string),
+            SparqlEndpointMethod::PostForm => {
+                let mut form = vec![("query", query_string.as_str())];
+                form.extend(dataset_params);
+                self.client.post(&config.endpoint).form(&form)
+            }
+        };
+        let accept = match config.results_format {
+            None => NEGOTIATED_RESULTS_ACCEPT,
+            Some(QueryResultsFormat::Json) => JSON_RESULTS_ACCEPT,
+            Some(format) => format.media_type(),
+        };
+        request = request
+            .header(ACCEPT, accept)
+            .header(USER_AGENT, "chrontext");
+        match &config.auth {
+            Some(SparqlEndpointAuth::Basic { username, password }) => {
+                request =
+                    request.basic_auth(username, password.as_ref().map(|p| p.expose_secret()));
+            }
+            Some(SparqlEndpointAuth::Bearer(token)) => {
+                request = request.bearer_auth(token.expose_secret());
+            }
+            None => {}
+        }
+        for (k, v) in &config.headers {
+            request = request.header(k, v.expose_secret());
+        }
+        if let Some(timeout) = config.timeout {
+            request = request.timeout(timeout);
+        }
+        request
+    }
 }
+
 #[async_trait]
 impl SparqlQueryable for SparqlEndpoint {
-    async fn execute(&self, query: &Query) -> Result<Vec<QuerySolution>, SparqlQueryError> {
-        let client = reqwest::Client::new();
-        let response = client
-            .get(&self.endpoint)
-            .header(ACCEPT, "application/sparql-results+json,application/json,text/javascript,application/javascript")
-            .header(USER_AGENT, "chrontext")
-            .query(&[("query",query.to_string())])
-            .query(&[("format", "json"), ("output", "json"), ("results", "json")])
+    async fn execute(
+        &self,
+        query: &Query,
+        sink: &mut dyn SolutionSink,
+    ) -> Result<(), SparqlQueryError> {
+        let response = self
+            .request(query)
             .send()
-            .await;
-        match response {
-            Ok(proper_response) => {
-                if proper_response.status().as_u16() != 200 {
-                    Err(SparqlEndpointQueryExecutionError::BadStatusCode(
-                        proper_response.status().to_string(),
-                    )
-                    .into())
-                } else {
-                    parse_json_text(&proper_response.text().await.expect("Read text error"))
-                }
+            .await
+            .map_err(SparqlEndpointQueryExecutionError::RequestError)?;
+        let status = response.status();
+        if status.as_u16() != 200 {
+            // The body of an error response usually explains what went wrong
+            let text = response
+                .text()
+                .await
+                .map_err(SparqlEndpointQueryExecutionError::RequestError)?;
+            return Err(
+                SparqlEndpointQueryExecutionError::BadStatusCode(status.to_string(), text).into(),
+            );
+        }
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok());
+        let format = response_format(content_type, self.config.results_format)?;
+        let body = StreamReader::new(response.bytes_stream().map_err(std::io::Error::other));
+        let parsed_results = QueryResultsParser::from_format(format)
+            .for_tokio_async_reader(body)
+            .await
+            .map_err(SparqlEndpointQueryExecutionError::ResultsParseError)?;
+        if let TokioAsyncReaderQueryResultsParserOutput::Solutions(mut solutions) = parsed_results {
+            while let Some(s) = solutions.next().await {
+                sink.push(s.map_err(SparqlEndpointQueryExecutionError::SolutionParseError)?);
             }
-            Err(error) => Err(SparqlEndpointQueryExecutionError::RequestError(error).into()),
+            Ok(())
+        } else {
+            Err(SparqlEndpointQueryExecutionError::WrongResultType.into())
+        }
+    }
+}
+
+fn response_format(
+    content_type: Option<&str>,
+    requested_format: Option<QueryResultsFormat>,
+) -> Result<QueryResultsFormat, SparqlEndpointQueryExecutionError> {
+    let Some(content_type) = content_type else {
+        return Ok(requested_format.unwrap_or(QueryResultsFormat::Json));
+    };
+    match QueryResultsFormat::from_media_type(content_type) {
+        // CSV results do not carry datatypes, so they are not parsed
+        Some(QueryResultsFormat::Csv) => Err(
+            SparqlEndpointQueryExecutionError::UnsupportedResultsFormat(content_type.to_string()),
+        ),
+        Some(format) => Ok(format),
+        // Many endpoints return JSON results as application/json or javascript
+        None if content_type.contains("json") || content_type.contains("javascript") => {
+            Ok(QueryResultsFormat::Json)
+        }
+        None => Ok(requested_format.unwrap_or(QueryResultsFormat::Json)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Method;
+
+    fn build_request(config: SparqlEndpointConfig) -> reqwest::Request {
+        let query = Query::parse("SELECT ?s WHERE { ?s ?p ?o }", None).unwrap();
+        SparqlEndpoint::new(config).request(&query).build().unwrap()
+    }
+
+    fn dataset_config(method: SparqlEndpointMethod) -> SparqlEndpointConfig {
+        SparqlEndpointConfig {
+            method,
+            default_graph_uris: vec!["http://example.org/default".to_string()],
+            named_graph_uris: vec!["http://example.org/named".to_string()],
+            ..SparqlEndpointConfig::new("http://localhost:7878/query".to_string())
+        }
+    }
+
+    fn query_pairs(request: &reqwest::Request) -> Vec<(String, String)> {
+        request
+            .url()
+            .query_pairs()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_get_request_has_query_and_dataset_params() {
+        let request = build_request(dataset_config(SparqlEndpointMethod::Get));
+        assert_eq!(request.method(), Method::GET);
+        let pairs = query_pairs(&request);
+        assert!(pairs
+            .iter()
+            .any(|(k, v)| k == "query" && v.contains("?s ?p ?o")));
+        assert!(pairs.contains(&(
+            "default-graph-uri".to_string(),
+            "http://example.org/default".to_string()
+        )));
+        assert!(pairs.contains(&(
+            "named-graph-uri".to_string(),
+            "http://example.org/named".to_string()
+        )));
+        assert_eq!(
+            request.headers().get(ACCEPT).unwrap(),
+            NEGOTIATED_RESULTS_ACCEPT
+        );
+    }
+
+    #[test]
+    fn test_get_request_has_json_format_params_only_when_configured() {
+        let format_params = [("format", "json"), ("output", "json"), ("results", "json")]
+            .map(|(k, v)| (k.to_string(), v.to_string()));
+        let pairs = query_pairs(&build_request(dataset_config(SparqlEndpointMethod::Get)));
+        assert!(format_params.iter().all(|p| !pairs.contains(p)));
+        let pairs = query_pairs(&build_request(SparqlEndpointConfig {
+            json_format_parameters: true,
+            ..dataset_config(SparqlEndpointMethod::Get)
+        }));
+        assert!(format_params.iter().all(|p| pairs.contains(p)));
+    }
+
+    #[test]
+    fn test_post_request_sends_query_as_body() {
+        let request = build_request(dataset_config(SparqlEndpointMethod::Post));
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(
+            request.headers().get(CONTENT_TYPE).unwrap(),
+            "application/sparql-query"
+        );
+        let body = std::str::from_utf8(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert!(body.contains("?s ?p ?o"));
+        let pairs = query_pairs(&request);
+        assert!(pairs.iter().all(|(k, _)| k != "query"));
+        assert!(pairs.contains(&(
+            "default-graph-uri".to_string(),
+            "http://example.org/default".to_string()
+        )));
+        assert!(pairs.contains(&(
+            "named-graph-uri".to_string(),
+            "http://example.org/named".to_string()
+        )));
+    }
+
+    #[test]
+    fn test_post_form_request_sends_dataset_params_in_form() {
+        let request = build_request(dataset_config(SparqlEndpointMethod::PostForm));
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(
+            request.headers().get(CONTENT_TYPE).unwrap(),
+            "application/x-www-form-urlencoded"
+        );
+        let body = std::str::from_utf8(request.body().unwrap().as_bytes().unwrap()).unwrap();
+        assert!(body.starts_with("query="));
+        assert!(body.contains("default-graph-uri=http%3A%2F%2Fexample.org%2Fdefault"));
+        assert!(body.contains("named-graph-uri=http%3A%2F%2Fexample.org%2Fnamed"));
+        assert!(query_pairs(&request).is_empty());
+    }
+
+    #[test]
+    fn test_request_has_headers_and_auth() {
+        let request = build_request(SparqlEndpointConfig {
+            auth: Some(SparqlEndpointAuth::Bearer(SecretString::from("my-token"))),
+            headers: vec![("X-Api-Key".to_string(), SecretString::from("my-key"))],
+            results_format: Some(QueryResultsFormat::Json),
+            ..SparqlEndpointConfig::new("http://localhost:7878/query".to_string())
+        });
+        let headers = request.headers();
+        assert_eq!(headers.get("X-Api-Key").unwrap(), "my-key");
+        assert_eq!(headers.get("authorization").unwrap(), "Bearer my-token");
+        assert_eq!(headers.get(ACCEPT).unwrap(), JSON_RESULTS_ACCEPT);
+        assert!(query_pairs(&request).contains(&("format".to_string(), "json".to_string())));
+    }
+
+    #[test]
+    fn test_response_format_follows_content_type() {
+        for (content_type, format) in [
+            (
+                "text/tab-separated-values; charset=utf-8",
+                QueryResultsFormat::Tsv,
+            ),
+            ("application/sparql-results+json", QueryResultsFormat::Json),
+            ("application/sparql-results+xml", QueryResultsFormat::Xml),
+            ("application/json", QueryResultsFormat::Json),
+            ("text/javascript; charset=utf-8", QueryResultsFormat::Json),
+        ] {
+            assert_eq!(response_format(Some(content_type), None).unwrap(), format);
+            // The content type wins over the requested format
+            assert_eq!(
+                response_format(Some(content_type), Some(QueryResultsFormat::Xml)).unwrap(),
+                format
+            );
         }
     }
+
+    #[test]
+    fn test_response_format_without_known_content_type() {
+        assert_eq!(
+            response_format(None, None).unwrap(),
+            QueryResultsFormat::Json
+        );
+        assert_eq!(
+            response_format(
+                Some("application/octet-stream"),
+                Some(QueryResultsFormat::Tsv)
+            )
+            .unwrap(),
+            QueryResultsFormat::Tsv
+        );
+        assert!(matches!(
+            response_format(Some("text/csv"), None),
+            Err(SparqlEndpointQueryExecutionError::UnsupportedResultsFormat(
+                _
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_debug_redacts_secrets() {
+        let config = SparqlEndpointConfig {
+            auth: Some(SparqlEndpointAuth::Basic {
+                username: "user".to_string(),
+                password: Some(SecretString::from("my-password")),
+            }),
+            headers: vec![("X-Api-Key".to_string(), SecretString::from("my-key"))],
+            ..SparqlEndpointConfig::new("http://localhost:7878/query".to_string())
+        };
+        let debug = format!("{:?}", config);
+        assert!(debug.contains("user"));
+        assert!(!debug.contains("my-password"));
+        assert!(!debug.contains("my-key"));
+        let debug = format!(
+            "{:?}",
+            SparqlEndpointAuth::Bearer(SecretString::from("my-token"))
+        );
+        assert!(!debug.contains("my-token"));
+    }
 }
@@ -1,9 +1,11 @@
 pub mod embedded_oxigraph;
 pub mod endpoint;
+pub mod native_oxigraph;
 
 use async_trait::async_trait;
 use embedded_oxigraph::EmbeddedOxigraphError;
 use endpoint::SparqlEndpointQueryExecutionError;
+use native_oxigraph::NativeOxigraphError;
 use sparesults::{
     QueryResultsFormat, QueryResultsParser, QuerySolution, SliceQueryResultsParserOutput,
 };
@@ -15,38 +17,47 @@ pub enum SparqlQueryError {
     EmbeddedOxigraphError(#[from] EmbeddedOxigraphError),
     #[error(transparent)]
     SparqlEndpointQueryExecutionError(#[from] SparqlEndpointQueryExecutionError),
+    #[error(transparent)]
+    NativeOxigraphError(#[from] NativeOxigraphError),
+}
+
+// Receives solutions one at a time as they are parsed, so that results need not be materialized
+pub trait SolutionSink: Send {
+    fn push(&mut self, solution: QuerySolution);
+}
+
+impl SolutionSink for Vec<QuerySolution> {
+    fn push(&mut self, solution: QuerySolution) {
+        Vec::push(self, solution);
+    }
 }
 
 #[async_trait]
 pub trait SparqlQueryable: Send + Sync {
-    async fn execute(&self, query: &Query) -> Result<Vec<QuerySolution>, SparqlQueryError>;
+    async fn execute(
+        &self,
+        query: &Query,
+        sink: &mut dyn SolutionSink,
+    ) -> Result<(), SparqlQueryError>;
 }
 
-fn parse_json_text(text: &str) -> Result<Vec<QuerySolution>, SparqlQueryError> {
-    let json_parser = QueryResultsParser::from_format(QueryResultsFormat::Json);
-    let parsed_results = json_parser.for_slice(text.as_bytes());
-    match parsed_results {
-        Ok(reader) => {
-            let mut solns = vec![];
-            if let SliceQueryResultsParserOutput::Solutions(solutions) = reader {
-                for s in solutions {
-                    match s {
-                        Ok(query_solution) => solns.push(query_solution),
-                        Err(syntax_error) => {
-                            return Err(SparqlEndpointQueryExecutionError::SolutionParseError(
-                                syntax_error,
-                            )
-                            .into())
-                        }
-                    }
-                }
-                Ok(solns)
-            } else {
-                Err(SparqlEndpointQueryExecutionError::WrongResultType.into())
-            }
-        }
-        Err(parse_error) => {
-            Err(SparqlEndpointQueryExecutionError::ResultsParseError(parse_error).into())
+fn parse_results_slice(
+    format: QueryResultsFormat,
+    slice: &[u8],
+    sink: &mut dyn SolutionSink,
+) -> Result<(), SparqlQueryError> {
+    let parser = QueryResultsParser::from_format(format);
+    let parsed_results = parser
+        .for_slice(slice)
+        .map_err(|e| SparqlEndpointQueryExecutionError::ResultsParseError(e.into()))?;
+    if let SliceQueryResultsParserOutput::Solutions(solutions) = parsed_results {
+        for s in solutions {
+            let query_solution =
+                s.map_err(|e| SparqlEndpointQueryExecutionError::SolutionParseError(e.into()))?;
+            sink.push(query_solution);
         }
+        Ok(())
+    } else {
+        Err(SparqlEndpointQueryExecutionError::WrongResultType.into())
     }
 }
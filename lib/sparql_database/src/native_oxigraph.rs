@@ -0,0 +1,200 @@
+use super::{SolutionSink, SparqlQueryError, SparqlQueryable};
+use async_trait::async_trait;
+use oxigraph::io::RdfFormat;
+use oxigraph::sparql::{EvaluationError, QueryResults};
+use oxigraph::store::{LoaderError, StorageError, Store};
+use spargebra::Query;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::task::JoinError;
+
+// Solutions evaluated ahead of the sink before evaluation waits
+const SOLUTION_CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Error)]
+pub enum NativeOxigraphError {
+    #[error(transparent)]
+    StorageError(#[from] StorageError),
+    #[error("Could not determine RDF format of file {0:?}")]
+    UnknownRDFFormat(PathBuf),
+    #[error("Error reading file {0:?}: {1}")]
+    ReadFileError(PathBuf, std::io::Error),
+    #[error("Error loading file {0:?}: {1}")]
+    LoadFileError(PathBuf, LoaderError),
+    #[error("Oxigraph evaluation error: {0}")]
+    EvaluationError(EvaluationError),
+    #[error("Wrong result type, expected solutions")]
+    WrongResultType,
+    #[error("Oxigraph evaluation task failed: {0}")]
+    EvaluationTaskError(JoinError),
+}
+
+// Oxigraph store queried directly from Rust, without going through Python.
+pub struct NativeOxigraph {
+    pub store: Store,
+}
+
+impl NativeOxigraph {
+    pub fn new(store: Store) -> NativeOxigraph {
+        NativeOxigraph { store }
+    }
+
+    pub fn from_files(paths: &[PathBuf]) -> Result<NativeOxigraph, NativeOxigraphError> {
+        let store = Store::new()?;
+        for p in paths {
+            load_file(&store, p)?;
+        }
+        Ok(NativeOxigraph { store })
+    }
+}
+
+fn load_file(store: &Store, path: &Path) -> Result<(), NativeOxigraphError> {
+    let format = path
+        .extension()
+        .and_then(|x| x.to_str())
+        .and_then(RdfFormat::from_extension)
+        .ok_or_else(|| NativeOxigraphError::UnknownRDFFormat(path.to_path_buf()))?;
+    let file =
+        File::open(path).map_err(|e| NativeOxigraphError::ReadFileError(path.to_path_buf(), e))?;
+    store
+        .load_from_reader(format, BufReader::new(file))
+        .map_err(|e| NativeOxigraphError::LoadFileError(path.to_path_buf(), e))
+}
+
+#[async_trait]
+impl SparqlQueryable for NativeOxigraph {
+    async fn execute(
+        &self,
+        query: &Query,
+        sink: &mut dyn SolutionSink,
+    ) -> Result<(), SparqlQueryError> {
+        // Evaluation is blocking, so it runs on a blocking thread that sends the solutions back.
+        // If the query is cancelled, the receiver is dropped and the evaluation stops.
+        let store = self.store.clone();
+        let query = query.to_string();
+        let (sender, mut receiver) = mpsc::channel(SOLUTION_CHANNEL_CAPACITY);
+        let evaluation = tokio::task::spawn_blocking(move || {
+            let results = store
+                .query(query.as_str())
+                .map_err(NativeOxigraphError::EvaluationError)?;
+            if let QueryResults::Solutions(solutions) = results {
+                for s in solutions {
+                    let s = s.map_err(NativeOxigraphError::EvaluationError)?;
+                    if sender.blocking_send(s).is_err() {
+                        break;
+                    }
+                }
+                Ok(())
+            } else {
+                Err(NativeOxigraphError::WrongResultType)
+            }
+        });
+        while let Some(s) = receiver.recv().await {
+            sink.push(s);
+        }
+        evaluation
+            .await
+            .map_err(NativeOxigraphError::EvaluationTaskError)??;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sparesults::QuerySolution;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const TURTLE: &str = r#"
+    @prefix ex: <http://example.org/> .
+    ex:myWidget1 ex:hasSensor ex:mySensor1 .
+    "#;
+    const NTRIPLES: &str =
+        "<http://example.org/myWidget2> <http://example.org/hasSensor> <http://example.org/mySensor2> .\n";
+
+    // The directory is removed when it is dropped at the end of the test
+    fn write_file(dir: &TempDir, name: &str, content: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    async fn execute(
+        store: &NativeOxigraph,
+        query: &str,
+    ) -> Result<Vec<QuerySolution>, SparqlQueryError> {
+        let query = Query::parse(query, None).unwrap();
+        let mut solutions = vec![];
+        store.execute(&query, &mut solutions).await?;
+        Ok(solutions)
+    }
+
+    #[tokio::test]
+    async fn test_from_files_loads_each_format() {
+        let dir = TempDir::new().unwrap();
+        let paths = vec![
+            write_file(&dir, "widgets.ttl", TURTLE),
+            write_file(&dir, "widgets.nt", NTRIPLES),
+        ];
+        let store = NativeOxigraph::from_files(&paths).unwrap();
+        let solutions = execute(
+            &store,
+            "SELECT ?w ?s WHERE { ?w <http://example.org/hasSensor> ?s }",
+        )
+        .await
+        .unwrap();
+        let mut widgets: Vec<_> = solutions
+            .iter()
+            .map(|s| s.get("w").unwrap().to_string())
+            .collect();
+        widgets.sort();
+        assert_eq!(
+            widgets,
+            vec![
+                "<http://example.org/myWidget1>",
+                "<http://example.org/myWidget2>"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_files_missing_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("missing.ttl");
+        let res = NativeOxigraph::from_files(&[path.clone()]);
+        assert!(matches!(res, Err(NativeOxigraphError::ReadFileError(p, _)) if p == path));
+    }
+
+    #[test]
+    fn test_from_files_unknown_extension() {
+        let dir = TempDir::new().unwrap();
+        let path = write_file(&dir, "widgets.unknown", TURTLE);
+        let res = NativeOxigraph::from_files(&[path.clone()]);
+        assert!(matches!(res, Err(NativeOxigraphError::UnknownRDFFormat(p)) if p == path));
+    }
+
+    #[test]
+    fn test_from_files_invalid_content() {
+        let dir = TempDir::new().unwrap();
+        let path = write_file(&dir, "invalid.ttl", "this is not turtle");
+        let res = NativeOxigraph::from_files(&[path.clone()]);
+        assert!(matches!(res, Err(NativeOxigraphError::LoadFileError(p, _)) if p == path));
+    }
+
+    #[tokio::test]
+    async fn test_execute_non_select_query() {
+        let dir = TempDir::new().unwrap();
+        let store = NativeOxigraph::from_files(&[write_file(&dir, "ask.ttl", TURTLE)]).unwrap();
+        let res = execute(&store, "ASK { ?s ?p ?o }").await;
+        assert!(matches!(
+            res,
+            Err(SparqlQueryError::NativeOxigraphError(
+                NativeOxigraphError::WrongResultType
+            ))
+        ));
+    }
+}
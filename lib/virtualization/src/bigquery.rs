@@ -11,12 +11,16 @@ use reqwest::Url;
 use spargebra::algebra::{AggregateExpression, Expression, OrderExpression};
 use spargebra::term::TermPattern;
 use std::collections::{HashMap, HashSet};
+use tokio::sync::OnceCell;
+use tokio_util::sync::CancellationToken;
 use virtualized_query::pushdown_setting::{all_pushdowns, PushdownSetting};
 use virtualized_query::{GroupedVirtualizedQuery, VirtualizedQuery};
 
 pub struct VirtualizedBigQueryDatabase {
     gcp_sa_key: String,
     resource_sql_map: Py<PyDict>,
+    // Client and project id, created on first query and reused
+    client: OnceCell<(Client, String)>,
 }
 
 impl VirtualizedBigQueryDatabase {
@@ -24,18 +28,25 @@ impl VirtualizedBigQueryDatabase {
         VirtualizedBigQueryDatabase {
             gcp_sa_key,
             resource_sql_map,
+            client: OnceCell::new(),
         }
     }
 }
 
 impl VirtualizedBigQueryDatabase {
     pub fn pushdown_settings() -> HashSet<PushdownSetting> {
-        all_pushdowns()
+        let mut pushdowns = all_pushdowns();
+        pushdowns.insert(PushdownSetting::GapFill);
+        pushdowns.insert(PushdownSetting::Window);
+        pushdowns.insert(PushdownSetting::Extend);
+        pushdowns.insert(PushdownSetting::Having);
+        pushdowns
     }
 
     pub async fn query(
         &self,
         vq: &VirtualizedQuery,
+        cancellation_token: &CancellationToken,
     ) -> Result<EagerSolutionMappings, ChrontextError> {
         if rustls::crypto::CryptoProvider::get_default().is_none() {
             rustls::crypto::aws_lc_rs::default_provider()
@@ -45,48 +56,13 @@ impl VirtualizedBigQueryDatabase {
         let mut rename_map = HashMap::new();
         let new_vq = rename_non_alpha_vars(vq.clone(), &mut rename_map);
         let query_string = translate_sql(&new_vq, &self.resource_sql_map, "bigquery")?;
-        // The following code is based on https://github.com/DataTreehouse/connector-x/blob/main/connectorx/src/sources/bigquery/mod.rs
-        // Last modified in commit: 8134d42
-        // It has been simplified and made async
-        // Connector-x has the following license:
-        // MIT License
-        //
-        // Copyright (c) 2021 SFU Database Group
-        //
-        // Permission is hereby granted, free of charge, to any person obtaining a copy
-        // of this software and associated documentation files (the "Software"), to deal
-        // in the Software without restriction, including without limitation the rights
-        // to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
-        // copies of the Software, and to permit persons to whom the Software is
-        // furnished to do so, subject to the following conditions:
-        //
-        // The above copyright notice and this permission notice shall be included in all
-        // copies or substantial portions of the Software.
-        //
-        // THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
-        // IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
-        // FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
-        // AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
-        // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
-        // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
-        // SOFTWARE.
+        let (client, project_id) = self
+            .client
+            .get_or_try_init(|| create_client(&self.gcp_sa_key))
+            .await?;
 
-        let url = Url::parse(&self.gcp_sa_key)?;
-        let sa_key_path = url.path();
-        let client = Client::from_service_account_key_file(sa_key_path).await?;
-
-        let auth_data = std::fs::read_to_string(sa_key_path)?;
-        let auth_json: serde_json::Value = serde_json::from_str(&auth_data)?;
-        let project_id = auth_json
-            .get("project_id")
-            .unwrap()
-            .as_str()
-            .unwrap()
-            .to_string();
-        //End copied code.
-
-        let ex = BigQueryExecutor::new(client, project_id, query_string);
-        let lf = ex.execute_query().await?;
+        let ex = BigQueryExecutor::new(client.clone(), project_id.clone(), query_string);
+        let lf = ex.execute_query(cancellation_token).await?;
         let mut df = lf.collect().unwrap();
         for (k, v) in rename_map {
             let v_smallstr = PlSmallStr::from_str(v.as_str());
@@ -99,6 +75,49 @@ impl VirtualizedBigQueryDatabase {
     }
 }
 
+async fn create_client(gcp_sa_key: &str) -> Result<(Client, String), ChrontextError> {
+    // The following code is based on https://github.com/DataTreehouse/connector-x/blob/main/connectorx/src/sources/bigquery/mod.rs
+    // Last modified in commit: 8134d42
+    // It has been simplified and made async
+    // Connector-x has the following license:
+    // MIT License
+    //
+    // Copyright (c) 2021 SFU Database Group
+    //
+    // Permission is hereby granted, free of charge, to any person obtaining a copy
+    // of this software and associated documentation files (the "Software"), to deal
+    // in the Software without restriction, including without limitation the rights
+    // to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+    // copies of the Software, and to permit persons to whom the Software is
+    // furnished to do so, subject to the following conditions:
+    //
+    // The above copyright notice and this permission notice shall be included in all
+    // copies or substantial portions of the Software.
+    //
+    // THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    // IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    // FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    // AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    // LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+    // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+    // SOFTWARE.
+
+    let url = Url::parse(gcp_sa_key)?;
+    let sa_key_path = url.path();
+    let client = Client::from_service_account_key_file(sa_key_path).await?;
+
+    let auth_data = std::fs::read_to_string(sa_key_path)?;
+    let auth_json: serde_json::Value = serde_json::from_str(&auth_data)?;
+    let project_id = auth_json
+        .get("project_id")
+        .unwrap()
+        .as_str()
+        .unwrap()
+        .to_string();
+    //End copied code.
+    Ok((client, project_id))
+}
+
 fn rename_non_alpha_vars(
     vq: VirtualizedQuery,
     rename_map: &mut HashMap<Variable, Variable>,
@@ -170,6 +189,7 @@ fn rename_non_alpha_vars(
             vq,
             by,
             aggregations,
+            gap_fill,
         }) => {
             let new_vq = rename_non_alpha_vars(*vq, rename_map);
             let mut new_by = vec![];
@@ -191,17 +211,47 @@ fn rename_non_alpha_vars(
                 rename_non_alpha_agg_expr_vars(&mut agg_expr, rename_map);
                 new_aggregations.push((v, agg_expr));
             }
+            let gap_fill = gap_fill.map(|mut gap_fill| {
+                if let Some(v) = rename_non_alpha_var(&gap_fill.bucket_variable, rename_map) {
+                    gap_fill.bucket_variable = v;
+                }
+                for (v, _) in &mut gap_fill.methods {
+                    if let Some(new_v) = rename_non_alpha_var(v, rename_map) {
+                        *v = new_v;
+                    }
+                }
+                gap_fill
+            });
             VirtualizedQuery::Grouped(GroupedVirtualizedQuery {
                 context,
                 vq: Box::new(new_vq),
                 by: new_by,
                 aggregations: new_aggregations,
+                gap_fill,
             })
         }
         VirtualizedQuery::Sliced(vq, offset, limit) => {
             let new_vq = rename_non_alpha_vars(*vq, rename_map);
             VirtualizedQuery::Sliced(Box::new(new_vq), offset, limit)
         }
+        VirtualizedQuery::Windowed(vq, v, mut window) => {
+            let new_vq = rename_non_alpha_vars(*vq, rename_map);
+            let v = if let Some(v) = rename_non_alpha_var(&v, rename_map) {
+                v
+            } else {
+                v
+            };
+            for w in [
+                &mut window.value,
+                &mut window.timestamp,
+                &mut window.partition_by,
+            ] {
+                if let Some(new_w) = rename_non_alpha_var(w, rename_map) {
+                    *w = new_w;
+                }
+            }
+            VirtualizedQuery::Windowed(Box::new(new_vq), v, window)
+        }
     }
 }
 
@@ -27,4 +27,30 @@ pub enum ChrontextError {
     BigQueryKeyPathParseError(#[from] ParseError),
     #[error(transparent)]
     BigQueryError(#[from] BQError),
+    #[error("Virtualized query was cancelled")]
+    Cancelled,
+}
+
+#[derive(Error, Debug)]
+pub enum TemplateExpansionError {
+    #[error("Invalid template library: {0}")]
+    InvalidTemplateLibrary(String),
+    #[error("Template `{0}` was not found among the provided templates")]
+    TemplateNotFound(String),
+    #[error("Template `{template}` expects {expected} arguments but was given {actual}")]
+    WrongNumberOfArguments {
+        template: String,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("List expansion in instance of template `{0}` is not supported in resource templates")]
+    ListExpansionNotSupported(String),
+    #[error("Template `{0}` is defined in terms of itself")]
+    CyclicTemplate(String),
+    #[error("Parameter `{parameter}` of template `{template}` does not allow blank nodes")]
+    BlankArgument { template: String, parameter: String },
+    #[error("Argument to parameter `{parameter}` of template `{template}` has the wrong type")]
+    ArgumentTypeMismatch { template: String, parameter: String },
+    #[error("Template `{0}` gives ottr:none to a triple, use a default value instead")]
+    NoneInTriple(String),
 }
@@ -0,0 +1,187 @@
+use crate::errors::TemplateExpansionError;
+use oxrdf::vocab::{rdfs, xsd};
+use oxrdf::Variable;
+use representation::BaseRDFNodeType;
+use std::collections::HashMap;
+use templates::ast::{
+    ConstantTerm, ConstantTermOrList, Instance, PType, Parameter, Statement, StottrDocument,
+    StottrTerm, Template,
+};
+use templates::constants::OTTR_TRIPLE;
+use templates::dataset::TemplateDataset;
+
+pub fn expand_resource_templates(
+    resources: HashMap<String, Template>,
+    templates: &Vec<Template>,
+) -> Result<HashMap<String, Template>, TemplateExpansionError> {
+    // The library is validated and typed by the templates crate before it is used for expansion
+    let library = TemplateDataset::from_documents(vec![StottrDocument {
+        directives: vec![],
+        statements: templates.iter().cloned().map(Statement::Template).collect(),
+    }])
+    .map_err(|e| TemplateExpansionError::InvalidTemplateLibrary(e.to_string()))?;
+    let mut expanded = HashMap::new();
+    for (k, mut t) in resources {
+        let mut counter = 0;
+        let mut pattern_list = vec![];
+        let mut stack = vec![t.signature.template_name.as_str().to_string()];
+        for i in &t.pattern_list {
+            expand_instance(i, &library, &mut stack, &mut counter, &mut pattern_list)?;
+        }
+        t.pattern_list = pattern_list;
+        expanded.insert(k, t);
+    }
+    Ok(expanded)
+}
+
+fn expand_instance(
+    instance: &Instance,
+    library: &TemplateDataset,
+    stack: &mut Vec<String>,
+    counter: &mut usize,
+    out: &mut Vec<Instance>,
+) -> Result<(), TemplateExpansionError> {
+    let name = instance.template_name.as_str();
+    if instance.list_expander.is_some() || instance.argument_list.iter().any(|a| a.list_expand) {
+        return Err(TemplateExpansionError::ListExpansionNotSupported(
+            name.to_string(),
+        ));
+    }
+    if name == OTTR_TRIPLE {
+        // Optional arguments that were not given cannot be part of a virtualized triple
+        if instance.argument_list.iter().any(|a| is_none(&a.term)) {
+            return Err(TemplateExpansionError::NoneInTriple(
+                stack.last().unwrap().to_string(),
+            ));
+        }
+        out.push(instance.clone());
+        return Ok(());
+    }
+    if stack.iter().any(|x| x == name) {
+        return Err(TemplateExpansionError::CyclicTemplate(name.to_string()));
+    }
+    let Some(template) = library.get(name) else {
+        return Err(TemplateExpansionError::TemplateNotFound(name.to_string()));
+    };
+    let parameters = &template.signature.parameter_list;
+    if parameters.len() != instance.argument_list.len() {
+        return Err(TemplateExpansionError::WrongNumberOfArguments {
+            template: name.to_string(),
+            expected: parameters.len(),
+            actual: instance.argument_list.len(),
+        });
+    }
+    let mut substitution = HashMap::new();
+    for (p, a) in parameters.iter().zip(instance.argument_list.iter()) {
+        let term = match (&a.term, &p.default_value) {
+            // As in OTTR, a parameter given ottr:none takes its default value
+            (
+                StottrTerm::ConstantTerm(ConstantTermOrList::ConstantTerm(ConstantTerm::None)),
+                Some(default_value),
+            ) => StottrTerm::ConstantTerm(default_value.constant_term.clone()),
+            // ... and an instance giving ottr:none to a non-optional parameter is ignored
+            (
+                StottrTerm::ConstantTerm(ConstantTermOrList::ConstantTerm(ConstantTerm::None)),
+                None,
+            ) => {
+                if !p.optional {
+                    return Ok(());
+                }
+                a.term.clone()
+            }
+            _ => {
+                check_argument(name, p, &a.term)?;
+                a.term.clone()
+            }
+        };
+        substitution.insert(p.variable.clone(), term);
+    }
+
+    *counter += 1;
+    let instance_number = *counter;
+    stack.push(name.to_string());
+    for inner in &template.pattern_list {
+        let mut inner = inner.clone();
+        for a in inner.argument_list.iter_mut() {
+            a.term = substitute_term(&a.term, &mut substitution, instance_number);
+        }
+        expand_instance(&inner, library, stack, counter, out)?;
+    }
+    stack.pop();
+    Ok(())
+}
+
+// Variables that are not parameters of the called template are local to each instance,
+// so they are given a fresh name to avoid clashes with the caller.
+fn substitute_term(
+    term: &StottrTerm,
+    substitution: &mut HashMap<Variable, StottrTerm>,
+    counter: usize,
+) -> StottrTerm {
+    match term {
+        StottrTerm::Variable(v) => substitution
+            .entry(v.clone())
+            .or_insert_with(|| {
+                StottrTerm::Variable(Variable::new_unchecked(format!(
+                    "{}_expanded_{}",
+                    v.as_str(),
+                    counter
+                )))
+            })
+            .clone(),
+        StottrTerm::ConstantTerm(_) => term.clone(),
+        StottrTerm::List(l) => StottrTerm::List(
+            l.iter()
+                .map(|x| substitute_term(x, substitution, counter))
+                .collect(),
+        ),
+    }
+}
+
+fn is_none(term: &StottrTerm) -> bool {
+    matches!(
+        term,
+        StottrTerm::ConstantTerm(ConstantTermOrList::ConstantTerm(ConstantTerm::None))
+    )
+}
+
+// Constant arguments are checked against the non-blank flag and the type of the parameter.
+// Variables are parameters of the calling template, and were checked when it was called.
+fn check_argument(
+    template: &str,
+    parameter: &Parameter,
+    term: &StottrTerm,
+) -> Result<(), TemplateExpansionError> {
+    let StottrTerm::ConstantTerm(ConstantTermOrList::ConstantTerm(c)) = term else {
+        return Ok(());
+    };
+    if parameter.non_blank && matches!(c, ConstantTerm::BlankNode(_)) {
+        return Err(TemplateExpansionError::BlankArgument {
+            template: template.to_string(),
+            parameter: parameter.variable.as_str().to_string(),
+        });
+    }
+    let type_matches = match &parameter.ptype {
+        Some(PType::Basic(BaseRDFNodeType::IRI)) => matches!(c, ConstantTerm::Iri(_)),
+        Some(PType::Basic(BaseRDFNodeType::BlankNode)) => matches!(c, ConstantTerm::BlankNode(_)),
+        Some(PType::Basic(BaseRDFNodeType::Literal(dt))) => match c {
+            ConstantTerm::Literal(l) => {
+                dt.as_ref() == rdfs::LITERAL
+                    || l.data_type_iri
+                        .as_ref()
+                        .map(|x| x.as_ref())
+                        .unwrap_or(xsd::STRING)
+                        == dt.as_ref()
+            }
+            _ => false,
+        },
+        _ => true,
+    };
+    if !type_matches {
+        return Err(TemplateExpansionError::ArgumentTypeMismatch {
+            template: template.to_string(),
+            parameter: parameter.variable.as_str().to_string(),
+        });
+    }
+    Ok(())
+}
@@ -1,9 +1,11 @@
 pub mod errors;
+pub mod expansion;
 pub mod python;
 
 pub mod bigquery;
 use crate::bigquery::VirtualizedBigQueryDatabase;
-use crate::errors::ChrontextError;
+use crate::errors::{ChrontextError, TemplateExpansionError};
+use crate::expansion::expand_resource_templates;
 #[cfg(feature = "opcua")]
 use crate::opcua::VirtualizedOPCUADatabase;
 use crate::python::VirtualizedPythonDatabase;
@@ -15,6 +17,7 @@ use representation::RDFNodeType;
 use std::collections::{HashMap, HashSet};
 use templates::ast::{ConstantTerm, ConstantTermOrList, StottrTerm, Template};
 use templates::constants::OTTR_TRIPLE;
+use tokio_util::sync::CancellationToken;
 use virtualized_query::pushdown_setting::PushdownSetting;
 use virtualized_query::{VirtualizedQuery, ID_VARIABLE_NAME};
 
@@ -24,6 +27,15 @@ pub struct Virtualization {
 }
 
 impl Virtualization {
+    pub fn new(
+        resources: HashMap<String, Template>,
+        templates: &Vec<Template>,
+    ) -> Result<Virtualization, TemplateExpansionError> {
+        Ok(Virtualization {
+            resources: expand_resource_templates(resources, templates)?,
+        })
+    }
+
     pub fn get_virtualized_iris(&self) -> HashSet<NamedNode> {
         let mut nns = HashSet::new();
         for t in self.resources.values() {
@@ -85,14 +97,20 @@ impl VirtualizedDatabase {
     pub async fn query(
         &self,
         vq: &VirtualizedQuery,
+        cancellation_token: &CancellationToken,
     ) -> Result<EagerSolutionMappings, ChrontextError> {
+        if cancellation_token.is_cancelled() {
+            return Err(ChrontextError::Cancelled);
+        }
         match self {
             VirtualizedDatabase::VirtualizedPythonDatabase(pyvdb) => {
                 let df = pyvdb.query(vq).map_err(ChrontextError::from)?;
                 let rdf_node_types = get_datatype_map(&df);
                 Ok(EagerSolutionMappings::new(df, rdf_node_types))
             }
-            VirtualizedDatabase::VirtualizedBigQueryDatabase(q) => q.query(vq).await,
+            VirtualizedDatabase::VirtualizedBigQueryDatabase(q) => {
+                q.query(vq, cancellation_token).await
+            }
         }
     }
 }
@@ -39,7 +39,26 @@ impl VirtualizedPythonDatabase {
 
 impl VirtualizedPythonDatabase {
     pub fn pushdown_settings(&self) -> HashSet<PushdownSetting> {
-        all_pushdowns()
+        let mut pushdowns = all_pushdowns();
+        if self.resource_sql_map.is_some() {
+            pushdowns.insert(PushdownSetting::Window);
+            pushdowns.insert(PushdownSetting::Extend);
+            pushdowns.insert(PushdownSetting::Having);
+            match self.sql_dialect.as_deref() {
+                Some("duckdb") => {
+                    pushdowns.insert(PushdownSetting::AsOfJoin);
+                }
+                Some("bigquery") => {
+                    pushdowns.insert(PushdownSetting::GapFill);
+                }
+                // TimescaleDB's time_bucket_gapfill needs an explicit time range
+                Some("timescale") => {
+                    pushdowns.insert(PushdownSetting::GapFillInTimeRange);
+                }
+                _ => {}
+            }
+        }
+        pushdowns
     }
 
     pub fn query(&self, vq: &VirtualizedQuery) -> PyResult<DataFrame> {
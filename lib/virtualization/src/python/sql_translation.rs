@@ -1,23 +1,41 @@
 pub const PYTHON_CODE: &str = r#"
-from datetime import datetime
+from datetime import datetime, timedelta, timezone
 from typing import Dict, Literal, Any, List, Union
 
 import sqlalchemy.types as types
 from sqlalchemy.dialects import postgresql
+from sqlalchemy.ext.compiler import compiles
 from sqlalchemy.sql.base import ColumnCollection
-from sqlalchemy.sql.functions import GenericFunction
+from sqlalchemy.sql.selectable import Join
+from sqlalchemy.sql.functions import GenericFunction, FunctionElement
 from sqlalchemy_bigquery.base import BigQueryDialect
 from databricks.sqlalchemy import DatabricksDialect
 
-from chrontext.vq import Expression, VirtualizedQuery, AggregateExpression, XSDDuration
+from chrontext.vq import Expression, VirtualizedQuery, AggregateExpression, XSDDuration, Window
 from sqlalchemy import ColumnElement, Column, Table, MetaData, Select, select, literal, DateTime, values, cast, \
-    BigInteger, CompoundSelect, and_, literal_column, case, func, TIMESTAMP, text
+    BigInteger, CompoundSelect, and_, literal_column, case, func, TIMESTAMP, text, extract, Float, String
 
 XSD = "http://www.w3.org/2001/XMLSchema#"
 XSD_INTEGER = "http://www.w3.org/2001/XMLSchema#integer"
 XSD_DURATION = "http://www.w3.org/2001/XMLSchema#duration"
 FLOOR_DATE_TIME_TO_SECONDS_INTERVAL = "https://github.com/DataTreehouse/chrontext#FloorDateTimeToSecondsInterval"
 DATE_BIN = "https://github.com/DataTreehouse/chrontext#dateBin"
+GAP_FILL_AGGREGATES = {
+    "https://github.com/DataTreehouse/chrontext#interpolateLinear",
+    "https://github.com/DataTreehouse/chrontext#locf",
+    "https://github.com/DataTreehouse/chrontext#fillNull",
+}
+# Columns used to keep the buckets filled by time_bucket_gapfill between the first and last bucket of each group
+GAP_FILL_OBSERVED = "gap_fill_observed"
+GAP_FILL_FIRST = "gap_fill_first"
+GAP_FILL_LAST = "gap_fill_last"
+EPOCH = datetime(1970, 1, 1, tzinfo=timezone.utc)
+MEDIAN = "https://github.com/DataTreehouse/chrontext#median"
+PERCENTILE = "https://github.com/DataTreehouse/chrontext#percentile"
+STDDEV = "https://github.com/DataTreehouse/chrontext#stddev"
+VARIANCE = "https://github.com/DataTreehouse/chrontext#variance"
+FIRST = "https://github.com/DataTreehouse/chrontext#first"
+LAST = "https://github.com/DataTreehouse/chrontext#last"
 
 import warnings
 
@@ -28,14 +46,64 @@ with warnings.catch_warnings():
         package = "bq"
         inherit_cache = True
 
-def translate_sql(vq: VirtualizedQuery, dialect: Literal["bigquery", "postgres", "databricks"],
+class AsOfJoin(Join):
+    inherit_cache = True
+
+@compiles(AsOfJoin)
+def compile_as_of_join(element, compiler, **kw):
+    kw["asfrom"] = True
+    left = compiler.process(element.left, **kw)
+    right = compiler.process(element.right, **kw)
+    onclause = compiler.process(element.onclause, **kw)
+    return f"{left} ASOF JOIN {right} ON {onclause}"
+
+class range_window(FunctionElement):
+    inherit_cache = True
+
+# SQLAlchemy only supports integer offsets in window frames
+@compiles(range_window)
+def compile_range_window(element, compiler, **kw):
+    aggregate, partition_by, order_by, preceding = [compiler.process(c, **kw) for c in element.clauses]
+    return f"{aggregate} OVER (PARTITION BY {partition_by} ORDER BY {order_by} RANGE BETWEEN {preceding} PRECEDING AND CURRENT ROW)"
+
+class approx_quantile(FunctionElement):
+    inherit_cache = True
+
+# BigQuery has no exact percentiles, the 1000-quantiles are the closest approximation
+@compiles(approx_quantile)
+def compile_approx_quantile(element, compiler, **kw):
+    expression, offset = list(element.clauses)
+    return f"APPROX_QUANTILES({compiler.process(expression, **kw)}, 1000)[OFFSET({compiler.process(offset, **kw)})]"
+
+class any_value_having(FunctionElement):
+    inherit_cache = True
+
+@compiles(any_value_having)
+def compile_any_value_having(element, compiler, **kw):
+    expression, timestamp, having = list(element.clauses)
+    return f"ANY_VALUE({compiler.process(expression, **kw)} HAVING {compiler.process(having, **kw)} {compiler.process(timestamp, **kw)})"
+
+def epoch_micros(l: Any) -> int:
+    native = l.to_native()
+    if native.tzinfo is None:
+        native = native.replace(tzinfo=timezone.utc)
+    return (native - EPOCH) // timedelta(microseconds=1)
+
+def translate_sql(vq: VirtualizedQuery, dialect: Literal["bigquery", "postgres", "databricks", "duckdb", "timescale"],
                   resource_sql_map: Dict[str, Any]) -> str:
-    mapper = SPARQLMapper(dialect, resource_sql_map)
+    # DuckDB accepts the Postgres dialect, and additionally supports ASOF JOIN
+    if dialect == "duckdb":
+        mapper = SPARQLMapper("postgres", resource_sql_map, as_of_join=True)
+    # TimescaleDB accepts the Postgres dialect, and additionally fills gaps with time_bucket_gapfill
+    elif dialect == "timescale":
+        mapper = SPARQLMapper("postgres", resource_sql_map, time_bucket_gapfill=True)
+    else:
+        mapper = SPARQLMapper(dialect, resource_sql_map)
     q = mapper.virtualized_query_to_sql(vq)
     match dialect:
         case "bigquery":
             use_dialect = BigQueryDialect()
-        case "postgres":
+        case "postgres" | "duckdb" | "timescale":
             use_dialect = postgresql.dialect()
         case "databricks":
             use_dialect = DatabricksDialect()
@@ -45,9 +113,13 @@ def translate_sql(vq: VirtualizedQuery, dialect: Literal["bigquery", "postgres",
 
 class SPARQLMapper:
     def __init__(self,
-                 dialect: Literal["bigquery", "postgres"],
-                 resource_sql_map: Dict[str, Union[Table, CompoundSelect]]):
+                 dialect: Literal["bigquery", "postgres", "databricks"],
+                 resource_sql_map: Dict[str, Union[Table, CompoundSelect]],
+                 as_of_join: bool = False,
+                 time_bucket_gapfill: bool = False):
         self.dialect = dialect
+        self.as_of_join = as_of_join
+        self.time_bucket_gapfill = time_bucket_gapfill
         self.resource_sql_map = resource_sql_map
         self.counter = 0
 
@@ -58,6 +130,9 @@ class SPARQLMapper:
             case "Filtered":
                 sql_quer = self.virtualized_query_to_sql(query.query)
                 filter_expr = self.expression_to_sql(query.filter, sql_quer.selected_columns)
+                if query.query.type_name() == "Grouped":
+                    # Filters on aggregates, from SPARQL HAVING
+                    return sql_quer.having(filter_expr)
                 filtered = sql_quer.filter(filter_expr)
                 return filtered
 
@@ -77,7 +152,11 @@ class SPARQLMapper:
                     if self.dialect == "bigquery":
                         structs = []
                         for (id, group) in query.id_grouping_tuples:
-                            structs.append(f"STRUCT('{id}' as id, {group} as {query.grouping_column_name})")
+                            if isinstance(id, str):
+                                # BigQuery string literals escape quotes and backslashes with a backslash
+                                escaped = id.replace("\\", "\\\\").replace("'", "\\'")
+                                id = f"'{escaped}'"
+                            structs.append(f"STRUCT({id} as id, {group} as {query.grouping_column_name})")
                         values_sub = func.bq.unnest(literal_column(f"[{', '.join(structs)}]")).table_valued(
                             Column("id"),
                             Column(query.grouping_column_name)
@@ -151,6 +230,8 @@ class SPARQLMapper:
                 ).group_by(
                     *by
                 )
+                if query.gap_fill is not None:
+                    sql_quer = self.gap_fill_to_sql(sql_quer, query)
                 return sql_quer
 
             case "ExpressionAs":
@@ -163,12 +244,18 @@ class SPARQLMapper:
                 for q in query.queries:
                     sql_quer = self.virtualized_query_to_sql(q)
                     sql_queries.append(sql_quer)
+                as_of_synchronizers = query.as_of_synchronizers
+                if self.as_of_join and len(as_of_synchronizers) > 0:
+                    # The series with the left timestamps of an ASOF JOIN must be on its left side
+                    left_names = set(s.left.name for s in as_of_synchronizers)
+                    sql_queries.sort(key=lambda q: any(c in left_names for c in q.selected_columns.keys()))
                 cols_keys = set()
                 cols = []
                 out_sql_quer = sql_queries.pop().subquery(self.inner_name())
                 for c in out_sql_quer.columns.keys():
                     cols_keys.add(c)
                     cols.append(out_sql_quer.columns[c].label(c))
+                tolerance_conditions = []
                 for sql_quer in sql_queries:
                     on = None
                     sql_quer = sql_quer.subquery(self.inner_name())
@@ -182,10 +269,22 @@ class SPARQLMapper:
                                 on = on & new_on
                             else:
                                 on = new_on
-
-                    out_sql_quer = out_sql_quer.join(sql_quer, onclause=on)
+                    as_of = self.as_of_condition(as_of_synchronizers, out_sql_quer.columns, sql_quer.columns)
+                    if as_of is not None:
+                        as_of_on, tolerance_condition = as_of
+                        if on is not None:
+                            on = on & as_of_on
+                        else:
+                            on = as_of_on
+                        if tolerance_condition is not None:
+                            tolerance_conditions.append(tolerance_condition)
+                        out_sql_quer = AsOfJoin(out_sql_quer, sql_quer, onclause=on)
+                    else:
+                        out_sql_quer = out_sql_quer.join(sql_quer, onclause=on)
 
                 out_sql_quer = select(*cols).select_from(out_sql_quer)
+                for t in tolerance_conditions:
+                    out_sql_quer = out_sql_quer.where(t)
                 return out_sql_quer
             case "Ordered":
                 sql_quer = self.virtualized_query_to_sql(query.query)
@@ -198,6 +297,13 @@ class SPARQLMapper:
                     sql_quer = sql_quer.order_by(sql_expr_order)
                 return sql_quer
 
+            case "Windowed":
+                sql_quer = self.virtualized_query_to_sql(query.query)
+                sql_expression = self.window_to_sql(query.window, sql_quer.selected_columns)
+                sql_quer = sql_quer.add_columns(sql_expression.label(query.variable.name)).subquery(self.inner_name())
+                # Conditions on the windowed query are applied after the window is computed
+                return select(*[sql_quer.columns[c].label(c) for c in sql_quer.columns.keys()]).select_from(sql_quer)
+
             case "Sliced":
                 sql_quer = self.virtualized_query_to_sql(query.query)
                 if query.offset > 0:
@@ -213,6 +319,21 @@ class SPARQLMapper:
             columns: ColumnCollection[str, ColumnElement],
     ) -> ColumnElement:
         sql_expression = self.expression_to_sql(aggregate_expression.expression, columns)
+        custom_name = aggregate_expression.name.strip("<>")
+        if custom_name in GAP_FILL_AGGREGATES:
+            # The gaps between the buckets are filled afterwards
+            return func.avg(sql_expression)
+        elif custom_name == MEDIAN:
+            return self.percentile_to_sql(sql_expression, 0.5)
+        elif custom_name.startswith(PERCENTILE):
+            return self.percentile_to_sql(sql_expression, float(custom_name[len(PERCENTILE):]) / 100)
+        elif custom_name == STDDEV:
+            return func.stddev_samp(sql_expression)
+        elif custom_name == VARIANCE:
+            return func.var_samp(sql_expression)
+        elif custom_name in (FIRST, LAST):
+            timestamp = columns[aggregate_expression.timestamp.name]
+            return self.first_or_last_to_sql(sql_expression, timestamp, custom_name == FIRST)
         match aggregate_expression.name:
             case "MIN":
                 return func.min(sql_expression)
@@ -235,6 +356,66 @@ class SPARQLMapper:
                 print(aggregate_expression.name)
                 assert False
 
+    def window_to_sql(self, window: Window, columns: ColumnCollection[str, ColumnElement]) -> ColumnElement:
+        value = columns[window.value.name]
+        timestamp = columns[window.timestamp.name]
+        partition_by = columns[window.partition_by.name]
+
+        def over(e):
+            return e.over(partition_by=partition_by, order_by=timestamp)
+
+        match window.function:
+            case "lag":
+                return over(func.lag(value, window.offset))
+            case "lead":
+                return over(func.lead(value, window.offset))
+            case "rolling_mean" | "rolling_sum":
+                if window.function == "rolling_mean":
+                    aggregate = func.avg(cast(value, Float))
+                else:
+                    aggregate = func.sum(value)
+                micros = round(window.window_size * 1_000_000)
+                if self.dialect == "postgres":
+                    return range_window(aggregate, partition_by, timestamp,
+                                        literal_column(f"INTERVAL '{micros} microseconds'"))
+                else:
+                    # BigQuery only supports numeric ranges
+                    return range_window(aggregate, partition_by, func.unix_micros(timestamp),
+                                        literal_column(str(micros)))
+            case "cumulative_sum":
+                return func.sum(value).over(partition_by=partition_by, order_by=timestamp, rows=(None, 0))
+            case "delta":
+                return value - over(func.lag(value, 1))
+            case "derivative":
+                if self.dialect == "postgres":
+                    seconds = extract("epoch", timestamp) - over(func.lag(extract("epoch", timestamp), 1))
+                else:
+                    seconds = (func.unix_micros(timestamp) - over(func.lag(func.unix_micros(timestamp), 1))) / 1_000_000
+                return (cast(value, Float) - over(func.lag(cast(value, Float), 1))) / seconds
+            case "row_number":
+                return over(func.row_number())
+            case "reverse_row_number":
+                return func.row_number().over(partition_by=partition_by, order_by=timestamp.desc())
+
+    def percentile_to_sql(self, sql_expression: ColumnElement, percentile: float) -> ColumnElement:
+        if self.dialect == "bigquery":
+            return approx_quantile(sql_expression, literal(round(percentile * 1000)))
+        else:
+            return func.percentile_cont(percentile).within_group(sql_expression)
+
+    def first_or_last_to_sql(self,
+                             sql_expression: ColumnElement,
+                             timestamp: ColumnElement,
+                             first: bool) -> ColumnElement:
+        match self.dialect:
+            case "bigquery":
+                return any_value_having(sql_expression, timestamp, literal_column("MIN" if first else "MAX"))
+            case "databricks":
+                return func.min_by(sql_expression, timestamp) if first else func.max_by(sql_expression, timestamp)
+            case _:
+                order_by = timestamp if first else timestamp.desc()
+                return postgresql.array_agg(postgresql.aggregate_order_by(sql_expression, order_by))[1]
+
     def expression_to_sql(
             self,
             expression: Expression,
@@ -382,6 +563,132 @@ class SPARQLMapper:
         print(function)
         assert False
 
+    def gap_fill_to_sql(self, sql_quer: Select, query: VirtualizedQuery) -> Select:
+        gap_fill = query.gap_fill
+        if self.dialect == "bigquery":
+            return self.bigquery_gap_fill_to_sql(sql_quer, query)
+        elif self.time_bucket_gapfill and gap_fill.start is not None and gap_fill.end is not None:
+            return self.time_bucket_gapfill_to_sql(sql_quer, query)
+        else:
+            # The gaps are filled by chrontext
+            return sql_quer
+
+    def bigquery_gap_fill_to_sql(self, sql_quer: Select, query: VirtualizedQuery) -> Select:
+        gap_fill = query.gap_fill
+        dialect = BigQueryDialect()
+        bucket = gap_fill.bucket_variable.name
+        partitioning_columns = [c.name for c in query.by if c.name != bucket]
+        methods = {v.name: m for (v, m) in gap_fill.methods}
+
+        # GAP_FILL is given the names of the columns as strings
+        def string_literal(s: str) -> str:
+            return str(literal(s, String).compile(dialect=dialect, compile_kwargs={"literal_binds": True}))
+
+        # Aggregations without a method are null in the added buckets
+        value_columns = [
+            f"({string_literal(v.name)}, {string_literal(methods.get(v.name, 'null'))})"
+            for (v, _) in query.aggregations
+        ]
+        inner_sql = sql_quer.compile(dialect=dialect, compile_kwargs={"literal_binds": True})
+        args = [
+            f"({inner_sql})",
+            f"ts_column => {string_literal(bucket)}",
+            f"bucket_width => INTERVAL {int(round(gap_fill.bucket_width * 1_000_000))} MICROSECOND",
+            f"value_columns => [{', '.join(value_columns)}]",
+        ]
+        if len(partitioning_columns) > 0:
+            args.append(f"partitioning_columns => [{', '.join(string_literal(c) for c in partitioning_columns)}]")
+        if gap_fill.origin is not None:
+            args.append(f"origin => TIMESTAMP {string_literal(gap_fill.origin.to_native().isoformat())}")
+        columns = [bucket] + partitioning_columns + [v.name for (v, _) in query.aggregations]
+        return select(
+            *[literal_column(dialect.identifier_preparer.quote(c)) for c in columns]
+        ).select_from(
+            text(f"GAP_FILL({', '.join(args)})")
+        )
+
+    def time_bucket_gapfill_to_sql(self, sql_quer: Select, query: VirtualizedQuery) -> Select:
+        gap_fill = query.gap_fill
+        bucket = gap_fill.bucket_variable.name
+        partitioning_columns = [c.name for c in query.by if c.name != bucket]
+        methods = {v.name: m for (v, m) in gap_fill.methods}
+        grouped = sql_quer.subquery(self.inner_name())
+
+        # The buckets are filled as microseconds since the origin, so that they are aligned with the buckets of the query
+        origin = epoch_micros(gap_fill.origin) if gap_fill.origin is not None else 0
+        bucket_micros = cast(extract("epoch", grouped.columns[bucket]) * 1_000_000, BigInteger) - origin
+        filled_bucket = func.time_bucket_gapfill(
+            cast(literal(int(round(gap_fill.bucket_width * 1_000_000))), BigInteger),
+            bucket_micros,
+            cast(literal(epoch_micros(gap_fill.start) - origin), BigInteger),
+            # The end of the time range is exclusive
+            cast(literal(epoch_micros(gap_fill.end) - origin + 1), BigInteger),
+        )
+        partition_by = [grouped.columns[c] for c in partitioning_columns]
+        values = []
+        for (v, _) in query.aggregations:
+            # There is a single row in each bucket of each group
+            value = func.min(grouped.columns[v.name])
+            match methods.get(v.name):
+                case "linear":
+                    value = func.interpolate(value)
+                case "locf":
+                    value = func.locf(value)
+            values.append(value.label(v.name))
+        filled = select(
+            filled_bucket.label(bucket),
+            *[c.label(c.name) for c in partition_by],
+            *values,
+            # Null in the added buckets
+            func.min(bucket_micros).label(GAP_FILL_OBSERVED),
+        ).group_by(
+            filled_bucket,
+            *partition_by
+        ).subquery(self.inner_name())
+
+        # The whole time range is filled, so the buckets before the first and after the last bucket of each group are removed
+        observed = filled.columns[GAP_FILL_OBSERVED]
+        window_partition_by = [filled.columns[c] for c in partitioning_columns] or None
+        bounded = select(
+            filled,
+            func.min(observed).over(partition_by=window_partition_by).label(GAP_FILL_FIRST),
+            func.max(observed).over(partition_by=window_partition_by).label(GAP_FILL_LAST),
+        ).subquery(self.inner_name())
+        return select(
+            func.to_timestamp((bounded.columns[bucket] + origin) / 1_000_000.0).label(bucket),
+            *[bounded.columns[c] for c in partitioning_columns],
+            *[bounded.columns[v.name] for (v, _) in query.aggregations],
+        ).where(
+            bounded.columns[bucket].between(bounded.columns[GAP_FILL_FIRST], bounded.columns[GAP_FILL_LAST])
+        )
+
+    def as_of_condition(self, as_of_synchronizers, out_columns, columns):
+        if not self.as_of_join:
+            return None
+        for s in as_of_synchronizers:
+            left = s.left.name
+            right = s.right.name
+            if left not in out_columns or right not in columns:
+                continue
+            left_col = out_columns[left]
+            right_col = columns[right]
+            if s.strategy == "backward":
+                as_of_on = left_col >= right_col
+            elif s.strategy == "forward":
+                as_of_on = left_col <= right_col
+            else:
+                # ASOF JOIN only matches in one direction, nearest matches are found after the join
+                raise NotImplementedError(f"As-of strategy {s.strategy} can not be translated to an ASOF JOIN")
+            tolerance_condition = None
+            if s.tolerance is not None:
+                tolerance = literal_column(f"INTERVAL '{int(round(s.tolerance * 1_000_000))} microseconds'")
+                if s.strategy == "backward":
+                    tolerance_condition = left_col - right_col <= tolerance
+                else:
+                    tolerance_condition = right_col - left_col <= tolerance
+            return as_of_on, tolerance_condition
+        return None
+
     def inner_name(self) -> str:
         name = f"inner_{self.counter}"
         self.counter += 1
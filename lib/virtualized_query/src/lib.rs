@@ -4,12 +4,14 @@ pub mod python;
 use polars::frame::DataFrame;
 use query_processing::find_query_variables::find_all_used_variables_in_expression;
 use representation::query_context::{Context, VariableInContext};
+use representation::BaseRDFNodeType;
 use spargebra::algebra::{AggregateExpression, Expression, OrderExpression};
 use spargebra::remove_sugar::{HAS_TIMESTAMP, HAS_VALUE};
-use spargebra::term::{NamedNodePattern, TermPattern, TriplePattern, Variable};
+use spargebra::term::{Literal, NamedNodePattern, TermPattern, TriplePattern, Variable};
 use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fmt::{Display, Formatter};
+use std::time::Duration;
 use templates::ast::{ConstantTerm, ConstantTermOrList, StottrTerm, Template};
 use templates::constants::OTTR_TRIPLE;
 
@@ -24,6 +26,7 @@ pub enum VirtualizedQuery {
     Grouped(GroupedVirtualizedQuery),
     Sliced(Box<VirtualizedQuery>, usize, Option<usize>),
     Ordered(Box<VirtualizedQuery>, Vec<OrderExpression>),
+    Windowed(Box<VirtualizedQuery>, Variable, Window),
 }
 
 impl VirtualizedQuery {
@@ -41,7 +44,7 @@ impl VirtualizedQuery {
             VirtualizedQuery::Basic(_) => false,
             VirtualizedQuery::Filtered(inner, _) => inner.try_modify_existing_sort(join_cols),
             VirtualizedQuery::InnerJoin(_, _) => false,
-            VirtualizedQuery::ExpressionAs(inner, _, _) => {
+            VirtualizedQuery::ExpressionAs(inner, _, _) | VirtualizedQuery::Windowed(inner, ..) => {
                 inner.try_modify_existing_sort(join_cols)
             }
             VirtualizedQuery::Grouped(_) => false,
@@ -57,6 +60,23 @@ impl VirtualizedQuery {
     }
 }
 
+// Blank nodes in virtualized patterns are treated as fresh variables that are never projected.
+fn blank_node_as_variable(term_pattern: &TermPattern) -> TermPattern {
+    if let TermPattern::BlankNode(b) = term_pattern {
+        TermPattern::Variable(Variable::new_unchecked(format!("blank_{}", b.as_str())))
+    } else {
+        term_pattern.clone()
+    }
+}
+
+fn term_pattern_variable(term_pattern: &TermPattern) -> Option<Variable> {
+    if let TermPattern::Variable(v) = blank_node_as_variable(term_pattern) {
+        Some(v)
+    } else {
+        None
+    }
+}
+
 fn create_orderings(join_cols: &Vec<String>) -> Vec<OrderExpression> {
     let mut orderings = vec![];
     for c in join_cols {
@@ -70,6 +90,36 @@ fn create_orderings(join_cols: &Vec<String>) -> Vec<OrderExpression> {
 #[derive(Debug, Clone, PartialEq)]
 pub enum Synchronizer {
     Identity(String),
+    AsOf(AsOfSynchronizer),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsOfStrategy {
+    // The last right timestamp at or before the left timestamp
+    Backward,
+    // The first right timestamp at or after the left timestamp
+    Forward,
+    Nearest,
+}
+
+// Matches each left timestamp with a right timestamp instead of requiring them to be equal.
+// Left timestamps without a match within the tolerance are dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsOfSynchronizer {
+    pub left: Variable,
+    pub right: Variable,
+    pub strategy: AsOfStrategy,
+    pub tolerance: Option<Duration>,
+}
+
+impl AsOfStrategy {
+    pub fn as_str(&self) -> &str {
+        match self {
+            AsOfStrategy::Backward => "backward",
+            AsOfStrategy::Forward => "forward",
+            AsOfStrategy::Nearest => "nearest",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -78,6 +128,79 @@ pub struct GroupedVirtualizedQuery {
     pub vq: Box<VirtualizedQuery>,
     pub by: Vec<Variable>,
     pub aggregations: Vec<(Variable, AggregateExpression)>,
+    pub gap_fill: Option<GapFill>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapFillMethod {
+    Null,
+    Locf,
+    Linear,
+}
+
+impl GapFillMethod {
+    pub fn as_str(&self) -> &str {
+        match self {
+            GapFillMethod::Null => "null",
+            GapFillMethod::Locf => "locf",
+            GapFillMethod::Linear => "linear",
+        }
+    }
+}
+
+// Completes the time buckets of each group from its first to its last bucket.
+// The values of the aggregations are filled in the added buckets using the methods.
+// The start and end are bounds on the bucketed timestamps, when they are given by the filters of the query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GapFill {
+    pub bucket_variable: Variable,
+    pub bucket_width: Duration,
+    pub origin: Option<Literal>,
+    pub methods: Vec<(Variable, GapFillMethod)>,
+    pub start: Option<Literal>,
+    pub end: Option<Literal>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowFunction {
+    Lag(usize),
+    Lead(usize),
+    RollingMean(Duration),
+    RollingSum(Duration),
+    CumulativeSum,
+    // The value minus the previous value
+    Delta,
+    // The change per second since the previous value
+    Derivative,
+    // Counts from one at the earliest value
+    RowNumber,
+    // Counts from one at the latest value
+    ReverseRowNumber,
+}
+
+impl WindowFunction {
+    pub fn as_str(&self) -> &str {
+        match self {
+            WindowFunction::Lag(_) => "lag",
+            WindowFunction::Lead(_) => "lead",
+            WindowFunction::RollingMean(_) => "rolling_mean",
+            WindowFunction::RollingSum(_) => "rolling_sum",
+            WindowFunction::CumulativeSum => "cumulative_sum",
+            WindowFunction::Delta => "delta",
+            WindowFunction::Derivative => "derivative",
+            WindowFunction::RowNumber => "row_number",
+            WindowFunction::ReverseRowNumber => "reverse_row_number",
+        }
+    }
+}
+
+// Computes a function of the values of each series, ordered by timestamp.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub function: WindowFunction,
+    pub value: Variable,
+    pub timestamp: Variable,
+    pub partition_by: Variable,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -89,6 +212,7 @@ pub struct BasicVirtualizedQuery {
     pub query_source_variable: Variable,
     pub resource: Option<String>,
     pub ids: Option<Vec<String>>,
+    pub id_type: Option<BaseRDFNodeType>,
     pub grouping_mapping: Option<DataFrame>,
     pub grouping_col: Option<String>,
     pub chrontext_timestamp_variable: Option<Variable>,
@@ -106,14 +230,14 @@ impl BasicVirtualizedQuery {
         let mut new_mappings = vec![];
         let mut visited_query_vars = HashSet::new();
         let id_var = Variable::new_unchecked(ID_VARIABLE_NAME);
-        let mut queue = vec![(&self.query_source_variable, &id_var)];
+        let mut queue = vec![(self.query_source_variable.clone(), &id_var)];
         while let Some((current_query_var, current_template_var)) = queue.pop() {
             if !visited_query_vars.contains(&current_query_var) {
-                visited_query_vars.insert(current_query_var);
+                visited_query_vars.insert(current_query_var.clone());
                 for p in patterns {
                     match &p.predicate {
                         NamedNodePattern::NamedNode(nn) => {
-                            if let TermPattern::Variable(v) = &p.subject {
+                            if let Some(v) = term_pattern_variable(&p.subject) {
                                 if current_query_var == v {
                                     for tp in &template.pattern_list {
                                         if let StottrTerm::ConstantTerm(
@@ -136,12 +260,15 @@ impl BasicVirtualizedQuery {
                                                                     if param_vars.contains(tobj) {
                                                                         new_mappings.push((
                                                                             tobj.clone(),
-                                                                            p.object.clone(),
+                                                                            blank_node_as_variable(
+                                                                                &p.object,
+                                                                            ),
                                                                         ));
                                                                     }
-                                                                    if let TermPattern::Variable(
-                                                                        obj,
-                                                                    ) = &p.object
+                                                                    if let Some(obj) =
+                                                                        term_pattern_variable(
+                                                                            &p.object,
+                                                                        )
                                                                     {
                                                                         queue.push((obj, tobj));
                                                                     }
@@ -206,15 +333,41 @@ impl BasicVirtualizedQuery {
 }
 
 impl BasicVirtualizedQuery {
+    // The timestamp of the data points that the variables of the expression belong to
+    pub fn timestamp_variable_of(&self, expression: &Expression) -> Option<&Variable> {
+        let mut used_vars = HashSet::new();
+        find_all_used_variables_in_expression(expression, &mut used_vars, true, true);
+        let expected_columns = self.expected_columns();
+        if used_vars
+            .iter()
+            .any(|v| expected_columns.contains(v.as_str()))
+        {
+            self.chrontext_timestamp_variable.as_ref()
+        } else {
+            None
+        }
+    }
+
+    // The identifier and timestamp of the series that the variable belongs to
+    pub fn series_of(&self, variable: &Variable) -> Option<(&Variable, &Variable)> {
+        if variable != &self.identifier_variable
+            && self.expected_columns().contains(variable.as_str())
+        {
+            Some((
+                &self.identifier_variable,
+                self.chrontext_timestamp_variable.as_ref()?,
+            ))
+        } else {
+            None
+        }
+    }
+
     fn expected_columns(&self) -> HashSet<&str> {
         let mut s = HashSet::new();
         for tp in self.column_mapping.values() {
             match tp {
-                TermPattern::BlankNode(b) => {
-                    todo!("For the time being, replace {} with a variable", b)
-                }
-                TermPattern::Literal(_) | TermPattern::NamedNode(_) => {
-                    //No action required
+                TermPattern::BlankNode(_) | TermPattern::Literal(_) | TermPattern::NamedNode(_) => {
+                    //Blank nodes are replaced by variables in finish_column_mapping
                 }
                 TermPattern::Variable(v) => {
                     s.insert(v.as_str());
@@ -277,7 +430,9 @@ impl VirtualizedQuery {
             | VirtualizedQuery::Sliced(i, ..)
             | VirtualizedQuery::Ordered(i, _) => i.has_identifiers(),
             VirtualizedQuery::InnerJoin(i, _) => i.iter().any(|x| x.has_identifiers()),
-            VirtualizedQuery::ExpressionAs(t, _, _) => t.has_identifiers(),
+            VirtualizedQuery::ExpressionAs(t, _, _) | VirtualizedQuery::Windowed(t, ..) => {
+                t.has_identifiers()
+            }
             VirtualizedQuery::Grouped(g) => g.vq.has_identifiers(),
         }
     }
@@ -289,7 +444,9 @@ impl VirtualizedQuery {
             | VirtualizedQuery::Sliced(i, ..)
             | VirtualizedQuery::Ordered(i, _) => i.has_resources(),
             VirtualizedQuery::InnerJoin(i, _) => i.iter().any(|x| x.has_resources()),
-            VirtualizedQuery::ExpressionAs(t, _, _) => t.has_resources(),
+            VirtualizedQuery::ExpressionAs(t, _, _) | VirtualizedQuery::Windowed(t, ..) => {
+                t.has_resources()
+            }
             VirtualizedQuery::Grouped(g) => g.vq.has_resources(),
         }
     }
@@ -348,7 +505,11 @@ impl VirtualizedQuery {
                 expected_columns.extend(grouping_col.iter().map(|x| x.as_str()));
                 expected_columns
             }
-            VirtualizedQuery::ExpressionAs(t, ..) => t.expected_columns(),
+            VirtualizedQuery::ExpressionAs(t, v, _) | VirtualizedQuery::Windowed(t, v, _) => {
+                let mut expected_columns = t.expected_columns();
+                expected_columns.insert(v.as_str());
+                expected_columns
+            }
         }
     }
 
@@ -364,7 +525,8 @@ impl VirtualizedQuery {
             VirtualizedQuery::Filtered(inner, _)
             | VirtualizedQuery::Sliced(inner, ..)
             | VirtualizedQuery::Ordered(inner, ..)
-            | VirtualizedQuery::ExpressionAs(inner, ..) => inner.get_ids(),
+            | VirtualizedQuery::ExpressionAs(inner, ..)
+            | VirtualizedQuery::Windowed(inner, ..) => inner.get_ids(),
             VirtualizedQuery::InnerJoin(inners, _) => {
                 let mut ss = vec![];
                 for inner in inners {
@@ -382,7 +544,8 @@ impl VirtualizedQuery {
             VirtualizedQuery::Filtered(inner, _)
             | VirtualizedQuery::Sliced(inner, ..)
             | VirtualizedQuery::Ordered(inner, ..)
-            | VirtualizedQuery::ExpressionAs(inner, ..) => inner.get_virtualized_variables(),
+            | VirtualizedQuery::ExpressionAs(inner, ..)
+            | VirtualizedQuery::Windowed(inner, ..) => inner.get_virtualized_variables(),
             VirtualizedQuery::InnerJoin(inners, _) => {
                 let mut vs = vec![];
                 for inner in inners {
@@ -394,6 +557,36 @@ impl VirtualizedQuery {
         }
     }
 
+    pub fn get_timestamp_variable_of(&self, expression: &Expression) -> Option<&Variable> {
+        match self {
+            VirtualizedQuery::Basic(b) => b.timestamp_variable_of(expression),
+            VirtualizedQuery::Filtered(inner, _)
+            | VirtualizedQuery::Sliced(inner, ..)
+            | VirtualizedQuery::ExpressionAs(inner, ..)
+            | VirtualizedQuery::Windowed(inner, ..)
+            | VirtualizedQuery::Ordered(inner, ..) => inner.get_timestamp_variable_of(expression),
+            VirtualizedQuery::InnerJoin(inners, _) => inners
+                .iter()
+                .find_map(|inner| inner.get_timestamp_variable_of(expression)),
+            VirtualizedQuery::Grouped(grouped) => grouped.vq.get_timestamp_variable_of(expression),
+        }
+    }
+
+    pub fn get_series_of(&self, variable: &Variable) -> Option<(&Variable, &Variable)> {
+        match self {
+            VirtualizedQuery::Basic(b) => b.series_of(variable),
+            VirtualizedQuery::Filtered(inner, _)
+            | VirtualizedQuery::Sliced(inner, ..)
+            | VirtualizedQuery::ExpressionAs(inner, ..)
+            | VirtualizedQuery::Windowed(inner, ..)
+            | VirtualizedQuery::Ordered(inner, ..) => inner.get_series_of(variable),
+            VirtualizedQuery::InnerJoin(inners, _) => inners
+                .iter()
+                .find_map(|inner| inner.get_series_of(variable)),
+            VirtualizedQuery::Grouped(_) => None,
+        }
+    }
+
     pub fn get_timestamp_variables(&self) -> Vec<Variable> {
         match self {
             VirtualizedQuery::Basic(b) => {
@@ -406,6 +599,7 @@ impl VirtualizedQuery {
             VirtualizedQuery::Filtered(inner, _)
             | VirtualizedQuery::Sliced(inner, ..)
             | VirtualizedQuery::ExpressionAs(inner, ..)
+            | VirtualizedQuery::Windowed(inner, ..)
             | VirtualizedQuery::Ordered(inner, ..) => inner.get_timestamp_variables(),
             VirtualizedQuery::InnerJoin(inners, _) => {
                 let mut vs = vec![];
@@ -430,6 +624,7 @@ impl VirtualizedQuery {
             VirtualizedQuery::Filtered(inner, _)
             | VirtualizedQuery::Sliced(inner, ..)
             | VirtualizedQuery::ExpressionAs(inner, ..)
+            | VirtualizedQuery::Windowed(inner, ..)
             | VirtualizedQuery::Ordered(inner, ..) => inner.get_value_variables(),
             VirtualizedQuery::InnerJoin(inners, _) => {
                 let mut vs = vec![];
@@ -447,7 +642,8 @@ impl VirtualizedQuery {
             VirtualizedQuery::Basic(_) => vec![],
             VirtualizedQuery::Filtered(inner, _)
             | VirtualizedQuery::Sliced(inner, ..)
-            | VirtualizedQuery::Ordered(inner, ..) => inner.get_extend_functions(),
+            | VirtualizedQuery::Ordered(inner, ..)
+            | VirtualizedQuery::Windowed(inner, ..) => inner.get_extend_functions(),
             VirtualizedQuery::InnerJoin(inners, _) => {
                 let mut vs = vec![];
                 for inner in inners {
@@ -464,6 +660,29 @@ impl VirtualizedQuery {
         }
     }
 
+    pub fn get_window_variables(&self) -> Vec<&Variable> {
+        match self {
+            VirtualizedQuery::Basic(_) => vec![],
+            VirtualizedQuery::Filtered(inner, _)
+            | VirtualizedQuery::Sliced(inner, ..)
+            | VirtualizedQuery::Ordered(inner, ..)
+            | VirtualizedQuery::ExpressionAs(inner, ..) => inner.get_window_variables(),
+            VirtualizedQuery::InnerJoin(inners, _) => {
+                let mut vs = vec![];
+                for inner in inners {
+                    vs.extend(inner.get_window_variables())
+                }
+                vs
+            }
+            VirtualizedQuery::Grouped(grouped) => grouped.vq.get_window_variables(),
+            VirtualizedQuery::Windowed(inner, v, _) => {
+                let mut vs = inner.get_window_variables();
+                vs.push(v);
+                vs
+            }
+        }
+    }
+
     pub fn get_identifier_variables(&self) -> Vec<&Variable> {
         match self {
             VirtualizedQuery::Basic(b) => {
@@ -480,7 +699,9 @@ impl VirtualizedQuery {
                 vs
             }
             VirtualizedQuery::Grouped(grouped) => grouped.vq.get_identifier_variables(),
-            VirtualizedQuery::ExpressionAs(t, ..) => t.get_identifier_variables(),
+            VirtualizedQuery::ExpressionAs(t, ..) | VirtualizedQuery::Windowed(t, ..) => {
+                t.get_identifier_variables()
+            }
         }
     }
 
@@ -500,7 +721,9 @@ impl VirtualizedQuery {
                 vs
             }
             VirtualizedQuery::Grouped(grouped) => grouped.vq.get_resource_variables(),
-            VirtualizedQuery::ExpressionAs(t, ..) => t.get_resource_variables(),
+            VirtualizedQuery::ExpressionAs(t, ..) | VirtualizedQuery::Windowed(t, ..) => {
+                t.get_resource_variables()
+            }
         }
     }
 
@@ -510,7 +733,13 @@ impl VirtualizedQuery {
                 return true;
             }
         }
-        false
+        // Variables bound by pushed down expressions and windows are computed by the virtualized database, unless they are grouped away
+        !matches!(self, VirtualizedQuery::Grouped(_))
+            && (self
+                .get_extend_functions()
+                .iter()
+                .any(|(v, _)| *v == variable)
+                || self.get_window_variables().contains(&variable))
     }
 }
 
@@ -529,6 +758,7 @@ impl BasicVirtualizedQuery {
             query_source_variable,
             resource: None,
             ids: None,
+            id_type: None,
             grouping_mapping: None,
             grouping_col: None,
             chrontext_timestamp_variable: None,
@@ -550,7 +780,8 @@ impl VirtualizedQuery {
             VirtualizedQuery::Filtered(inner, _)
             | VirtualizedQuery::Sliced(inner, ..)
             | VirtualizedQuery::Ordered(inner, ..)
-            | VirtualizedQuery::ExpressionAs(inner, ..) => inner.get_groupby_columns(),
+            | VirtualizedQuery::ExpressionAs(inner, ..)
+            | VirtualizedQuery::Windowed(inner, ..) => inner.get_groupby_columns(),
             VirtualizedQuery::InnerJoin(vqs, _) => {
                 let mut colnames = HashSet::new();
                 for vq in vqs {
@@ -574,6 +805,7 @@ impl VirtualizedQuery {
             }
             VirtualizedQuery::Filtered(vq, _)
             | VirtualizedQuery::ExpressionAs(vq, ..)
+            | VirtualizedQuery::Windowed(vq, ..)
             | VirtualizedQuery::Sliced(vq, ..)
             | VirtualizedQuery::Ordered(vq, ..) => vq.get_groupby_mapping_df(),
             VirtualizedQuery::InnerJoin(vqs, _) => {
@@ -600,7 +832,8 @@ impl VirtualizedQuery {
             }
             VirtualizedQuery::Filtered(vq, _)
             | VirtualizedQuery::Sliced(vq, ..)
-            | VirtualizedQuery::Ordered(vq, ..) => vq.get_virtualized_functions(context),
+            | VirtualizedQuery::Ordered(vq, ..)
+            | VirtualizedQuery::Windowed(vq, ..) => vq.get_virtualized_functions(context),
             VirtualizedQuery::InnerJoin(vqs, _) => {
                 let mut out_tsfs = vec![];
                 for vq in vqs {
@@ -15,4 +15,16 @@ pub enum PushdownSetting {
     ValueConditions,
     GroupBy,
     Ordering,
+    // Not part of all_pushdowns, as few SQL dialects support as-of joins
+    AsOfJoin,
+    // Not part of all_pushdowns, otherwise gaps are filled after the grouped results are returned
+    GapFill,
+    // Not part of all_pushdowns, gaps are only filled by databases such as TimescaleDB when the query bounds the time range
+    GapFillInTimeRange,
+    // Not part of all_pushdowns, as the windows would otherwise have to be computed by every virtualized database
+    Window,
+    // Not part of all_pushdowns, BIND expressions are otherwise only pushed down below grouping
+    Extend,
+    // Not part of all_pushdowns, filters on aggregates are otherwise only applied after the grouped results are returned
+    Having,
 }
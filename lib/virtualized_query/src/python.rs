@@ -1,11 +1,15 @@
-use crate::VirtualizedQuery;
+use crate::{AsOfSynchronizer, GapFill, Synchronizer, VirtualizedQuery, Window, WindowFunction};
+use oxrdf::vocab::xsd;
+use oxrdf::NamedNodeRef;
 use polars::prelude::AnyValue;
 use pyo3::prelude::*;
+use pyo3::IntoPyObjectExt;
 use representation::python::{PyIRI, PyLiteral, PyVariable};
+use representation::BaseRDFNodeType;
 use spargebra::algebra::{
     AggregateExpression, AggregateFunction, Expression, Function, OrderExpression,
 };
-use spargebra::term::TermPattern;
+use spargebra::term::{Literal, TermPattern, Variable};
 use std::collections::HashMap;
 
 #[derive(Clone)]
@@ -16,6 +20,7 @@ pub enum PyVirtualizedQuery {
         column_mapping: HashMap<String, String>,
         resource: String,
         ids: Vec<String>,
+        id_datatype: Option<String>,
         grouping_column_name: Option<String>,
         id_grouping_tuples: Option<Vec<(String, i64)>>,
     },
@@ -27,6 +32,7 @@ pub enum PyVirtualizedQuery {
         query: Py<PyVirtualizedQuery>,
         by: Vec<PyVariable>,
         aggregations: Vec<(Py<PyVariable>, Py<PyAggregateExpression>)>,
+        gap_fill: Option<Py<PyGapFill>>,
     },
     ExpressionAs {
         query: Py<PyVirtualizedQuery>,
@@ -35,6 +41,7 @@ pub enum PyVirtualizedQuery {
     },
     InnerJoin {
         queries: Vec<Py<PyVirtualizedQuery>>,
+        as_of_synchronizers: Vec<Py<PyAsOfSynchronizer>>,
     },
     Ordered {
         query: Py<PyVirtualizedQuery>,
@@ -45,6 +52,11 @@ pub enum PyVirtualizedQuery {
         offset: usize,
         limit: Option<usize>,
     },
+    Windowed {
+        query: Py<PyVirtualizedQuery>,
+        variable: Py<PyVariable>,
+        window: Py<PyWindow>,
+    },
 }
 
 #[pymethods]
@@ -58,6 +70,7 @@ impl PyVirtualizedQuery {
             PyVirtualizedQuery::InnerJoin { .. } => "InnerJoin",
             PyVirtualizedQuery::Ordered { .. } => "Ordered",
             PyVirtualizedQuery::Sliced { .. } => "Sliced",
+            PyVirtualizedQuery::Windowed { .. } => "Windowed",
         }
     }
 
@@ -86,9 +99,24 @@ impl PyVirtualizedQuery {
         }
     }
     #[getter]
-    fn ids(&self) -> Option<Vec<String>> {
+    fn ids(&self, py: Python) -> PyResult<Option<Vec<PyObject>>> {
         match self {
-            PyVirtualizedQuery::Basic { ids, .. } => Some(ids.clone()),
+            PyVirtualizedQuery::Basic {
+                ids, id_datatype, ..
+            } => {
+                let mut py_ids = vec![];
+                for id in ids {
+                    py_ids.push(id_to_python(id, id_datatype, py)?);
+                }
+                Ok(Some(py_ids))
+            }
+            _ => Ok(None),
+        }
+    }
+    #[getter]
+    fn id_datatype(&self) -> Option<String> {
+        match self {
+            PyVirtualizedQuery::Basic { id_datatype, .. } => id_datatype.clone(),
             _ => None,
         }
     }
@@ -100,12 +128,20 @@ impl PyVirtualizedQuery {
         }
     }
     #[getter]
-    fn id_grouping_tuples(&self) -> Option<Vec<(String, i64)>> {
+    fn id_grouping_tuples(&self, py: Python) -> PyResult<Option<Vec<(PyObject, i64)>>> {
         match self {
             PyVirtualizedQuery::Basic {
-                id_grouping_tuples, ..
-            } => id_grouping_tuples.clone(),
-            _ => None,
+                id_grouping_tuples: Some(id_grouping_tuples),
+                id_datatype,
+                ..
+            } => {
+                let mut tuples = vec![];
+                for (id, group) in id_grouping_tuples {
+                    tuples.push((id_to_python(id, id_datatype, py)?, *group));
+                }
+                Ok(Some(tuples))
+            }
+            _ => Ok(None),
         }
     }
     #[getter]
@@ -125,7 +161,8 @@ impl PyVirtualizedQuery {
             | PyVirtualizedQuery::ExpressionAs { query, .. }
             | PyVirtualizedQuery::Ordered { query, .. }
             | PyVirtualizedQuery::Grouped { query, .. }
-            | PyVirtualizedQuery::Sliced { query, .. } => Some(query.clone_ref(py)),
+            | PyVirtualizedQuery::Sliced { query, .. }
+            | PyVirtualizedQuery::Windowed { query, .. } => Some(query.clone_ref(py)),
             _ => None,
         }
     }
@@ -138,6 +175,17 @@ impl PyVirtualizedQuery {
         }
     }
 
+    #[getter]
+    fn as_of_synchronizers(&self) -> Option<Vec<Py<PyAsOfSynchronizer>>> {
+        match self {
+            PyVirtualizedQuery::InnerJoin {
+                as_of_synchronizers,
+                ..
+            } => Some(as_of_synchronizers.clone()),
+            _ => None,
+        }
+    }
+
     #[getter]
     fn by(&self) -> Option<Vec<PyVariable>> {
         match self {
@@ -162,10 +210,29 @@ impl PyVirtualizedQuery {
         }
     }
 
+    #[getter]
+    fn gap_fill(&self, py: Python) -> Option<Py<PyGapFill>> {
+        match self {
+            PyVirtualizedQuery::Grouped { gap_fill, .. } => {
+                gap_fill.as_ref().map(|x| x.clone_ref(py))
+            }
+            _ => None,
+        }
+    }
+
     #[getter]
     fn variable(&self, py: Python) -> Option<Py<PyVariable>> {
         match self {
-            PyVirtualizedQuery::ExpressionAs { variable, .. } => Some(variable.clone_ref(py)),
+            PyVirtualizedQuery::ExpressionAs { variable, .. }
+            | PyVirtualizedQuery::Windowed { variable, .. } => Some(variable.clone_ref(py)),
+            _ => None,
+        }
+    }
+
+    #[getter]
+    fn window(&self, py: Python) -> Option<Py<PyWindow>> {
+        match self {
+            PyVirtualizedQuery::Windowed { window, .. } => Some(window.clone_ref(py)),
             _ => None,
         }
     }
@@ -218,8 +285,8 @@ impl PyVirtualizedQuery {
                         .as_materialized_series()
                         .iter();
                     for (id, group) in id_iter.zip(group_iter) {
-                        if let (AnyValue::String(id), AnyValue::Int64(group)) = (id, group) {
-                            id_grouping_tuples.push((id.to_string(), group));
+                        if let AnyValue::Int64(group) = group {
+                            id_grouping_tuples.push((id.str_value().to_string(), group));
                         } else {
                             panic!("Should never happen")
                         }
@@ -233,6 +300,10 @@ impl PyVirtualizedQuery {
                     column_mapping,
                     resource: basic.resource.unwrap(),
                     ids: basic.ids.unwrap(),
+                    id_datatype: match basic.id_type {
+                        Some(BaseRDFNodeType::Literal(l)) => Some(l.as_str().to_string()),
+                        _ => None,
+                    },
                     grouping_column_name: basic.grouping_col,
                     id_grouping_tuples,
                 }
@@ -248,15 +319,27 @@ impl PyVirtualizedQuery {
                 }
                 let mut aggregations = vec![];
                 for (v, a) in &grouped.aggregations {
+                    // Used to order the values when finding the first or last value
+                    let timestamp = if let AggregateExpression::FunctionCall { expr, .. } = a {
+                        grouped.vq.get_timestamp_variable_of(expr)
+                    } else {
+                        None
+                    };
                     aggregations.push((
                         Py::new(py, PyVariable::new(v.as_str().to_string())?)?,
-                        Py::new(py, PyAggregateExpression::new(a, py)?)?,
+                        Py::new(py, PyAggregateExpression::new(a, timestamp, py)?)?,
                     ))
                 }
+                let gap_fill = if let Some(gap_fill) = &grouped.gap_fill {
+                    Some(Py::new(py, PyGapFill::new(gap_fill, py)?)?)
+                } else {
+                    None
+                };
                 PyVirtualizedQuery::Grouped {
                     query: Py::new(py, PyVirtualizedQuery::new(*grouped.vq, py)?)?,
                     by,
                     aggregations,
+                    gap_fill,
                 }
             }
             VirtualizedQuery::ExpressionAs(query, variable, expression) => {
@@ -269,12 +352,21 @@ impl PyVirtualizedQuery {
                     expression: Py::new(py, py_expression)?,
                 }
             }
-            VirtualizedQuery::InnerJoin(queries, _on) => {
+            VirtualizedQuery::InnerJoin(queries, synchronizers) => {
                 let mut py_qs = vec![];
                 for q in queries {
                     py_qs.push(Py::new(py, PyVirtualizedQuery::new(q, py)?)?);
                 }
-                PyVirtualizedQuery::InnerJoin { queries: py_qs }
+                let mut as_of_synchronizers = vec![];
+                for s in synchronizers {
+                    if let Synchronizer::AsOf(s) = s {
+                        as_of_synchronizers.push(Py::new(py, PyAsOfSynchronizer::new(&s)?)?);
+                    }
+                }
+                PyVirtualizedQuery::InnerJoin {
+                    queries: py_qs,
+                    as_of_synchronizers,
+                }
             }
             VirtualizedQuery::Ordered(vq, ordering) => {
                 let mut py_orderings = vec![];
@@ -292,10 +384,231 @@ impl PyVirtualizedQuery {
                 limit,
                 offset,
             },
+            VirtualizedQuery::Windowed(vq, variable, window) => PyVirtualizedQuery::Windowed {
+                query: Py::new(py, PyVirtualizedQuery::new(*vq, py)?)?,
+                variable: Py::new(py, PyVariable::new(variable.as_str().to_string())?)?,
+                window: Py::new(py, PyWindow::new(&window)?)?,
+            },
         })
     }
 }
 
+#[pyclass(name = "AsOfSynchronizer")]
+#[derive(Clone)]
+pub struct PyAsOfSynchronizer {
+    left: PyVariable,
+    right: PyVariable,
+    strategy: String,
+    tolerance: Option<f64>,
+}
+
+impl PyAsOfSynchronizer {
+    pub fn new(synchronizer: &AsOfSynchronizer) -> PyResult<PyAsOfSynchronizer> {
+        Ok(PyAsOfSynchronizer {
+            left: PyVariable::new(synchronizer.left.as_str().to_string())?,
+            right: PyVariable::new(synchronizer.right.as_str().to_string())?,
+            strategy: synchronizer.strategy.as_str().to_string(),
+            tolerance: synchronizer.tolerance.map(|x| x.as_secs_f64()),
+        })
+    }
+}
+
+#[pymethods]
+impl PyAsOfSynchronizer {
+    #[getter]
+    fn left(&self) -> PyVariable {
+        self.left.clone()
+    }
+
+    #[getter]
+    fn right(&self) -> PyVariable {
+        self.right.clone()
+    }
+
+    #[getter]
+    fn strategy(&self) -> String {
+        self.strategy.clone()
+    }
+
+    #[getter]
+    fn tolerance(&self) -> Option<f64> {
+        self.tolerance
+    }
+}
+
+#[pyclass(name = "GapFill")]
+pub struct PyGapFill {
+    bucket_variable: PyVariable,
+    bucket_width: f64,
+    origin: Option<Py<PyLiteral>>,
+    methods: Vec<(PyVariable, String)>,
+    start: Option<Py<PyLiteral>>,
+    end: Option<Py<PyLiteral>>,
+}
+
+impl PyGapFill {
+    pub fn new(gap_fill: &GapFill, py: Python) -> PyResult<PyGapFill> {
+        let py_literal = |l: &Option<Literal>| -> PyResult<Option<Py<PyLiteral>>> {
+            if let Some(l) = l {
+                Ok(Some(Py::new(py, PyLiteral::from_literal(l.clone()))?))
+            } else {
+                Ok(None)
+            }
+        };
+        let mut methods = vec![];
+        for (v, m) in &gap_fill.methods {
+            methods.push((
+                PyVariable::new(v.as_str().to_string())?,
+                m.as_str().to_string(),
+            ));
+        }
+        Ok(PyGapFill {
+            bucket_variable: PyVariable::new(gap_fill.bucket_variable.as_str().to_string())?,
+            bucket_width: gap_fill.bucket_width.as_secs_f64(),
+            origin: py_literal(&gap_fill.origin)?,
+            methods,
+            start: py_literal(&gap_fill.start)?,
+            end: py_literal(&gap_fill.end)?,
+        })
+    }
+}
+
+#[pymethods]
+impl PyGapFill {
+    #[getter]
+    fn bucket_variable(&self) -> PyVariable {
+        self.bucket_variable.clone()
+    }
+
+    #[getter]
+    fn bucket_width(&self) -> f64 {
+        self.bucket_width
+    }
+
+    #[getter]
+    fn origin(&self, py: Python) -> Option<Py<PyLiteral>> {
+        self.origin.as_ref().map(|x| x.clone_ref(py))
+    }
+
+    #[getter]
+    fn methods(&self) -> Vec<(PyVariable, String)> {
+        self.methods.clone()
+    }
+
+    #[getter]
+    fn start(&self, py: Python) -> Option<Py<PyLiteral>> {
+        self.start.as_ref().map(|x| x.clone_ref(py))
+    }
+
+    #[getter]
+    fn end(&self, py: Python) -> Option<Py<PyLiteral>> {
+        self.end.as_ref().map(|x| x.clone_ref(py))
+    }
+}
+
+#[pyclass(name = "Window")]
+pub struct PyWindow {
+    function: String,
+    value: PyVariable,
+    timestamp: PyVariable,
+    partition_by: PyVariable,
+    offset: Option<usize>,
+    window_size: Option<f64>,
+}
+
+impl PyWindow {
+    pub fn new(window: &Window) -> PyResult<PyWindow> {
+        let (offset, window_size) = match &window.function {
+            WindowFunction::Lag(n) | WindowFunction::Lead(n) => (Some(*n), None),
+            WindowFunction::RollingMean(d) | WindowFunction::RollingSum(d) => {
+                (None, Some(d.as_secs_f64()))
+            }
+            WindowFunction::CumulativeSum
+            | WindowFunction::Delta
+            | WindowFunction::Derivative
+            | WindowFunction::RowNumber
+            | WindowFunction::ReverseRowNumber => (None, None),
+        };
+        Ok(PyWindow {
+            function: window.function.as_str().to_string(),
+            value: PyVariable::new(window.value.as_str().to_string())?,
+            timestamp: PyVariable::new(window.timestamp.as_str().to_string())?,
+            partition_by: PyVariable::new(window.partition_by.as_str().to_string())?,
+            offset,
+            window_size,
+        })
+    }
+}
+
+#[pymethods]
+impl PyWindow {
+    #[getter]
+    fn function(&self) -> String {
+        self.function.clone()
+    }
+
+    #[getter]
+    fn value(&self) -> PyVariable {
+        self.value.clone()
+    }
+
+    #[getter]
+    fn timestamp(&self) -> PyVariable {
+        self.timestamp.clone()
+    }
+
+    #[getter]
+    fn partition_by(&self) -> PyVariable {
+        self.partition_by.clone()
+    }
+
+    #[getter]
+    fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    #[getter]
+    fn window_size(&self) -> Option<f64> {
+        self.window_size
+    }
+}
+
+fn id_to_python(id: &str, id_datatype: &Option<String>, py: Python) -> PyResult<PyObject> {
+    if let Some(dt) = id_datatype {
+        let dt = NamedNodeRef::new_unchecked(dt);
+        if dt == xsd::BOOLEAN {
+            if let Ok(b) = id.parse::<bool>() {
+                return b.into_py_any(py);
+            }
+        } else if [
+            xsd::INTEGER,
+            xsd::LONG,
+            xsd::INT,
+            xsd::SHORT,
+            xsd::BYTE,
+            xsd::UNSIGNED_LONG,
+            xsd::UNSIGNED_INT,
+            xsd::UNSIGNED_SHORT,
+            xsd::UNSIGNED_BYTE,
+            xsd::NON_NEGATIVE_INTEGER,
+            xsd::POSITIVE_INTEGER,
+            xsd::NON_POSITIVE_INTEGER,
+            xsd::NEGATIVE_INTEGER,
+        ]
+        .contains(&dt)
+        {
+            if let Ok(i) = id.parse::<i64>() {
+                return i.into_py_any(py);
+            }
+        } else if [xsd::DOUBLE, xsd::FLOAT, xsd::DECIMAL].contains(&dt) {
+            if let Ok(f) = id.parse::<f64>() {
+                return f.into_py_any(py);
+            }
+        }
+    }
+    id.into_py_any(py)
+}
+
 #[derive(Clone, Debug)]
 #[pyclass(name = "Expression")]
 pub enum PyExpression {
@@ -651,21 +964,30 @@ impl PyOrderExpression {
 pub struct PyAggregateExpression {
     pub function: AggregateFunction,
     pub expression: Option<Py<PyExpression>>,
+    pub timestamp: Option<PyVariable>,
 }
 
 impl PyAggregateExpression {
     pub fn new(
         aggregate_expression: &AggregateExpression,
+        timestamp: Option<&Variable>,
         py: Python,
     ) -> PyResult<PyAggregateExpression> {
+        let timestamp = if let Some(t) = timestamp {
+            Some(PyVariable::new(t.as_str().to_string())?)
+        } else {
+            None
+        };
         Ok(match aggregate_expression {
             AggregateExpression::CountSolutions { .. } => PyAggregateExpression {
                 function: AggregateFunction::Count,
                 expression: None,
+                timestamp,
             },
             AggregateExpression::FunctionCall { name, expr, .. } => PyAggregateExpression {
                 function: name.clone(),
                 expression: Some(Py::new(py, PyExpression::new(expr, py)?)?),
+                timestamp,
             },
         })
     }
@@ -690,4 +1012,9 @@ impl PyAggregateExpression {
             _ => None,
         }
     }
+
+    #[getter]
+    fn timestamp(&self) -> Option<PyVariable> {
+        self.timestamp.clone()
+    }
 }
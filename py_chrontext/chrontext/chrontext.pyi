@@ -1,5 +1,5 @@
 from datetime import datetime, date
-from typing import List, Dict, Callable, Literal as LiteralType, Union, Optional, Any
+from typing import List, Dict, Callable, Literal as LiteralType, Union, Optional, Any, Tuple
 from polars import DataFrame
 from sqlalchemy import Select, Table
 from pyoxigraph import Store
@@ -240,7 +240,7 @@ class VirtualizedPythonDatabase:
     def __init__(self,
                  database: Any,
                  resource_sql_map: Optional[Dict[str, Any]],
-                 sql_dialect: Optional[LiteralType["postgres", "bigquery", "databricks"]]):
+                 sql_dialect: Optional[LiteralType["postgres", "bigquery", "databricks", "duckdb", "timescale"]]):
         """
         See the tutorial in README.md for guidance on how to use this class.
         This API is subject to change, it will be possible to specify what parts of the SPARQL query may be pushed down into the database.
@@ -249,7 +249,7 @@ class VirtualizedPythonDatabase:
 
         :param:database: An instance of a class containing a query method.
         :param:resource_sql_map: A dict providing a sqlalchemy Select for each resource.
-        :param:sql_dialect: The SQL dialect accepted by the query method.
+        :param:sql_dialect: The SQL dialect accepted by the query method. With "duckdb", as-of joins are pushed down as ASOF JOIN. With "timescale", gaps are filled using time_bucket_gapfill when the query bounds the timestamps.
         """
 
 class VirtualizedBigQueryDatabase:
@@ -270,6 +270,39 @@ class VirtualizedBigQueryDatabase:
         :param key_json_path: Path to JSON containing Key to connect to BigQuery.
         """
 
+class SparqlEndpoint:
+    """
+    A SPARQL endpoint with configuration of the HTTP requests sent to it.
+    """
+    def __init__(self,
+                 endpoint: str,
+                 method: Optional[LiteralType["get", "post", "post_form"]] = None,
+                 username: Optional[str] = None,
+                 password: Optional[str] = None,
+                 bearer_token: Optional[str] = None,
+                 headers: Optional[Dict[str, str]] = None,
+                 default_graph_uris: Optional[List[str]] = None,
+                 named_graph_uris: Optional[List[str]] = None,
+                 timeout: Optional[float] = None,
+                 results_format: Optional[LiteralType["json", "xml", "tsv"]] = None):
+        """
+        Configure a SPARQL endpoint.
+        Use method="post" or method="post_form" when static queries may be too long for a URL.
+
+        :param endpoint: The URL of the endpoint.
+        :param method: Send the query in the URL of a GET request (default), as the body of a POST request (application/sparql-query) or as a form-encoded POST request.
+        :param username: Username for basic authentication.
+        :param password: Password for basic authentication.
+        :param bearer_token: Token for bearer authentication, cannot be combined with basic authentication.
+        :param headers: Additional HTTP headers sent with each request.
+        :param default_graph_uris: Sent as default-graph-uri parameters.
+        :param named_graph_uris: Sent as named-graph-uri parameters.
+        :param timeout: Timeout in seconds for each request.
+        :param results_format: Request results in this format.
+                               By default, the most efficient format supported by the endpoint is negotiated.
+                               Results are parsed as they are received.
+        """
+
 class Engine:
     """
     The hybrid query engine of chrontext.
@@ -286,16 +319,23 @@ class Engine:
                  virtualized_python_database: Optional["VirtualizedPythonDatabase"]=None,
                  virtualized_bigquery_database: Optional["VirtualizedBigQueryDatabase"]=None,
                  virtualized_opcua_database: Optional["VirtualizedOPCUADatabase"]=None,
-                 sparql_endpoint: Optional[str]=None,
-                 sparql_embedded_oxigraph: Optional["Store"]=None,
+                 sparql_endpoint: Optional[Union[str, "SparqlEndpoint"]]=None,
+                 sparql_embedded_oxigraph: Optional[Union["Store", str, List[str]]]=None,
+                 templates: Optional[List[Template]]=None,
         ) -> "Engine":
         """
         Construct a new hybrid query engine.
         Specify exactly one of `sparql_endpoint` and `sparql_embedded_oxigraph`.
 
         :param resources: The templates associated with each
-        :param sparql_endpoint: A SPARQL endpoint (a URL)
+        :param sparql_endpoint: A SPARQL endpoint, either a URL or a SparqlEndpoint with further configuration.
+                                GET requests to a URL also have the parameters format=json, output=json and results=json,
+                                for endpoints that ignore the Accept header.
         :param sparql_embedded_oxigraph: An embedded oxigraph SPARQL database, a Store-object.
+                                         Alternatively, a path or a list of paths to RDF files, which are loaded into
+                                         a store owned by the engine. This store is queried without going through Python.
+        :param templates: A library of templates that may be instantiated in the resource templates.
+                          Instances of these are expanded into triple patterns when the engine is initialized.
         """
 
     def init(self) -> None:
@@ -309,6 +349,7 @@ class Engine:
               query:str,
               native_dataframe:bool=False,
               include_datatypes: bool = False,
+              timeout: Optional[float] = None,
               ) -> Union[DataFrame, SolutionMappings]:
         """
         Execute a query
@@ -316,9 +357,56 @@ class Engine:
         :param query: The SPARQL query.
         :param native_dataframe: Return columns with chrontext-native formatting. Useful for round-trips into e.g. maplib.
         :param include_datatypes: Return datatypes of the results DataFrame (returns SolutionMappings instead of DataFrame).
+        :param timeout: Timeout in seconds. When it is exceeded the query is cancelled and an exception is raised.
+                         Calls to a VirtualizedPythonDatabase can not be interrupted, so the timeout takes effect when such a call returns.
         :return: The query result.
         """
 
+    def prepare(self, query:str) -> "PreparedQuery":
+        """
+        Parse a SELECT query once so that it can be executed many times with different parameters.
+        Any variable in the query can be bound when the prepared query is executed.
+
+        :param query: The SPARQL query.
+        :return: The prepared query.
+        """
+
+    def construct(self,
+                  query:str,
+                  format: Optional[LiteralType["ntriples", "turtle"]] = None,
+                  timeout: Optional[float] = None,
+                  ) -> Union[DataFrame, str]:
+        """
+        Execute a CONSTRUCT query.
+        The WHERE clause may contain virtualized triples, the template is instantiated for each solution.
+
+        :param query: The SPARQL CONSTRUCT query.
+        :param format: Serialize the triples as N-Triples or Turtle instead of returning a DataFrame.
+        :param timeout: Timeout in seconds. When it is exceeded the query is cancelled and an exception is raised.
+                         Calls to a VirtualizedPythonDatabase can not be interrupted, so the timeout takes effect when such a call returns.
+        :return: A DataFrame with subject, predicate and object columns, or the serialized triples.
+        """
+
+    def ask(self, query:str, timeout: Optional[float] = None) -> bool:
+        """
+        Execute an ASK query.
+        Evaluation stops after the first solution, and the limit is pushed into the virtualized queries where possible.
+
+        :param query: The SPARQL ASK query.
+        :param timeout: Timeout in seconds. When it is exceeded the query is cancelled and an exception is raised.
+                         Calls to a VirtualizedPythonDatabase can not be interrupted, so the timeout takes effect when such a call returns.
+        :return: True if the query has a solution.
+        """
+
+    def is_hybrid(self, query:str) -> bool:
+        """
+        Check if a query uses any virtualized IRIs.
+        Queries that do not are sent directly to the SPARQL database.
+
+        :param query: The SPARQL query.
+        :return: True if the query is hybrid.
+        """
+
     def serve_postgres(self, catalog:"Catalog"):
         """
         Serve the data product catalog as a postgres endpoint.
@@ -335,6 +423,30 @@ class Engine:
         :return:
         """
 
+class PreparedQuery:
+    """
+    A query prepared with Engine.prepare.
+    """
+
+    def query(self,
+              native_dataframe:bool=False,
+              include_datatypes: bool = False,
+              timeout: Optional[float] = None,
+              **parameters: Union[IRI, Literal, str, int, float, bool],
+              ) -> Union[DataFrame, SolutionMappings]:
+        """
+        Execute the prepared query.
+        Parameters are given by variable name, e.g. query(site=IRI("http://example.org/site1")) binds ?site.
+        Bound variables are substituted by the values in triple patterns and expressions.
+
+        :param native_dataframe: Return columns with chrontext-native formatting. Useful for round-trips into e.g. maplib.
+        :param include_datatypes: Return datatypes of the results DataFrame (returns SolutionMappings instead of DataFrame).
+        :param timeout: Timeout in seconds. When it is exceeded the query is cancelled and an exception is raised.
+                         Calls to a VirtualizedPythonDatabase can not be interrupted, so the timeout takes effect when such a call returns.
+        :param parameters: Values of the variables to bind.
+        :return: The query result.
+        """
+
 class Catalog:
     """
     A Catalog maps SPARQL queries to virtual SQL tables.
@@ -388,4 +500,53 @@ class FlightClient:
         :param native_dataframe: Return columns with chrontext-native formatting. Useful for round-trips into e.g. maplib.
         :param include_datatypes: Return datatypes of the results DataFrame (returns SolutionMappings instead of DataFrame).
         :return: The query result.
-        """
\ No newline at end of file
+        """
+
+class DatatypeIRIParseError(Exception):
+    ...
+
+class DataProductQueryParseError(Exception):
+    ...
+
+class MissingSPARQLDatabaseError(Exception):
+    ...
+
+class MultipleSPARQLDatabasesError(Exception):
+    ...
+
+class MissingVirtualizedDatabaseError(Exception):
+    ...
+
+class MultipleVirtualizedDatabasesError(Exception):
+    ...
+
+class FlightClientError(Exception):
+    ...
+
+class FlightServerError(Exception):
+    ...
+
+class ChrontextError(Exception):
+    ...
+
+class TemplateExpansionError(Exception):
+    ...
+
+class UnknownSerializationFormat(Exception):
+    ...
+
+class QueryParameterTypeError(Exception):
+    ...
+
+class SparqlEndpointConfigError(Exception):
+    ...
+
+class NativeOxigraphError(Exception):
+    ...
+
+class StaticQueryExecutionError(Exception):
+    """
+    A static query failed in the SPARQL database.
+    The arguments are the error message, the failing query and the context of the query.
+    """
+    args: Tuple[str, str, str]
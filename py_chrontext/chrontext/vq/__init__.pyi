@@ -1,4 +1,4 @@
-from typing import Optional, List, Type, Tuple, Dict, Literal as LiteralType
+from typing import Optional, List, Type, Tuple, Dict, Union, Literal as LiteralType
 
 from chrontext import Variable, Literal, IRI
 
@@ -65,8 +65,51 @@ class AggregateExpression:
     name: str
     expression: Expression
     separator: Optional[str]
+    timestamp: Optional[Variable]
     """
+    The timestamp is set for ct:first and ct:last, and is the timestamp variable used to order the values.
+    """
+
+
+class AsOfSynchronizer:
+    left: Variable
+    right: Variable
+    strategy: LiteralType["backward", "forward", "nearest"]
+    tolerance: Optional[float]
+    """
+    Matches each timestamp in the left variable with the closest timestamp in the right variable,
+    looking backward or forward in time. The tolerance is given in seconds.
+    """
+
 
+class GapFill:
+    bucket_variable: Variable
+    bucket_width: float
+    origin: Optional[Literal]
+    methods: List[Tuple[Variable, LiteralType["null", "locf", "linear"]]]
+    start: Optional[Literal]
+    end: Optional[Literal]
+    """
+    Completes the time buckets of each group from its first to its last bucket, 
+    and fills the values of the aggregations in the added buckets. The bucket width is given in seconds.
+    The start and end bound the bucketed timestamps when they are given by the filters of the query.
+    Gaps are filled by chrontext after the query, except for BigQuery where GAP_FILL is used, 
+    and TimescaleDB where time_bucket_gapfill is used when the start and end are known.
+    """
+
+
+class Window:
+    function: LiteralType["lag", "lead", "rolling_mean", "rolling_sum", "cumulative_sum", "delta", "derivative", "row_number", "reverse_row_number"]
+    value: Variable
+    timestamp: Variable
+    partition_by: Variable
+    offset: Optional[int]
+    window_size: Optional[float]
+    """
+    Computes a function of the values of each series (partition_by), ordered by timestamp. 
+    The offset is set for lag and lead, and the window size is given in seconds for the rolling functions.
+    Derivatives are the change per second since the previous value.
+    Row numbers count from one at the earliest value, and reverse row numbers from one at the latest value.
     """
 
 
@@ -78,15 +121,22 @@ class VirtualizedQuery:
     identifier_name: Optional[str]
     column_mapping: Optional[Dict[str, str]]
     resource: Optional[str]
-    ids: Optional[List[str]]
+    ids: Optional[List[Union[str, int, float, bool]]]
+    id_datatype: Optional[str]
     grouping_column_name: Optional[str]
-    id_grouping_tuples: Optional[List[Tuple[str, int]]]
+    id_grouping_tuples: Optional[List[Tuple[Union[str, int, float, bool], int]]]
     Grouped:Type["PyVirtualizedQuery__Grouped"]
     by: List[Variable]
     aggregations: Optional[List[Tuple[Variable, AggregateExpression]]]
+    gap_fill: Optional[GapFill]
     ExpressionAs:Type["PyVirtualizedQuery__ExpressionAs"]
     variable: Optional[Variable]
     expression: Optional[Expression]
+    InnerJoin:Type["PyVirtualizedQuery__InnerJoin"]
+    queries: Optional[List[VirtualizedQuery]]
+    as_of_synchronizers: Optional[List[AsOfSynchronizer]]
+    Windowed:Type["PyVirtualizedQuery__Windowed"]
+    window: Optional[Window]
 
     def type_name(self) -> LiteralType["Filtered", "Basic"]:
         """
@@ -99,9 +149,10 @@ class PyVirtualizedQuery__Basic:
     identifier_name: str
     column_mapping: Dict[str, str]
     resource: str
-    ids: List[str]
+    ids: List[Union[str, int, float, bool]]
+    id_datatype: Optional[str]
     grouping_column_name: Optional[str]
-    id_grouping_tuples: Optional[List[Tuple[str, int]]]
+    id_grouping_tuples: Optional[List[Tuple[Union[str, int, float, bool], int]]]
 
     """
     Basic Virtualized Query
@@ -119,6 +170,7 @@ class PyVirtualizedQuery__Filtered:
 class PyVirtualizedQuery__Grouped:
     by: List[Variable]
     aggregations: List[Tuple[Variable, AggregateExpression]]
+    gap_fill: Optional[GapFill]
     """
     Grouped Virtualized Query
     """
@@ -131,3 +183,20 @@ class PyVirtualizedQuery__ExpressionAs:
     """
     ExpressionAs Virtualized Query    
     """
+
+
+class PyVirtualizedQuery__InnerJoin:
+    queries: List[VirtualizedQuery]
+    as_of_synchronizers: List[AsOfSynchronizer]
+    """
+    InnerJoin Virtualized Query
+    """
+
+
+class PyVirtualizedQuery__Windowed:
+    query: VirtualizedQuery
+    variable: Variable
+    window: Window
+    """
+    Windowed Virtualized Query
+    """
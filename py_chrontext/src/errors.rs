@@ -1,10 +1,13 @@
+use chrontext::combiner::CombinerError;
 use chrontext::errors::ChrontextError as RustChrontextError;
 use flight::client::ChrontextFlightClientError;
 use flight::server::ChrontextFlightServerError;
 use oxrdf::IriParseError;
 use pyo3::{create_exception, exceptions::PyException, prelude::*};
 use spargebra::SparqlSyntaxError;
+use sparql_database::native_oxigraph::NativeOxigraphError as RustNativeOxigraphError;
 use thiserror::Error;
+use virtualization::errors::TemplateExpansionError as RustTemplateExpansionError;
 
 #[derive(Error, Debug)]
 pub enum PyChrontextError {
@@ -26,11 +29,31 @@ pub enum PyChrontextError {
     FlightClientError(ChrontextFlightClientError),
     #[error(transparent)]
     FlightServerError(ChrontextFlightServerError),
+    #[error(transparent)]
+    TemplateExpansionError(RustTemplateExpansionError),
+    #[error("Unknown serialization format `{0}`, use `ntriples` or `turtle`")]
+    UnknownSerializationFormat(String),
+    #[error("Query parameter `{0}` has unsupported type `{1}`, use IRI, Literal, str, int, float or bool")]
+    QueryParameterTypeError(String, String),
+    #[error("Invalid SPARQL endpoint configuration: {0}")]
+    SparqlEndpointConfigError(String),
+    #[error(transparent)]
+    NativeOxigraphError(RustNativeOxigraphError),
 }
 
 impl std::convert::From<PyChrontextError> for PyErr {
     fn from(pqe: PyChrontextError) -> Self {
         match pqe {
+            PyChrontextError::ChrontextError(RustChrontextError::CombinerError(
+                ref e @ CombinerError::StaticQueryExecutionError {
+                    ref query,
+                    ref context,
+                    ..
+                },
+            )) => {
+                // The failing query and its context are passed as arguments to the exception
+                StaticQueryExecutionError::new_err((e.to_string(), query.clone(), context.clone()))
+            }
             PyChrontextError::ChrontextError(e) => ChrontextError::new_err(format!("{}", e)),
             PyChrontextError::DatatypeIRIParseError(err) => {
                 DatatypeIRIParseError::new_err(format!("{}", err))
@@ -50,6 +73,19 @@ impl std::convert::From<PyChrontextError> for PyErr {
             }
             PyChrontextError::FlightClientError(x) => FlightClientError::new_err(x.to_string()),
             PyChrontextError::FlightServerError(x) => FlightServerError::new_err(x.to_string()),
+            PyChrontextError::TemplateExpansionError(x) => {
+                TemplateExpansionError::new_err(x.to_string())
+            }
+            PyChrontextError::UnknownSerializationFormat(x) => {
+                UnknownSerializationFormat::new_err(x)
+            }
+            PyChrontextError::QueryParameterTypeError(..) => {
+                QueryParameterTypeError::new_err(pqe.to_string())
+            }
+            PyChrontextError::SparqlEndpointConfigError(..) => {
+                SparqlEndpointConfigError::new_err(pqe.to_string())
+            }
+            PyChrontextError::NativeOxigraphError(x) => NativeOxigraphError::new_err(x.to_string()),
         }
     }
 }
@@ -63,3 +99,62 @@ create_exception!(exceptions, MultipleVirtualizedDatabasesError, PyException);
 create_exception!(exceptions, FlightClientError, PyException);
 create_exception!(exceptions, FlightServerError, PyException);
 create_exception!(exceptions, ChrontextError, PyException);
+create_exception!(exceptions, TemplateExpansionError, PyException);
+create_exception!(exceptions, UnknownSerializationFormat, PyException);
+create_exception!(exceptions, QueryParameterTypeError, PyException);
+create_exception!(exceptions, SparqlEndpointConfigError, PyException);
+create_exception!(exceptions, StaticQueryExecutionError, PyException);
+create_exception!(exceptions, NativeOxigraphError, PyException);
+
+// The exceptions are added to the module so that they can be caught by type
+pub fn add_exceptions(py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add(
+        "DatatypeIRIParseError",
+        py.get_type::<DatatypeIRIParseError>(),
+    )?;
+    m.add(
+        "DataProductQueryParseError",
+        py.get_type::<DataProductQueryParseError>(),
+    )?;
+    m.add(
+        "MissingSPARQLDatabaseError",
+        py.get_type::<MissingSPARQLDatabaseError>(),
+    )?;
+    m.add(
+        "MultipleSPARQLDatabasesError",
+        py.get_type::<MultipleSPARQLDatabasesError>(),
+    )?;
+    m.add(
+        "MissingVirtualizedDatabaseError",
+        py.get_type::<MissingVirtualizedDatabaseError>(),
+    )?;
+    m.add(
+        "MultipleVirtualizedDatabasesError",
+        py.get_type::<MultipleVirtualizedDatabasesError>(),
+    )?;
+    m.add("FlightClientError", py.get_type::<FlightClientError>())?;
+    m.add("FlightServerError", py.get_type::<FlightServerError>())?;
+    m.add("ChrontextError", py.get_type::<ChrontextError>())?;
+    m.add(
+        "TemplateExpansionError",
+        py.get_type::<TemplateExpansionError>(),
+    )?;
+    m.add(
+        "UnknownSerializationFormat",
+        py.get_type::<UnknownSerializationFormat>(),
+    )?;
+    m.add(
+        "QueryParameterTypeError",
+        py.get_type::<QueryParameterTypeError>(),
+    )?;
+    m.add(
+        "SparqlEndpointConfigError",
+        py.get_type::<SparqlEndpointConfigError>(),
+    )?;
+    m.add(
+        "StaticQueryExecutionError",
+        py.get_type::<StaticQueryExecutionError>(),
+    )?;
+    m.add("NativeOxigraphError", py.get_type::<NativeOxigraphError>())?;
+    Ok(())
+}
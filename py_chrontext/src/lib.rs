@@ -35,40 +35,59 @@ static GLOBAL: Jemalloc = Jemalloc;
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
 
-use crate::errors::PyChrontextError;
-use chrontext::engine::{Engine, EngineConfig};
+use crate::errors::{add_exceptions, PyChrontextError};
+use chrontext::construct::{triples_to_df, triples_to_ntriples, triples_to_turtle};
+use chrontext::engine::{Engine, EngineConfig, QueryOptions};
+use chrontext::errors::ChrontextError as RustChrontextError;
+use chrontext::prepared::PreparedQuery;
 use flight::client::ChrontextFlightClient;
 use flight::server::ChrontextFlightServer;
 use log::{debug, info};
+use oxrdf::{Literal, Variable};
 use postgres::catalog::{Catalog, DataProduct};
 use postgres::server::{start_server, Config};
 use pydf_io::to_python::{df_to_py_df, fix_cats_and_multicolumns};
 use pyo3::prelude::*;
-use pyo3::types::PyDict;
+use pyo3::types::{PyBool, PyDict, PyFloat, PyInt, PyString};
+use pyo3::IntoPyObjectExt;
 use representation::python::{PyIRI, PyLiteral, PyPrefix, PyRDFType, PyVariable, PyXSDDuration};
 use representation::solution_mapping::EagerSolutionMappings;
 use representation::BaseRDFNodeType;
 use secrecy::SecretString;
+use sparesults::QueryResultsFormat;
+use spargebra::term::GroundTerm;
 use sparql_database::embedded_oxigraph::EmbeddedOxigraph;
+use sparql_database::endpoint::{SparqlEndpointAuth, SparqlEndpointConfig, SparqlEndpointMethod};
+use sparql_database::native_oxigraph::NativeOxigraph;
 use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use templates::python::{a, py_triple, PyArgument, PyInstance, PyParameter, PyTemplate, PyXSD};
-use tokio::runtime::Builder;
+use tokio::runtime::{Builder, Runtime};
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 use virtualization::bigquery::VirtualizedBigQueryDatabase;
 use virtualization::python::VirtualizedPythonDatabase;
 use virtualization::{Virtualization, VirtualizedDatabase};
 use virtualized_query::python::{
-    PyAggregateExpression, PyExpression, PyOrderExpression, PyVirtualizedQuery,
+    PyAggregateExpression, PyAsOfSynchronizer, PyExpression, PyGapFill, PyOrderExpression,
+    PyVirtualizedQuery, PyWindow,
 };
 
+const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
 #[pyclass(name = "Engine")]
 pub struct PyEngine {
     engine: Option<Engine>,
-    sparql_endpoint: Option<String>,
+    sparql_endpoint: Option<SparqlEndpointConfig>,
     sparql_embedded_oxigraph: Option<Py<PyAny>>,
     virtualized_python_database: Option<VirtualizedPythonDatabase>,
     virtualized_bigquery_database: Option<PyVirtualizedBigQueryDatabase>,
     resources: HashMap<String, PyTemplate>,
+    templates: Vec<PyTemplate>,
+    runtime: Runtime,
 }
 
 impl PyEngine {
@@ -76,9 +95,18 @@ impl PyEngine {
         resources: HashMap<String, PyTemplate>,
         virtualized_python_database: Option<VirtualizedPythonDatabase>,
         virtualized_bigquery_database: Option<PyVirtualizedBigQueryDatabase>,
-        sparql_endpoint: Option<String>,
+        sparql_endpoint: Option<PySparqlEndpointArgument>,
         sparql_embedded_oxigraph: Option<Py<PyAny>>,
+        templates: Option<Vec<PyTemplate>>,
     ) -> PyResult<PyEngine> {
+        let sparql_endpoint = sparql_endpoint.map(|x| match x {
+            // Plain URLs are queried with the same parameters as in earlier versions
+            PySparqlEndpointArgument::Url(url) => SparqlEndpointConfig {
+                json_format_parameters: true,
+                ..SparqlEndpointConfig::new(url)
+            },
+            PySparqlEndpointArgument::SparqlEndpoint(endpoint) => endpoint.config,
+        });
         let num_sparql =
             sparql_endpoint.is_some() as usize + sparql_embedded_oxigraph.is_some() as usize;
 
@@ -99,6 +127,8 @@ impl PyEngine {
             return Err(PyChrontextError::MultipleVirtualizedDatabasesError.into());
         }
 
+        let mut builder = Builder::new_multi_thread();
+        builder.enable_all();
         let engine = PyEngine {
             engine: None,
             sparql_endpoint,
@@ -106,6 +136,8 @@ impl PyEngine {
             virtualized_python_database,
             virtualized_bigquery_database,
             resources,
+            templates: templates.unwrap_or_default(),
+            runtime: builder.build()?,
         };
         Ok(engine)
     }
@@ -114,13 +146,14 @@ impl PyEngine {
 #[pymethods]
 impl PyEngine {
     #[new]
-    #[pyo3(signature = (resources, virtualized_python_database=None, virtualized_bigquery_database=None, sparql_endpoint=None, sparql_embedded_oxigraph=None))]
+    #[pyo3(signature = (resources, virtualized_python_database=None, virtualized_bigquery_database=None, sparql_endpoint=None, sparql_embedded_oxigraph=None, templates=None))]
     pub fn new<'py>(
         resources: HashMap<String, PyTemplate>,
         virtualized_python_database: Option<VirtualizedPythonDatabase>,
         virtualized_bigquery_database: Option<PyVirtualizedBigQueryDatabase>,
-        sparql_endpoint: Option<String>,
+        sparql_endpoint: Option<PySparqlEndpointArgument>,
         sparql_embedded_oxigraph: Option<Py<PyAny>>,
+        templates: Option<Vec<PyTemplate>>,
     ) -> PyResult<PyEngine> {
         Self::new_impl(
             resources,
@@ -128,6 +161,7 @@ impl PyEngine {
             virtualized_bigquery_database,
             sparql_endpoint,
             sparql_embedded_oxigraph,
+            templates,
         )
     }
 
@@ -143,30 +177,35 @@ impl PyEngine {
             } else {
                 panic!("Should never happen");
             };
-            let sparql_endpoint = if let Some(endpoint) = &self.sparql_endpoint {
-                Some(endpoint.clone())
-            } else {
-                None
-            };
-
-            let sparql_oxigraph_config = if let Some(store) = &self.sparql_embedded_oxigraph {
-                Some(EmbeddedOxigraph {
-                    store: store.clone(),
-                })
-            } else {
-                None
-            };
+            let sparql_endpoint = self.sparql_endpoint.clone();
+
+            let mut sparql_oxigraph_config = None;
+            let mut sparql_native_oxigraph = None;
+            if let Some(store) = &self.sparql_embedded_oxigraph {
+                // Files are loaded into a store that is queried without going through Python
+                if let Some(paths) = Python::with_gil(|py| rdf_file_paths(store.bind(py))) {
+                    sparql_native_oxigraph = Some(
+                        NativeOxigraph::from_files(&paths)
+                            .map_err(PyChrontextError::NativeOxigraphError)?,
+                    );
+                } else {
+                    sparql_oxigraph_config = Some(EmbeddedOxigraph {
+                        store: store.clone(),
+                    });
+                }
+            }
 
             let mut virtualization_map = HashMap::new();
             for (k, v) in &self.resources {
                 virtualization_map.insert(k.clone(), v.template.clone());
             }
-            let virtualization = Virtualization {
-                resources: virtualization_map,
-            };
+            let templates: Vec<_> = self.templates.iter().map(|x| x.template.clone()).collect();
+            let virtualization = Virtualization::new(virtualization_map, &templates)
+                .map_err(PyChrontextError::TemplateExpansionError)?;
 
             let config = EngineConfig {
                 sparql_oxigraph_config,
+                sparql_native_oxigraph,
                 virtualized_database,
                 sparql_endpoint,
                 virtualization,
@@ -178,26 +217,29 @@ impl PyEngine {
         Ok(())
     }
 
-    #[pyo3(signature = (sparql, native_dataframe=None, include_datatypes=None))]
+    #[pyo3(signature = (sparql, native_dataframe=None, include_datatypes=None, timeout=None))]
     pub fn query(
         &mut self,
         sparql: &str,
         native_dataframe: Option<bool>,
         include_datatypes: Option<bool>,
+        timeout: Option<f64>,
         py: Python<'_>,
     ) -> PyResult<PyObject> {
         if self.engine.is_none() {
             self.init()?;
         }
 
-        let mut builder = Builder::new_multi_thread();
-        builder.enable_all();
-        let (mut df, mut datatypes, pushdown_contexts) = py.allow_threads(move || {
-            builder
-                .build()
-                .unwrap()
-                .block_on(self.engine.as_mut().unwrap().query(sparql))
-                .map_err(|err| PyChrontextError::ChrontextError(err))
+        let options = query_options(timeout);
+        let (mut df, mut datatypes, pushdown_contexts) = py.allow_threads(|| {
+            block_on_interruptible(
+                &self.runtime,
+                self.engine
+                    .as_ref()
+                    .unwrap()
+                    .query_with_options(sparql, &options),
+                &options.cancellation_token,
+            )
         })?;
 
         (df, datatypes) =
@@ -212,6 +254,93 @@ impl PyEngine {
         Ok(pydf)
     }
 
+    pub fn prepare(slf: Bound<'_, Self>, sparql: &str) -> PyResult<PyPreparedQuery> {
+        let prepared_query = {
+            let mut engine = slf.borrow_mut();
+            if engine.engine.is_none() {
+                engine.init()?;
+            }
+            engine
+                .engine
+                .as_ref()
+                .unwrap()
+                .prepare(sparql)
+                .map_err(PyChrontextError::ChrontextError)?
+        };
+        Ok(PyPreparedQuery {
+            engine: slf.unbind(),
+            prepared_query,
+        })
+    }
+
+    #[pyo3(signature = (sparql, format=None, timeout=None))]
+    pub fn construct(
+        &mut self,
+        sparql: &str,
+        format: Option<&str>,
+        timeout: Option<f64>,
+        py: Python<'_>,
+    ) -> PyResult<PyObject> {
+        if self.engine.is_none() {
+            self.init()?;
+        }
+
+        let options = query_options(timeout);
+        let triples = py.allow_threads(|| {
+            block_on_interruptible(
+                &self.runtime,
+                self.engine
+                    .as_ref()
+                    .unwrap()
+                    .construct_with_options(sparql, &options),
+                &options.cancellation_token,
+            )
+        })?;
+        match format {
+            None => {
+                let (df, datatypes) = triples_to_df(&triples)
+                    .map_err(|e| PyChrontextError::ChrontextError(e.into()))?;
+                df_to_py_df(df, datatypes, None, false, py)
+            }
+            Some("ntriples") => triples_to_ntriples(&triples).into_py_any(py),
+            Some("turtle") => triples_to_turtle(&triples).into_py_any(py),
+            Some(f) => Err(PyChrontextError::UnknownSerializationFormat(f.to_string()).into()),
+        }
+    }
+
+    #[pyo3(signature = (sparql, timeout=None))]
+    pub fn ask(&mut self, sparql: &str, timeout: Option<f64>, py: Python<'_>) -> PyResult<bool> {
+        if self.engine.is_none() {
+            self.init()?;
+        }
+
+        let options = query_options(timeout);
+        let answer = py.allow_threads(|| {
+            block_on_interruptible(
+                &self.runtime,
+                self.engine
+                    .as_ref()
+                    .unwrap()
+                    .ask_with_options(sparql, &options),
+                &options.cancellation_token,
+            )
+        })?;
+        Ok(answer)
+    }
+
+    pub fn is_hybrid(&mut self, sparql: &str) -> PyResult<bool> {
+        if self.engine.is_none() {
+            self.init()?;
+        }
+        let is_hybrid = self
+            .engine
+            .as_ref()
+            .unwrap()
+            .is_hybrid(sparql)
+            .map_err(PyChrontextError::ChrontextError)?;
+        Ok(is_hybrid)
+    }
+
     pub fn serve_postgres(&mut self, catalog: PyCatalog, py: Python) -> PyResult<()> {
         py.allow_threads(move || {
             if self.engine.is_none() {
@@ -220,11 +349,7 @@ impl PyEngine {
             let catalog = catalog.to_rust()?;
 
             let config = Config::default();
-            let mut builder = Builder::new_multi_thread();
-            builder.enable_all();
-            builder
-                .build()
-                .unwrap()
+            self.runtime
                 .block_on(start_server(self.engine.take().unwrap(), config, catalog))
                 .unwrap();
             Ok(())
@@ -238,11 +363,7 @@ impl PyEngine {
             }
             let flight_server =
                 ChrontextFlightServer::new(Some(Arc::new(self.engine.take().unwrap())));
-            let mut builder = Builder::new_multi_thread();
-            builder.enable_all();
-            builder
-                .build()
-                .unwrap()
+            self.runtime
                 .block_on(flight_server.serve(address))
                 .map_err(|x| PyChrontextError::FlightServerError(x))?;
             Ok(())
@@ -250,6 +371,140 @@ impl PyEngine {
     }
 }
 
+#[pyclass(name = "PreparedQuery")]
+pub struct PyPreparedQuery {
+    engine: Py<PyEngine>,
+    prepared_query: PreparedQuery,
+}
+
+#[pymethods]
+impl PyPreparedQuery {
+    #[pyo3(signature = (native_dataframe=None, include_datatypes=None, timeout=None, **parameters))]
+    pub fn query(
+        &self,
+        native_dataframe: Option<bool>,
+        include_datatypes: Option<bool>,
+        timeout: Option<f64>,
+        parameters: Option<&Bound<'_, PyDict>>,
+        py: Python<'_>,
+    ) -> PyResult<PyObject> {
+        let mut bindings = HashMap::new();
+        if let Some(parameters) = parameters {
+            for (k, v) in parameters.iter() {
+                let name: String = k.extract()?;
+                let term = query_parameter_term(&name, &v)?;
+                bindings.insert(Variable::new_unchecked(name), term);
+            }
+        }
+
+        {
+            let mut py_engine = self.engine.borrow_mut(py);
+            if py_engine.engine.is_none() {
+                py_engine.init()?;
+            }
+        }
+        // A shared borrow lets other prepared queries of the engine run at the same time
+        let py_engine = self.engine.borrow(py);
+        let engine = py_engine.engine.as_ref().unwrap();
+        let runtime = &py_engine.runtime;
+        let prepared_query = &self.prepared_query;
+
+        let options = query_options(timeout);
+        let (mut df, mut datatypes, pushdown_contexts) = py.allow_threads(|| {
+            block_on_interruptible(
+                runtime,
+                engine.query_prepared_with_options(prepared_query, &bindings, &options),
+                &options.cancellation_token,
+            )
+        })?;
+
+        (df, datatypes) =
+            fix_cats_and_multicolumns(df, datatypes, native_dataframe.unwrap_or(false));
+        let pydf = df_to_py_df(
+            df,
+            datatypes,
+            Some(pushdown_contexts),
+            include_datatypes.unwrap_or(false),
+            py,
+        )?;
+        Ok(pydf)
+    }
+}
+
+fn rdf_file_paths(store: &Bound<'_, PyAny>) -> Option<Vec<PathBuf>> {
+    if let Ok(path) = store.extract::<PathBuf>() {
+        Some(vec![path])
+    } else if let Ok(paths) = store.extract::<Vec<PathBuf>>() {
+        Some(paths)
+    } else {
+        None
+    }
+}
+
+fn query_options(timeout: Option<f64>) -> QueryOptions {
+    QueryOptions {
+        timeout: timeout.map(Duration::from_secs_f64),
+        cancellation_token: CancellationToken::new(),
+    }
+}
+
+// Signals such as KeyboardInterrupt are checked periodically while the query runs,
+// and cancel the query when raised.
+// Calls to a Python virtualized database are synchronous and hold the GIL, so neither signals
+// nor timeouts are observed before such a call returns.
+fn block_on_interruptible<F, T>(
+    runtime: &Runtime,
+    future: F,
+    cancellation_token: &CancellationToken,
+) -> PyResult<T>
+where
+    F: Future<Output = Result<T, RustChrontextError>>,
+{
+    runtime.block_on(async {
+        tokio::pin!(future);
+        let mut interrupt = None;
+        loop {
+            tokio::select! {
+                res = &mut future => {
+                    return match interrupt {
+                        Some(err) => Err(err),
+                        None => res.map_err(|e| PyChrontextError::ChrontextError(e).into()),
+                    };
+                }
+                _ = sleep(SIGNAL_CHECK_INTERVAL), if interrupt.is_none() => {
+                    if let Err(err) = Python::with_gil(|py| py.check_signals()) {
+                        cancellation_token.cancel();
+                        interrupt = Some(err);
+                    }
+                }
+            }
+        }
+    })
+}
+
+fn query_parameter_term(name: &str, value: &Bound<'_, PyAny>) -> PyResult<GroundTerm> {
+    let term = if let Ok(iri) = value.downcast::<PyIRI>() {
+        GroundTerm::NamedNode(iri.borrow().iri.clone())
+    } else if let Ok(literal) = value.downcast::<PyLiteral>() {
+        GroundTerm::Literal(literal.borrow().literal.clone())
+    } else if value.is_instance_of::<PyBool>() {
+        GroundTerm::Literal(Literal::from(value.extract::<bool>()?))
+    } else if value.is_instance_of::<PyInt>() {
+        GroundTerm::Literal(Literal::from(value.extract::<i64>()?))
+    } else if value.is_instance_of::<PyFloat>() {
+        GroundTerm::Literal(Literal::from(value.extract::<f64>()?))
+    } else if value.is_instance_of::<PyString>() {
+        GroundTerm::Literal(Literal::new_simple_literal(value.extract::<String>()?))
+    } else {
+        return Err(PyChrontextError::QueryParameterTypeError(
+            name.to_string(),
+            value.get_type().name()?.to_string(),
+        )
+        .into());
+    };
+    Ok(term)
+}
+
 #[derive(Clone)]
 #[pyclass(name = "FlightClient")]
 pub struct PyFlightClient {
@@ -322,6 +577,100 @@ impl PyFlightClient {
     }
 }
 
+#[pyclass(name = "SparqlEndpoint")]
+#[derive(Clone)]
+pub struct PySparqlEndpoint {
+    config: SparqlEndpointConfig,
+}
+
+#[pymethods]
+impl PySparqlEndpoint {
+    #[new]
+    #[pyo3(signature = (endpoint, method=None, username=None, password=None, bearer_token=None, headers=None, default_graph_uris=None, named_graph_uris=None, timeout=None, results_format=None))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        endpoint: String,
+        method: Option<&str>,
+        username: Option<String>,
+        password: Option<String>,
+        bearer_token: Option<String>,
+        headers: Option<HashMap<String, String>>,
+        default_graph_uris: Option<Vec<String>>,
+        named_graph_uris: Option<Vec<String>>,
+        timeout: Option<f64>,
+        results_format: Option<&str>,
+    ) -> PyResult<PySparqlEndpoint> {
+        let method = match method {
+            None | Some("get") => SparqlEndpointMethod::Get,
+            Some("post") => SparqlEndpointMethod::Post,
+            Some("post_form") => SparqlEndpointMethod::PostForm,
+            Some(m) => {
+                return Err(PyChrontextError::SparqlEndpointConfigError(format!(
+                    "unknown method `{m}`, use `get`, `post` or `post_form`"
+                ))
+                .into())
+            }
+        };
+        let results_format = match results_format {
+            None => None,
+            Some("json") => Some(QueryResultsFormat::Json),
+            Some("xml") => Some(QueryResultsFormat::Xml),
+            Some("tsv") => Some(QueryResultsFormat::Tsv),
+            Some(f) => {
+                return Err(PyChrontextError::SparqlEndpointConfigError(format!(
+                    "unknown results format `{f}`, use `json`, `xml` or `tsv`"
+                ))
+                .into())
+            }
+        };
+        let auth = match (username, password, bearer_token) {
+            (None, None, None) => None,
+            (Some(username), password, None) => Some(SparqlEndpointAuth::Basic {
+                username,
+                password: password.map(SecretString::from),
+            }),
+            (None, None, Some(token)) => {
+                Some(SparqlEndpointAuth::Bearer(SecretString::from(token)))
+            }
+            (None, Some(_), _) => {
+                return Err(PyChrontextError::SparqlEndpointConfigError(
+                    "password requires a username".to_string(),
+                )
+                .into())
+            }
+            (_, _, Some(_)) => {
+                return Err(PyChrontextError::SparqlEndpointConfigError(
+                    "use either basic authentication or a bearer token".to_string(),
+                )
+                .into())
+            }
+        };
+        Ok(PySparqlEndpoint {
+            config: SparqlEndpointConfig {
+                endpoint,
+                method,
+                auth,
+                headers: headers
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(k, v)| (k, SecretString::from(v)))
+                    .collect(),
+                default_graph_uris: default_graph_uris.unwrap_or_default(),
+                named_graph_uris: named_graph_uris.unwrap_or_default(),
+                timeout: timeout.map(Duration::from_secs_f64),
+                results_format,
+                json_format_parameters: false,
+            },
+        })
+    }
+}
+
+#[derive(FromPyObject)]
+pub enum PySparqlEndpointArgument {
+    Url(String),
+    SparqlEndpoint(PySparqlEndpoint),
+}
+
 #[pyclass(name = "VirtualizedBigQueryDatabase")]
 #[derive(Clone)]
 pub struct PyVirtualizedBigQueryDatabase {
@@ -414,8 +763,10 @@ fn _chrontext(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     }
 
     m.add_class::<PyEngine>()?;
+    m.add_class::<PyPreparedQuery>()?;
     m.add_class::<VirtualizedPythonDatabase>()?;
     m.add_class::<PyVirtualizedBigQueryDatabase>()?;
+    m.add_class::<PySparqlEndpoint>()?;
     m.add_class::<PyDataProduct>()?;
     m.add_class::<PyCatalog>()?;
     m.add_class::<PyRDFType>()?;
@@ -432,11 +783,15 @@ fn _chrontext(_py: Python, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(a, m)?)?;
 
     m.add_class::<PyFlightClient>()?;
+    add_exceptions(_py, m)?;
 
     let child = PyModule::new(m.py(), "vq")?;
     child.add_class::<PyVirtualizedQuery>()?;
     child.add_class::<PyExpression>()?;
     child.add_class::<PyOrderExpression>()?;
+    child.add_class::<PyAsOfSynchronizer>()?;
+    child.add_class::<PyGapFill>()?;
+    child.add_class::<PyWindow>()?;
     child.add_class::<PyAggregateExpression>()?;
     child.add_class::<PyXSDDuration>()?;
     m.add_submodule(&child)?;
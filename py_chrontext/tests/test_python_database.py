@@ -1,19 +1,29 @@
+import re
 import pytest
 import polars as pl
 import duckdb
 import pathlib
+import statistics
+
+from datetime import datetime, timezone
+from typing import List
 
 from polars.testing import assert_frame_equal
-from sqlalchemy import Column, Table, MetaData, bindparam
-from pyoxigraph import Store
+from sqlalchemy import Column, Table, MetaData, bindparam, union
+from pyoxigraph import Store, NamedNode, Quad, RdfFormat, parse
+from pyoxigraph import Literal as OxigraphLiteral
 
 from chrontext import VirtualizedPythonDatabase, Engine, Template, Prefix, Variable, Parameter, \
-    RDFType, XSD, Triple
+    RDFType, XSD, Triple, IRI, Literal, StaticQueryExecutionError, \
+    ChrontextError
 
 PATH_HERE = pathlib.Path(__file__).parent
 TESTDATA_PATH = PATH_HERE / "testdata" / "python_based"
 TS1_CSV = str(TESTDATA_PATH / "ts1.csv")
 TS2_CSV = str(TESTDATA_PATH / "ts2.csv")
+TS3_CSV = str(TESTDATA_PATH / "ts3.csv")
+CT = Prefix("ct", "https://github.com/DataTreehouse/chrontext#")
+DP = Variable("dp")
 
 
 class CSVDB():
@@ -28,16 +38,42 @@ class CSVDB():
         con.execute("""CREATE TABLE ts2 ("timestamp" TIMESTAMPTZ, "value" INTEGER)""")
         ts_2 = pl.read_csv(TS2_CSV, try_parse_dates=True).with_columns(pl.col("timestamp").dt.replace_time_zone("UTC"))
         con.append("ts2", df=ts_2.to_pandas())
+        con.execute("""CREATE TABLE ts3 ("timestamp" TIMESTAMPTZ, "value" INTEGER)""")
+        ts_3 = pl.read_csv(TS3_CSV, try_parse_dates=True).with_columns(pl.col("timestamp").dt.replace_time_zone("UTC"))
+        con.append("ts3", df=ts_3.to_pandas())
         self.con = con
+        self.queries = []
 
     def query(self, sql: str):
+        self.queries.append(sql)
         df = self.con.execute(sql).pl()
         print(df)
         return df
 
 
-@pytest.fixture(scope="module")
-def engine() -> Engine:
+class SQLRecordingDB():
+    # Records the SQL of dialects that can not be executed by DuckDB
+    def __init__(self):
+        self.queries = []
+
+    def query(self, sql: str):
+        self.queries.append(sql)
+        raise NotImplementedError("The SQL is only recorded")
+
+
+class RecordingStore():
+    # Records the static queries sent to the store
+    def __init__(self, store: Store):
+        self.store = store
+        self.queries = []
+
+    def query(self, sparql: str):
+        self.queries.append(sparql)
+        return self.store.query(sparql)
+
+
+def create_virtualized_database(sql_dialect="postgres", database=None, ids=("ts1", "ts2", "ts3"),
+                                resource="my_resource") -> VirtualizedPythonDatabase:
     metadata = MetaData()
     ts1_table = Table(
         "ts1",
@@ -51,26 +87,43 @@ def engine() -> Engine:
         Column("timestamp"),
         Column("value")
     )
+    ts3_table = Table(
+        "ts3",
+        metadata,
+        Column("timestamp"),
+        Column("value")
+    )
     ts1 = ts1_table.select().add_columns(
-        bindparam("id1", "ts1").label("id"),
+        bindparam("id1", ids[0]).label("id"),
     )
     ts2 = ts2_table.select().add_columns(
-        bindparam("id2", "ts2").label("id"),
+        bindparam("id2", ids[1]).label("id"),
     )
-    sql = ts1.union(ts2)
-    vdb = VirtualizedPythonDatabase(
-        database=CSVDB(),
-        resource_sql_map={"my_resource": sql},
-        sql_dialect="postgres"
+    ts3 = ts3_table.select().add_columns(
+        bindparam("id3", ids[2]).label("id"),
     )
+    sql = union(ts1, ts2, ts3)
+    return VirtualizedPythonDatabase(
+        database=database if database is not None else CSVDB(),
+        resource_sql_map={resource: sql},
+        sql_dialect=sql_dialect
+    )
+
+
+def create_oxigraph_store() -> Store:
+    oxigraph_store = Store()
+    oxigraph_store.bulk_load(path=TESTDATA_PATH / "testdata.ttl")
+    return oxigraph_store
+
 
+def create_resources():
     ct = Prefix("ct", "https://github.com/DataTreehouse/chrontext#")
     xsd = XSD()
     id = Variable("id")
     timestamp = Variable("timestamp")
     value = Variable("value")
     dp = Variable("dp")
-    resources = {
+    return {
         "my_resource": Template(
             iri=ct.suf("my_resource"),
             parameters=[
@@ -85,16 +138,218 @@ def engine() -> Engine:
             ]
         )
     }
+
+
+def create_offset_store() -> Store:
+    oxigraph_store = create_oxigraph_store()
+    case = "http://example.org/case#"
+    types = "http://example.org/types#"
+    ct = "https://github.com/DataTreehouse/chrontext#"
+    rdf_type = NamedNode("http://www.w3.org/1999/02/22-rdf-syntax-ns#type")
+    for s, p, o in [
+        (NamedNode(case + "myWidget3"), rdf_type, NamedNode(types + "OffsetWidget")),
+        (NamedNode(case + "myWidget3"), NamedNode(types + "hasSensor"), NamedNode(case + "mySensor3")),
+        (NamedNode(case + "mySensor3"), NamedNode(ct + "hasTimeseries"), NamedNode(case + "myTimeseries3")),
+        (NamedNode(case + "myTimeseries3"), NamedNode(ct + "hasResource"), OxigraphLiteral("my_resource")),
+        (NamedNode(case + "myTimeseries3"), NamedNode(ct + "hasExternalId"), OxigraphLiteral("ts3")),
+    ]:
+        oxigraph_store.add(Quad(s, p, o))
+    return oxigraph_store
+
+
+def create_typed_id_store(ids, resource) -> Store:
+    # The time series are identified by the given terms instead of by strings
+    oxigraph_store = create_oxigraph_store()
+    ct = "https://github.com/DataTreehouse/chrontext#"
+    for p in [NamedNode(ct + "hasExternalId"), NamedNode(ct + "hasResource")]:
+        for q in list(oxigraph_store.quads_for_pattern(None, p, None)):
+            oxigraph_store.remove(q)
+    for i, id in enumerate(ids):
+        ts = NamedNode(f"http://example.org/case#myTimeseries{i + 1}")
+        oxigraph_store.add(Quad(ts, NamedNode(ct + "hasExternalId"), id))
+        oxigraph_store.add(Quad(ts, NamedNode(ct + "hasResource"), resource))
+    return oxigraph_store
+
+
+def create_named_graph_store() -> Store:
     oxigraph_store = Store()
-    oxigraph_store.bulk_load(path=TESTDATA_PATH / "testdata.ttl")
+    oxigraph_store.bulk_load(path=TESTDATA_PATH / "testdata.ttl", to_graph=NamedNode("http://example.org/plantA"))
+    return oxigraph_store
+
+
+def create_template_resources(template: Template, instances):
+    # my_resource gives each time series a data point ?dp, described by the instances of the template
+    xsd = XSD()
+    my_resource = Template(
+        iri=CT.suf("my_resource"),
+        parameters=[
+            Parameter(Variable("id"), rdf_type=RDFType.Literal(xsd.string)),
+            Parameter(Variable("timestamp"), rdf_type=RDFType.Literal(xsd.dateTime)),
+            Parameter(Variable("value"), rdf_type=RDFType.Literal(xsd.double)),
+        ],
+        instances=[Triple(Variable("id"), CT.suf("hasDataPoint"), DP)] + instances
+    )
+    return {"my_resource": my_resource}, [template]
+
+
+def create_nested_template_resources():
+    timestamp = Variable("timestamp")
+    value = Variable("value")
+    data_point = Template(
+        iri=CT.suf("DataPoint"),
+        parameters=[DP, timestamp, value],
+        instances=[
+            Triple(DP, CT.suf("hasValue"), value),
+            Triple(DP, CT.suf("hasTimestamp"), timestamp)
+        ]
+    )
+    return create_template_resources(data_point, [data_point.instance([DP, timestamp, value])])
+
+
+def create_constant_argument_template_resources():
+    s = Variable("s")
+    p = Variable("p")
+    o = Variable("o")
+    statement = Template(iri=CT.suf("Statement"), parameters=[s, p, o], instances=[Triple(s, p, o)])
+    return create_template_resources(statement, [
+        statement.instance([DP, CT.suf("hasValue"), Variable("value")]),
+        statement.instance([DP, CT.suf("hasTimestamp"), Variable("timestamp")]),
+    ])
+
+
+def create_default_value_template_resources():
+    value = Variable("value")
+    predicate = Variable("predicate")
+    measurement = Template(
+        iri=CT.suf("Measurement"),
+        parameters=[DP, value, Parameter(predicate, default_value=CT.suf("hasValue"))],
+        instances=[Triple(DP, predicate, value)]
+    )
+    return create_template_resources(measurement, [
+        measurement.instance([DP, value, None]),
+        measurement.instance([DP, Variable("timestamp"), CT.suf("hasTimestamp")]),
+    ])
+
+
+def create_unit_template_resources(unit: Parameter, argument):
+    # The data points are given directly, and the unit by an instance of the HasUnit template
+    has_unit = Template(
+        iri=CT.suf("HasUnit"),
+        parameters=[DP, unit],
+        instances=[Triple(DP, CT.suf("hasUnit"), unit.variable)]
+    )
+    return create_template_resources(has_unit, [
+        Triple(DP, CT.suf("hasValue"), Variable("value")),
+        Triple(DP, CT.suf("hasTimestamp"), Variable("timestamp")),
+        has_unit.instance([DP, argument]),
+    ])
+
+
+@pytest.fixture(scope="module")
+def engine() -> Engine:
     engine = Engine(
-        resources,
-        virtualized_python_database=vdb,
-        sparql_embedded_oxigraph=oxigraph_store)
+        create_resources(),
+        virtualized_python_database=create_virtualized_database(),
+        sparql_embedded_oxigraph=create_oxigraph_store())
     engine.init()
     return engine
 
 
+# Parameters of the recording engine, given with indirect parametrization
+AS_OF = {"store": create_offset_store}
+DUCKDB_AS_OF = {"store": create_offset_store, "sql_dialect": "duckdb"}
+TIMESCALE_AS_OF = {"store": create_offset_store, "sql_dialect": "timescale"}
+TIMESCALE_SQL = {"store": create_offset_store, "sql_dialect": "timescale", "database": SQLRecordingDB}
+BIGQUERY_SQL = {"store": create_offset_store, "sql_dialect": "bigquery", "database": SQLRecordingDB}
+NAMED_GRAPH = {"store": create_named_graph_store}
+NATIVE_OXIGRAPH = {"store": lambda: str(TESTDATA_PATH / "testdata.ttl")}
+NESTED_TEMPLATE = {"resources": create_nested_template_resources}
+CONSTANT_ARGUMENT_TEMPLATE = {"resources": create_constant_argument_template_resources}
+DEFAULT_VALUE_TEMPLATE = {"resources": create_default_value_template_resources}
+# The unit is not optional, so the instance without a unit is ignored
+SKIPPED_INSTANCE_TEMPLATE = {"resources": lambda: create_unit_template_resources(Parameter(Variable("unit")), None)}
+INTEGER_IDS = {
+    "store": lambda: create_typed_id_store(
+        [OxigraphLiteral("1", datatype=NamedNode(XSD().integer.iri)),
+         OxigraphLiteral("2", datatype=NamedNode(XSD().integer.iri))],
+        OxigraphLiteral("my_resource")),
+    "ids": (1, 2, 3),
+}
+IRI_IDS = {
+    "store": lambda: create_typed_id_store(
+        [NamedNode("http://example.org/case#ts1"), NamedNode("http://example.org/case#ts2")],
+        NamedNode("http://example.org/resources#my_resource")),
+    "ids": ("http://example.org/case#ts1", "http://example.org/case#ts2", "http://example.org/case#ts3"),
+    "resource": "http://example.org/resources#my_resource",
+}
+
+
+@pytest.fixture
+def recording_engine(request):
+    options = getattr(request, "param", {})
+    database = options.get("database", CSVDB)()
+    store = options.get("store", create_oxigraph_store)()
+    if isinstance(store, Store):
+        store = RecordingStore(store)
+    resources, templates = options.get("resources", lambda: (create_resources(), None))()
+    if "resource" in options:
+        resources = {options["resource"]: resources["my_resource"]}
+    engine = Engine(
+        resources,
+        virtualized_python_database=create_virtualized_database(
+            options.get("sql_dialect", "postgres"),
+            database,
+            options.get("ids", ("ts1", "ts2", "ts3")),
+            options.get("resource", "my_resource"),
+        ),
+        sparql_embedded_oxigraph=store,
+        templates=templates)
+    engine.init()
+    return engine, database, store
+
+
+SIMPLE_HYBRID_QUERY = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > "2022-06-01T08:46:53Z"^^xsd:dateTime && ?v < 200) .
+    }
+    """
+
+
+def expected_simple_hybrid() -> pl.DataFrame:
+    return pl.read_csv(
+        TESTDATA_PATH / "expected_simple_hybrid.csv", try_parse_dates=True
+    ).cast(
+        {"v": pl.Int32}
+    )
+
+
+def read_widget_series() -> List[pl.DataFrame]:
+    # The series of each widget with columns w, t and v, ordered by time
+    return [
+        pl.read_csv(csv, try_parse_dates=True).sort("timestamp").select(
+            pl.lit(w).alias("w"),
+            pl.col("timestamp").dt.replace_time_zone("UTC").alias("t"),
+            pl.col("value").cast(pl.Float64).alias("v"),
+        )
+        for w, csv in [("<http://example.org/case#myWidget1>", TS1_CSV),
+                       ("<http://example.org/case#myWidget2>", TS2_CSV)]
+    ]
+
+
+def assert_simple_hybrid(df: pl.DataFrame):
+    by = ["w", "s", "t"]
+    assert_frame_equal(df.sort(by), expected_simple_hybrid().sort(by))
+
+
 def test_simple_hybrid(engine):
     q = """
     PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
@@ -123,6 +378,574 @@ def test_simple_hybrid(engine):
     assert_frame_equal(df, expected)
     assert sm.pushdown_paths == [['ProjectInner']]
 
+def test_prepared_query(engine):
+    q = """
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w a $type .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > $start && ?v < $max) .
+    }
+    """
+    prepared = engine.prepare(q)
+    start = Literal("2022-06-01T08:46:53Z", data_type=XSD().dateTime)
+    sm = prepared.query(
+        include_datatypes=True,
+        type=IRI("http://example.org/types#BigWidget"),
+        start=start,
+        max=200,
+    )
+    assert_simple_hybrid(sm.mappings)
+    assert sm.pushdown_paths == [['ProjectInner']]
+
+    df = prepared.query(type=IRI("http://example.org/types#SmallWidget"), start=start, max=200)
+    assert df.height > 0
+    assert df.get_column("w").unique().to_list() == ["<http://example.org/case#myWidget2>"]
+
+def test_prepared_query_projected_parameters(recording_engine):
+    engine, database, store = recording_engine
+    q = """
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?type ?w ?t ?v WHERE {
+        ?w a ?type .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?v < $max) .
+    }
+    """
+    prepared = engine.prepare(q)
+    big_widget = IRI("http://example.org/types#BigWidget")
+    df = prepared.query(type=big_widget, max=200)
+    assert df.columns == ["type", "w", "t", "v"]
+    assert df.get_column("type").unique().to_list() == ["<http://example.org/types#BigWidget>"]
+    assert sorted(df.get_column("v").to_list()) == [1, 10, 100, 102, 105]
+
+    df = prepared.query(type=big_widget, max=20)
+    assert sorted(df.get_column("v").to_list()) == [1, 10]
+    # The filter is pushed into the virtualized query of each execution
+    assert len(database.queries) == 2
+    assert "200" in database.queries[0]
+    assert "200" not in database.queries[1]
+    assert all("BigWidget" in sparql for sparql in store.queries)
+
+    with pytest.raises(Exception, match="can not be bound"):
+        prepared.query(ts=IRI("http://example.org/case#myTimeseries1"), max=200)
+    with pytest.raises(Exception, match="no variable `missing`"):
+        prepared.query(missing=200, max=200)
+
+
+@pytest.mark.parametrize("projection,assignment,group_by", [
+    ("?w ?v_milli", "BIND(?v * 1000 AS ?v_milli)", ""),
+    ("?w (MAX(?v) * 1000 AS ?v_milli)", "", "GROUP BY ?w"),
+])
+def test_prepared_query_assigned_parameter(engine, projection, assignment, group_by):
+    q = f"""
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT {projection} WHERE {{
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasValue ?v .
+        {assignment}
+    }} {group_by}
+    """
+    prepared = engine.prepare(q)
+    # The variable is computed by the query, so binding it would contradict the query
+    with pytest.raises(ChrontextError, match="`v_milli` is assigned by the query and can not be bound"):
+        prepared.query(v_milli=1000)
+
+
+@pytest.mark.parametrize("recording_engine", [NATIVE_OXIGRAPH], indirect=True)
+def test_simple_hybrid_native_oxigraph(recording_engine):
+    engine, _, _ = recording_engine
+    assert_simple_hybrid(engine.query(SIMPLE_HYBRID_QUERY))
+
+
+AS_OF_QUERY = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?t1 ?v1 ?t3 ?v3 WHERE {
+        ?w1 a types:BigWidget .
+        ?w1 types:hasSensor ?s1 .
+        ?s1 chrontext:hasTimeseries ?ts1 .
+        ?ts1 chrontext:hasDataPoint ?dp1 .
+        ?dp1 chrontext:hasTimestamp ?t1 .
+        ?dp1 chrontext:hasValue ?v1 .
+        ?w3 a types:OffsetWidget .
+        ?w3 types:hasSensor ?s3 .
+        ?s3 chrontext:hasTimeseries ?ts3 .
+        ?ts3 chrontext:hasDataPoint ?dp3 .
+        ?dp3 chrontext:hasTimestamp ?t3 .
+        ?dp3 chrontext:hasValue ?v3 .
+        FILTER(chrontext:asOf(?t1, ?t3, "backward", "PT2S"^^xsd:duration))
+    }
+    """
+
+
+@pytest.mark.parametrize("recording_engine", [AS_OF], indirect=True)
+def test_as_of_hybrid(recording_engine):
+    engine, _, _ = recording_engine
+    df = engine.query(AS_OF_QUERY).sort("t1")
+    expected = pl.DataFrame({
+        "t1": ["2022-06-01T08:46:53", "2022-06-01T08:46:54", "2022-06-01T08:46:56", "2022-06-01T08:46:57"],
+        "v1": [10, 100, 102, 303],
+        "t3": ["2022-06-01T08:46:52.500", "2022-06-01T08:46:52.500", "2022-06-01T08:46:55.500",
+               "2022-06-01T08:46:55.500"],
+        "v3": [1000, 1000, 2000, 2000],
+    }).with_columns(
+        pl.col("t1").str.to_datetime().dt.replace_time_zone("UTC"),
+        pl.col("t3").str.to_datetime().dt.replace_time_zone("UTC"),
+    ).cast(df.schema)
+    assert_frame_equal(df, expected)
+
+
+@pytest.mark.parametrize("recording_engine", [DUCKDB_AS_OF], indirect=True)
+def test_as_of_pushdown_group_by_hybrid(recording_engine):
+    engine, _, _ = recording_engine
+    q = AS_OF_QUERY.replace("SELECT ?t1 ?v1 ?t3 ?v3 WHERE", "SELECT (COUNT(?v1) AS ?n) (SUM(?v3) AS ?sum_v3) WHERE")
+    df = engine.query(q)
+    assert df.get_column("n").to_list() == [4]
+    assert df.get_column("sum_v3").to_list() == [6000]
+
+
+def as_of_query(strategy: str, tolerance: str) -> str:
+    return AS_OF_QUERY.replace('"backward", "PT2S"', f'"{strategy}", "{tolerance}"')
+
+
+def expected_as_of(t1, v1, t3, v3, schema) -> pl.DataFrame:
+    return pl.DataFrame({
+        "t1": [f"2022-06-01T08:46:{t}" for t in t1],
+        "v1": v1,
+        "t3": [f"2022-06-01T08:46:{t}" for t in t3],
+        "v3": v3,
+    }).with_columns(
+        pl.col("t1").str.to_datetime().dt.replace_time_zone("UTC"),
+        pl.col("t3").str.to_datetime().dt.replace_time_zone("UTC"),
+    ).cast(schema)
+
+
+@pytest.mark.parametrize("recording_engine", [AS_OF], indirect=True)
+def test_as_of_forward_tolerance_hybrid(recording_engine):
+    engine, _, _ = recording_engine
+    df = engine.query(as_of_query("forward", "PT1S")).sort("t1")
+    expected = expected_as_of(["52", "55"], [1, 301], ["52.500", "55.500"], [1000, 2000], df.schema)
+    assert_frame_equal(df, expected)
+
+
+@pytest.mark.parametrize("recording_engine", [AS_OF], indirect=True)
+def test_as_of_nearest_tolerance_hybrid(recording_engine):
+    engine, _, _ = recording_engine
+    df = engine.query(as_of_query("nearest", "PT1S")).sort("t1")
+    expected = expected_as_of(
+        ["52", "53", "55", "56"],
+        [1, 10, 301, 102],
+        ["52.500", "52.500", "55.500", "55.500"],
+        [1000, 1000, 2000, 2000],
+        df.schema
+    )
+    assert_frame_equal(df, expected)
+
+
+@pytest.mark.parametrize("recording_engine", [DUCKDB_AS_OF], indirect=True)
+def test_as_of_duckdb_asof_join_sql(recording_engine):
+    engine, database, _ = recording_engine
+    df = engine.query(AS_OF_QUERY)
+    assert df.height == 4
+    [sql] = [q for q in database.queries if "ASOF JOIN" in q]
+    assert ">=" in sql
+    assert "INTERVAL '2000000 microseconds'" in sql
+
+    database.queries.clear()
+    df = engine.query(as_of_query("forward", "PT1S")).sort("t1")
+    assert df.get_column("v1").to_list() == [1, 301]
+    [sql] = [q for q in database.queries if "ASOF JOIN" in q]
+    assert ">=" not in sql
+    assert "INTERVAL '1000000 microseconds'" in sql
+
+    # Nearest matches can not be expressed as an ASOF JOIN, so the series are matched after they are queried
+    database.queries.clear()
+    df = engine.query(as_of_query("nearest", "PT1S")).sort("t1")
+    assert df.get_column("v1").to_list() == [1, 10, 301, 102]
+    assert len(database.queries) > 0
+    assert all("ASOF JOIN" not in q for q in database.queries)
+
+
+def gap_fill_query(time_filter: str = "") -> str:
+    return f"""
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?t 
+        (chrontext:interpolateLinear(?v) AS ?linear) 
+        (chrontext:locf(?v) AS ?locf) 
+        (chrontext:fillNull(?v) AS ?null) WHERE {{
+        ?w a types:OffsetWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t_inner .
+        ?dp chrontext:hasValue ?v .
+        {time_filter}
+        BIND(chrontext:FloorDateTimeToSecondsInterval(?t_inner, 1) AS ?t)
+    }} GROUP BY ?w ?t
+    """
+
+
+GAP_FILL_TIME_FILTER = """FILTER(?t_inner >= "2022-06-01T08:46:00+00:00"^^xsd:dateTime 
+        && ?t_inner < "2022-06-01T08:47:00+00:00"^^xsd:dateTime)"""
+
+
+# Without a time range in the query, TimescaleDB does not fill the gaps
+@pytest.mark.parametrize("recording_engine", [AS_OF, TIMESCALE_AS_OF], indirect=True)
+def test_gap_fill_hybrid(recording_engine):
+    engine, database, _ = recording_engine
+    df = engine.query(gap_fill_query()).sort("t")
+    expected = pl.DataFrame({
+        "w": ["<http://example.org/case#myWidget3>"] * 4,
+        "t": ["2022-06-01T08:46:52", "2022-06-01T08:46:53", "2022-06-01T08:46:54", "2022-06-01T08:46:55"],
+        "linear": [1000.0, 4000.0 / 3.0, 5000.0 / 3.0, 2000.0],
+        "locf": [1000.0, 1000.0, 1000.0, 2000.0],
+        "null": [1000.0, None, None, 2000.0],
+    }).with_columns(
+        pl.col("t").str.to_datetime().dt.replace_time_zone("UTC"),
+    ).cast(df.schema)
+    assert_frame_equal(df.select(expected.columns), expected)
+    # The buckets are averaged by the database, and the empty buckets are added by chrontext
+    [sql] = database.queries
+    assert "avg(" in sql
+    assert "GROUP BY" in sql
+    assert "GAP_FILL" not in sql
+    assert "time_bucket_gapfill" not in sql
+    assert database.con.execute(sql).pl().height == 2
+
+
+@pytest.mark.parametrize("recording_engine", [TIMESCALE_SQL], indirect=True)
+def test_gap_fill_time_range_timescale(recording_engine):
+    engine, database, _ = recording_engine
+    with pytest.raises(Exception, match="The SQL is only recorded"):
+        engine.query(gap_fill_query(GAP_FILL_TIME_FILTER))
+    [sql] = database.queries
+    start = int(datetime(2022, 6, 1, 8, 46, tzinfo=timezone.utc).timestamp()) * 1_000_000
+    end = int(datetime(2022, 6, 1, 8, 47, tzinfo=timezone.utc).timestamp()) * 1_000_000
+    # The time range is explicit, and the end is exclusive
+    assert "time_bucket_gapfill(CAST(1000000 AS BIGINT), " in sql
+    assert f"CAST({start} AS BIGINT), CAST({end + 1} AS BIGINT))" in sql
+    assert "interpolate(min(" in sql
+    assert "locf(min(" in sql
+    # The buckets are kept between the first and last bucket of each group
+    assert "BETWEEN" in sql
+    assert "gap_fill_first" in sql
+    assert "gap_fill_last" in sql
+
+
+@pytest.mark.parametrize("recording_engine", [BIGQUERY_SQL], indirect=True)
+def test_gap_fill_bigquery(recording_engine):
+    engine, database, _ = recording_engine
+    with pytest.raises(Exception, match="The SQL is only recorded"):
+        engine.query(gap_fill_query())
+    [sql] = database.queries
+    assert "FROM GAP_FILL((SELECT " in sql
+    assert "ts_column => 't'" in sql
+    assert "bucket_width => INTERVAL 1000000 MICROSECOND" in sql
+    assert "value_columns => [('linear', 'linear'), ('locf', 'locf'), ('null', 'null')]" in sql
+    assert re.search(r"partitioning_columns => \['\w+'\]", sql)
+    # Reserved words are quoted
+    assert "`null`" in sql
+
+
+@pytest.mark.parametrize("recording_engine", [NESTED_TEMPLATE], indirect=True)
+def test_simple_hybrid_nested_template(recording_engine):
+    engine, _, _ = recording_engine
+    sm = engine.query(SIMPLE_HYBRID_QUERY, include_datatypes=True)
+    assert_simple_hybrid(sm.mappings)
+    assert sm.pushdown_paths == [['ProjectInner']]
+
+
+@pytest.mark.parametrize("recording_engine", [CONSTANT_ARGUMENT_TEMPLATE, DEFAULT_VALUE_TEMPLATE,
+                                              SKIPPED_INSTANCE_TEMPLATE], indirect=True)
+def test_simple_hybrid_template_arguments(recording_engine):
+    engine, _, _ = recording_engine
+    # The predicates of the data points are given as a constant argument or by a default value
+    sm = engine.query(SIMPLE_HYBRID_QUERY, include_datatypes=True)
+    assert_simple_hybrid(sm.mappings)
+    assert sm.pushdown_paths == [['ProjectInner']]
+
+
+@pytest.mark.parametrize("parameter,argument,message", [
+    (Parameter(Variable("unit"), optional=True), None, "gives ottr:none to a triple"),
+    (Parameter(Variable("unit"), rdf_type=RDFType.IRI()), Literal("m"), "has the wrong type"),
+])
+def test_invalid_template_arguments(parameter, argument, message):
+    resources, templates = create_unit_template_resources(parameter, argument)
+    engine = Engine(
+        resources,
+        virtualized_python_database=create_virtualized_database(),
+        sparql_embedded_oxigraph=create_oxigraph_store(),
+        templates=templates)
+    with pytest.raises(Exception, match=message):
+        engine.init()
+
+
+@pytest.mark.parametrize("recording_engine,id_sql,ids", [
+    (INTEGER_IDS, "IN (1)", [1, 2]),
+    (IRI_IDS, "IN ('http://example.org/case#ts1')",
+     ["<http://example.org/case#ts1>", "<http://example.org/case#ts2>"]),
+], indirect=["recording_engine"])
+def test_simple_hybrid_typed_ids(recording_engine, id_sql, ids):
+    engine, database, _ = recording_engine
+    sm = engine.query(SIMPLE_HYBRID_QUERY, include_datatypes=True)
+    assert_simple_hybrid(sm.mappings)
+    # The ids are sent to the database with their own type
+    [sql] = database.queries
+    assert id_sql in sql
+    assert "'1'" not in sql
+
+    # The identifiers keep their type when joined with the virtualized results
+    q = """
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    SELECT ?id (COUNT(?v) AS ?n) WHERE {
+        ?ts chrontext:hasExternalId ?id .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasValue ?v .
+    } GROUP BY ?id
+    """
+    df = engine.query(q).sort("id")
+    assert df.get_column("id").to_list() == ids
+    assert all(n > 0 for n in df.get_column("n").to_list())
+
+
+def test_simple_hybrid_blank_node_data_point(engine):
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint [ chrontext:hasTimestamp ?t ; chrontext:hasValue ?v ] .
+        FILTER(?t > "2022-06-01T08:46:53Z"^^xsd:dateTime && ?v < 200) .
+    }
+    """
+    assert_simple_hybrid(engine.query(q))
+
+
+def test_simple_hybrid_base_iri(engine):
+    q = """
+    BASE <http://example.org/>
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    SELECT ?w ?s ?t ?v WHERE {
+        ?w a <types#BigWidget> .
+        ?w <types#hasSensor> ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > "2022-06-01T08:46:53Z"^^xsd:dateTime && ?v < 200) .
+    }
+    """
+    assert_simple_hybrid(engine.query(q))
+
+@pytest.mark.parametrize("recording_engine", [NAMED_GRAPH], indirect=True)
+def test_simple_hybrid_from(recording_engine):
+    engine, _, _ = recording_engine
+    # The data is only in a named graph, so it is found through the FROM clause of the static query
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s ?t ?v
+    FROM <http://example.org/plantA>
+    WHERE {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > "2022-06-01T08:46:53Z"^^xsd:dateTime && ?v < 200) .
+    }
+    """
+    assert_simple_hybrid(engine.query(q))
+
+@pytest.mark.parametrize("recording_engine", [NAMED_GRAPH], indirect=True)
+def test_simple_hybrid_from_named(recording_engine):
+    engine, _, _ = recording_engine
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?g ?w ?s ?t ?v
+    FROM NAMED <http://example.org/plantA>
+    WHERE {
+        GRAPH ?g {
+            ?w a types:BigWidget .
+            ?w types:hasSensor ?s .
+            ?s chrontext:hasTimeseries ?ts .
+        }
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > "2022-06-01T08:46:53Z"^^xsd:dateTime && ?v < 200) .
+    }
+    """
+    df = engine.query(q)
+    assert (df.get_column("g") == "<http://example.org/plantA>").all()
+    assert_simple_hybrid(df.drop("g"))
+
+@pytest.mark.parametrize("recording_engine", [NAMED_GRAPH], indirect=True)
+def test_simple_hybrid_graph(recording_engine):
+    engine, _, _ = recording_engine
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?g ?w ?s ?t ?v WHERE {
+        GRAPH ?g {
+            ?w a types:BigWidget .
+            ?w types:hasSensor ?s .
+            ?s chrontext:hasTimeseries ?ts .
+            ?ts chrontext:hasDataPoint ?dp .
+            ?dp chrontext:hasTimestamp ?t .
+            ?dp chrontext:hasValue ?v .
+            FILTER(?t > "2022-06-01T08:46:53Z"^^xsd:dateTime && ?v < 200) .
+        }
+    }
+    """
+    df = engine.query(q)
+    assert (df.get_column("g") == "<http://example.org/plantA>").all()
+    assert_simple_hybrid(df.drop("g"))
+
+def test_static_query(engine):
+    q = """
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?s WHERE {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+    }
+    """
+    assert not engine.is_hybrid(q)
+    sm = engine.query(q, include_datatypes=True)
+    assert sm.mappings.to_dicts() == [
+        {"w": "<http://example.org/case#myWidget1>", "s": "<http://example.org/case#mySensor1>"}
+    ]
+    assert sm.pushdown_paths == []
+
+
+def test_static_query_error(engine):
+    q = """
+    SELECT ?a WHERE {
+        SERVICE <http://127.0.0.1:1/sparql> { ?a ?b ?c }
+    }
+    """
+    with pytest.raises(StaticQueryExecutionError, match="Static query execution error") as e:
+        engine.query(q)
+    # The failing query and its context are the arguments of the exception
+    message, query, context = e.value.args
+    assert "SERVICE <http://127.0.0.1:1/sparql>" in query
+    assert f"at context `{context}`" in message
+
+
+@pytest.mark.parametrize("condition,function", [
+    ("chrontext:latest(?t)", "ct:latest or ct:earliest"),
+    ('chrontext:asOf(?t, ?t2, "backward")', "ct:asOf"),
+])
+def test_static_query_chrontext_function(engine, condition, function):
+    q = f"""
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w WHERE {{
+        ?w types:hasSensor ?s .
+        ?s types:installed ?t .
+        ?s types:calibrated ?t2 .
+        FILTER({condition})
+    }}
+    """
+    # The query does not use virtualized resources, so the store would be asked to evaluate the function
+    with pytest.raises(ChrontextError, match=f"{re.escape(function)} can only be used in queries over virtualized resources"):
+        engine.query(q)
+
+
+def test_is_hybrid(engine):
+    q = """
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    SELECT ?s ?v WHERE {
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasValue ?v .
+    }
+    """
+    assert engine.is_hybrid(q)
+
+def test_construct_hybrid(engine):
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    CONSTRUCT { ?s types:hasMeasurement [ types:at ?t ; types:value ?v ] } WHERE {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > "2022-06-01T08:46:53Z"^^xsd:dateTime && ?v < 200) .
+    }
+    """
+    expected = expected_simple_hybrid()
+    df = engine.construct(q)
+    assert df.columns == ["subject", "predicate", "object"]
+    assert df.height == expected.height * 3
+    assert df.filter(pl.col("predicate") == "<http://example.org/types#hasMeasurement>").height == expected.height
+    nt = engine.construct(q, format="ntriples")
+    assert len(nt.splitlines()) == expected.height * 3
+    ttl = engine.construct(q, format="turtle")
+    nt_triples = set(parse(input=nt, format=RdfFormat.N_TRIPLES))
+    assert len(nt_triples) == expected.height * 3
+    assert set(parse(input=ttl, format=RdfFormat.TURTLE)) == nt_triples
+
+def test_ask_hybrid(recording_engine):
+    engine, database, store = recording_engine
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    ASK {
+        ?w a types:BigWidget .
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(?t > "2022-06-01T08:46:53Z"^^xsd:dateTime && ?v < %s) .
+    }
+    """
+    assert engine.ask(q % "200")
+    assert not engine.ask(q % "-200")
+    # The single solution limit is computed by the virtualized database
+    assert len(database.queries) == 2
+    assert all(re.search(r"LIMIT\s+1\b", sql) for sql in database.queries)
+
+    q_static = """
+    PREFIX types:<http://example.org/types#>
+    ASK { ?w a types:BigWidget . }
+    """
+    assert engine.ask(q_static)
+    # Queries without virtualized triples are sent to the SPARQL database with the limit
+    assert re.search(r"LIMIT\s+1\b", store.queries[-1])
+
 def test_simple_hybrid_no_vq_matches_query(engine):
     q = """
     PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
@@ -196,6 +1019,341 @@ def test_pushdown_group_by_hybrid_query(engine):
     assert sm.pushdown_paths == [['ProjectInner', 'ExtendInner']]
 
 
+def test_pushdown_group_by_custom_aggregates_hybrid_query(recording_engine):
+    engine, database, _ = recording_engine
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w 
+        (chrontext:median(?v) AS ?median_v) 
+        (chrontext:percentile50(?v) AS ?p50_v) 
+        (chrontext:stddev(?v) AS ?stddev_v) 
+        (chrontext:first(?v) AS ?first_v) 
+        (chrontext:last(?v) AS ?last_v) WHERE {
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+    } GROUP BY ?w
+    """
+    df = engine.query(q).sort("w")
+    expected = pl.DataFrame({
+        "w": ["<http://example.org/case#myWidget1>", "<http://example.org/case#myWidget2>"],
+        "median_v": [103.5, 201.5],
+        "p50_v": [103.5, 201.5],
+        "stddev_v": [statistics.stdev([1, 10, 100, 301, 102, 303, 304, 105]),
+                     statistics.stdev([2, 20, 200, 201, 202, 203, 204, 206])],
+        "first_v": [1, 2],
+        "last_v": [105, 206],
+    }).cast(df.schema)
+    assert_frame_equal(df, expected)
+    # All the aggregates are computed in a single grouped query with one row per widget
+    [sql] = database.queries
+    assert sql.count("percentile_cont(0.5) WITHIN GROUP") == 2
+    assert "stddev_samp(" in sql
+    assert sql.count("array_agg(") == 2
+    assert "DESC" in sql
+    assert database.con.execute(sql).pl().height == 2
+
+
+def test_pushdown_group_by_synchronized_series_hybrid_query(recording_engine):
+    engine, database, _ = recording_engine
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w1 ?w2 (SUM(?v1 - ?v2) AS ?sum_diff) (COUNT(?v1) AS ?n) WHERE {
+        {
+            ?w1 a types:BigWidget .
+            ?w1 types:hasSensor ?s1 .
+            ?s1 chrontext:hasTimeseries ?ts1 .
+            ?ts1 chrontext:hasDataPoint ?dp1 .
+            ?dp1 chrontext:hasTimestamp ?t .
+            ?dp1 chrontext:hasValue ?v1 .
+        }
+        {
+            ?w2 a types:SmallWidget .
+            ?w2 types:hasSensor ?s2 .
+            ?s2 chrontext:hasTimeseries ?ts2 .
+            ?ts2 chrontext:hasDataPoint ?dp2 .
+            ?dp2 chrontext:hasTimestamp ?t .
+            ?dp2 chrontext:hasValue ?v2 .
+        }
+    } GROUP BY ?w1 ?w2
+    """
+    sm = engine.query(q, include_datatypes=True)
+    df = sm.mappings.cast({"sum_diff": pl.Int64, "n": pl.Int64})
+    expected = pl.DataFrame({
+        "w1": ["<http://example.org/case#myWidget1>"],
+        "w2": ["<http://example.org/case#myWidget2>"],
+        "sum_diff": [1226 - 1238],
+        "n": [8],
+    })
+    assert_frame_equal(df.select(expected.columns), expected)
+    assert sm.pushdown_paths == [['ProjectInner']]
+    # The series are joined on the timestamp and aggregated in a single query
+    [sql] = database.queries
+    assert "JOIN" in sql
+    assert "GROUP BY" in sql
+    assert database.con.execute(sql).pl().height == 1
+
+
+def test_pushdown_group_by_independent_series_hybrid_query(recording_engine):
+    engine, database, _ = recording_engine
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w1 ?w2 (SUM(?v1) AS ?sum_v1) (COUNT(*) AS ?n) (AVG(?v2) AS ?avg_v2) (MAX(?v2) AS ?max_v2) WHERE {
+        ?w1 types:hasSensor ?s1 .
+        ?s1 chrontext:hasTimeseries ?ts1 .
+        ?ts1 chrontext:hasDataPoint ?dp1 .
+        ?dp1 chrontext:hasTimestamp ?t1 .
+        ?dp1 chrontext:hasValue ?v1 .
+        ?w2 types:hasSensor ?s2 .
+        ?s2 chrontext:hasTimeseries ?ts2 .
+        ?ts2 chrontext:hasDataPoint ?dp2 .
+        ?dp2 chrontext:hasTimestamp ?t2 .
+        ?dp2 chrontext:hasValue ?v2 .
+    } GROUP BY ?w1 ?w2
+    """
+    by = ["w1", "w2"]
+    sm = engine.query(q, include_datatypes=True)
+    df = sm.mappings.with_columns(pl.col(["sum_v1", "n", "avg_v2", "max_v2"]).cast(pl.Float64)).sort(by)
+    series = pl.concat(read_widget_series()).select("w", "v")
+    expected = series.rename({"w": "w1", "v": "v1"}).join(
+        series.rename({"w": "w2", "v": "v2"}), how="cross"
+    ).group_by(by).agg(
+        pl.col("v1").sum().alias("sum_v1"),
+        pl.len().cast(pl.Float64).alias("n"),
+        pl.col("v2").mean().alias("avg_v2"),
+        pl.col("v2").max().alias("max_v2"),
+    ).sort(by)
+    assert_frame_equal(df.select(expected.columns), expected)
+    assert sm.pushdown_paths == [['ProjectInner']]
+    # Each series is aggregated by its own query, with a row for each of the four groups
+    assert len(database.queries) == 2
+    assert all("GROUP BY" in sql for sql in database.queries)
+    assert any("sum(" in sql for sql in database.queries)
+    assert any("max(" in sql for sql in database.queries)
+    assert all(database.con.execute(sql).pl().height == 4 for sql in database.queries)
+
+
+def test_group_by_independent_series_several_static_solutions_hybrid_query(recording_engine):
+    engine, database, _ = recording_engine
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w1 (SUM(?v1) AS ?sum_v1) (COUNT(*) AS ?n) (MAX(?v2) AS ?max_v2) WHERE {
+        ?w1 types:hasSensor ?s1 .
+        ?s1 chrontext:hasTimeseries ?ts1 .
+        ?ts1 chrontext:hasDataPoint ?dp1 .
+        ?dp1 chrontext:hasTimestamp ?t1 .
+        ?dp1 chrontext:hasValue ?v1 .
+        ?w2 types:hasSensor ?s2 .
+        ?s2 chrontext:hasTimeseries ?ts2 .
+        ?ts2 chrontext:hasDataPoint ?dp2 .
+        ?dp2 chrontext:hasTimestamp ?t2 .
+        ?dp2 chrontext:hasValue ?v2 .
+    } GROUP BY ?w1
+    """
+    sm = engine.query(q, include_datatypes=True)
+    df = sm.mappings.with_columns(pl.col(["sum_v1", "n", "max_v2"]).cast(pl.Float64)).sort("w1")
+    series = pl.concat(read_widget_series()).select("w", "v")
+    expected = series.rename({"w": "w1", "v": "v1"}).join(
+        series.rename({"w": "w2", "v": "v2"}), how="cross"
+    ).group_by("w1").agg(
+        pl.col("v1").sum().alias("sum_v1"),
+        pl.len().cast(pl.Float64).alias("n"),
+        pl.col("v2").max().alias("max_v2"),
+    ).sort("w1")
+    assert_frame_equal(df.select(expected.columns), expected)
+    # Each group has a static solution for each ?w2, so the partial aggregates can not be combined
+    assert {tuple(p) for p in sm.pushdown_paths} == {('ProjectInner', 'GroupInner')}
+    assert df.height == 2
+    assert len(database.queries) > 0
+    assert all("GROUP BY" not in sql for sql in database.queries)
+
+
+def test_pushdown_window_hybrid_query(recording_engine):
+    engine, database, _ = recording_engine
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?t ?v ?lag_v ?delta_v ?cumsum_v ?mean_v ?derivative_v WHERE {
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        BIND(chrontext:lag(?v) AS ?lag_v)
+        BIND(chrontext:delta(?v) AS ?delta_v)
+        BIND(chrontext:cumulativeSum(?v) AS ?cumsum_v)
+        BIND(chrontext:rollingMean(?v, "PT2S"^^xsd:duration) AS ?mean_v)
+        BIND(chrontext:derivative(?v) AS ?derivative_v)
+    }
+    """
+    by = ["w", "t"]
+    sm = engine.query(q, include_datatypes=True)
+    value_cols = ["v", "lag_v", "delta_v", "cumsum_v", "mean_v", "derivative_v"]
+    df = sm.mappings.with_columns(pl.col(value_cols).cast(pl.Float64)).sort(by)
+    expected = pl.concat([
+        ts.with_columns(
+            pl.col("v").shift(1).alias("lag_v"),
+            (pl.col("v") - pl.col("v").shift(1)).alias("delta_v"),
+            pl.col("v").cum_sum().alias("cumsum_v"),
+            pl.col("v").rolling_mean_by("t", "2s", closed="both").alias("mean_v"),
+            (pl.col("v") - pl.col("v").shift(1)).alias("derivative_v"),
+        )
+        for ts in read_widget_series()
+    ]).sort(by)
+    assert_frame_equal(df.select(expected.columns), expected, check_dtypes=False)
+    assert sm.pushdown_paths == [['ProjectInner']]
+    [sql] = database.queries
+    assert "lag(" in sql
+    assert "RANGE BETWEEN INTERVAL '2000000 microseconds' PRECEDING AND CURRENT ROW" in sql
+    assert "ROWS BETWEEN UNBOUNDED PRECEDING AND CURRENT ROW" in sql
+    assert database.con.execute(sql).pl().height == 16
+
+
+def test_pushdown_window_filter_hybrid_query(recording_engine):
+    engine, database, _ = recording_engine
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?t ?v ?lag_v WHERE {
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        BIND(chrontext:lag(?v) AS ?lag_v)
+        FILTER(?t > "2022-06-01T08:46:55Z"^^xsd:dateTime)
+    }
+    """
+    by = ["w", "t"]
+    sm = engine.query(q, include_datatypes=True)
+    df = sm.mappings.with_columns(pl.col(["v", "lag_v"]).cast(pl.Float64)).sort(by)
+    expected = pl.DataFrame({
+        "w": ["<http://example.org/case#myWidget1>"] * 4 + ["<http://example.org/case#myWidget2>"] * 4,
+        "t": ["2022-06-01T08:46:56", "2022-06-01T08:46:57", "2022-06-01T08:46:58", "2022-06-01T08:46:59"] * 2,
+        "v": [102.0, 303.0, 304.0, 105.0, 202.0, 203.0, 204.0, 206.0],
+        "lag_v": [301.0, 102.0, 303.0, 304.0, 201.0, 202.0, 203.0, 204.0],
+    }).with_columns(pl.col("t").str.to_datetime().dt.replace_time_zone("UTC"))
+    # The filter is applied by the virtualized database after the window is computed
+    assert_frame_equal(df.select(expected.columns), expected, check_dtypes=False)
+    assert sm.pushdown_paths == [['ProjectInner']]
+    [sql] = database.queries
+    assert sql.index("lag(") < sql.rindex("WHERE")
+    assert database.con.execute(sql).pl().height == 8
+
+
+def test_window_over_joined_series_filter_hybrid_query(recording_engine):
+    engine, database, _ = recording_engine
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    PREFIX case:<http://example.org/case#>
+    SELECT ?t ?v1 ?v2 ?lag_v1 WHERE {
+        case:myWidget1 types:hasSensor ?s1 .
+        ?s1 chrontext:hasTimeseries ?ts1 .
+        ?ts1 chrontext:hasDataPoint ?dp1 .
+        ?dp1 chrontext:hasTimestamp ?t .
+        ?dp1 chrontext:hasValue ?v1 .
+        case:myWidget2 types:hasSensor ?s2 .
+        ?s2 chrontext:hasTimeseries ?ts2 .
+        ?ts2 chrontext:hasDataPoint ?dp2 .
+        ?dp2 chrontext:hasTimestamp ?t .
+        ?dp2 chrontext:hasValue ?v2 .
+        BIND(chrontext:lag(?v1) AS ?lag_v1)
+        FILTER(?t > "2022-06-01T08:46:55Z"^^xsd:dateTime)
+    }
+    """
+    df = engine.query(q).with_columns(pl.col(["v1", "v2", "lag_v1"]).cast(pl.Float64)).sort("t")
+    expected = pl.DataFrame({
+        "t": ["2022-06-01T08:46:56", "2022-06-01T08:46:57", "2022-06-01T08:46:58", "2022-06-01T08:46:59"],
+        "v1": [102.0, 303.0, 304.0, 105.0],
+        "v2": [202.0, 203.0, 204.0, 206.0],
+        "lag_v1": [301.0, 102.0, 303.0, 304.0],
+    }).with_columns(pl.col("t").str.to_datetime().dt.replace_time_zone("UTC"))
+    # The window is computed by chrontext over both series, so the filter must not restrict them first
+    assert_frame_equal(df.select(expected.columns), expected, check_dtypes=False)
+    assert len(database.queries) > 0
+    assert all("lag(" not in sql for sql in database.queries)
+    assert all(database.con.execute(sql).pl().height == 8 for sql in database.queries)
+
+
+def test_pushdown_latest_hybrid_query(recording_engine):
+    engine, database, _ = recording_engine
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?t ?v WHERE {
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        FILTER(chrontext:latest(?t, 2))
+    }
+    """
+    by = ["w", "t"]
+    sm = engine.query(q, include_datatypes=True)
+    df = sm.mappings.with_columns(pl.col("v").cast(pl.Float64)).sort(by)
+    expected = pl.concat([ts.tail(2) for ts in read_widget_series()]).sort(by)
+    assert_frame_equal(df.select(expected.columns), expected, check_dtypes=False)
+    assert sm.pushdown_paths == [['ProjectInner']]
+    [sql] = database.queries
+    assert re.search(r"row_number\(\) OVER \(PARTITION BY .* ORDER BY .* DESC\)", sql)
+    assert database.con.execute(sql).pl().height == 4
+
+    database.queries.clear()
+    df = engine.query(q.replace("chrontext:latest(?t, 2)", "chrontext:earliest(?t)")).sort("w")
+    assert df.get_column("v").to_list() == [1, 2]
+    [sql] = database.queries
+    assert "row_number() OVER" in sql
+    assert "DESC" not in sql
+    assert database.con.execute(sql).pl().height == 2
+
+
+def test_pushdown_extend_hybrid_query(recording_engine):
+    engine, database, _ = recording_engine
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w ?t ?v ?v_milli WHERE {
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+        BIND(?v * 1000 AS ?v_milli)
+        FILTER(?v_milli > 150000)
+    }
+    """
+    by = ["w", "t"]
+    sm = engine.query(q, include_datatypes=True)
+    df = sm.mappings.with_columns(pl.col(["v", "v_milli"]).cast(pl.Float64)).sort(by)
+    expected = pl.concat(read_widget_series()).with_columns(
+        (pl.col("v") * 1000).alias("v_milli")
+    ).filter(pl.col("v_milli") > 150000).sort(by)
+    assert_frame_equal(df.select(expected.columns), expected, check_dtypes=False)
+    assert sm.pushdown_paths == [['ProjectInner']]
+    # Both the expression and the filter on it are computed by the virtualized database
+    [sql] = database.queries
+    assert "* 1000" in sql
+    assert "150000" in sql
+    assert database.con.execute(sql).pl().height == expected.height == 9
+
+
 def test_pushdown_group_by_second_hybrid_query(engine):
     q = """
     PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
@@ -252,6 +1410,35 @@ def test_pushdown_group_by_second_having_hybrid_query(engine):
     assert sm.pushdown_paths == [['ProjectInner','FilterInner', 'ExtendInner', 'ExtendInner']]
 
 
+def test_pushdown_group_by_having_max_hybrid_query(recording_engine):
+    engine, database, _ = recording_engine
+    q = """
+    PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>
+    PREFIX chrontext:<https://github.com/DataTreehouse/chrontext#>
+    PREFIX types:<http://example.org/types#>
+    SELECT ?w (MAX(?v) AS ?max_v) WHERE {
+        ?w types:hasSensor ?s .
+        ?s chrontext:hasTimeseries ?ts .
+        ?ts chrontext:hasDataPoint ?dp .
+        ?dp chrontext:hasTimestamp ?t .
+        ?dp chrontext:hasValue ?v .
+    } GROUP BY ?w
+    HAVING (MAX(?v) > 250 && COUNT(?v) > 1)
+    """
+    sm = engine.query(q, include_datatypes=True)
+    df = sm.mappings.cast({"max_v": pl.Int64})
+    expected = pl.DataFrame({
+        "w": ["<http://example.org/case#myWidget1>"],
+        "max_v": [304],
+    })
+    assert_frame_equal(df, expected)
+    assert sm.pushdown_paths == [['ProjectInner', 'FilterInner', 'ExtendInner']]
+    # Only the widget passing the condition is returned by the database
+    [sql] = database.queries
+    assert "250" in sql[sql.index("HAVING"):]
+    assert database.con.execute(sql).pl().height == 1
+
+
 def test_union_of_two_groupby_queries(engine):
     q = """
 PREFIX xsd:<http://www.w3.org/2001/XMLSchema#>